@@ -0,0 +1,111 @@
+use std::fs;
+
+use ckb_jsonrpc_types::{JsonBytes, Script, ScriptHashType};
+use ckb_types::H256;
+
+use crate::decoder::DOBDecoder;
+use crate::types::{DOBDecoderFormat, DecoderLocationType, DecoderSubstitute};
+
+const USAGE: &str = "usage: dob-decoder-server [validate-cluster <description.json> [sample_dna]]
+       dob-decoder-server [dry-run <spore_id> <decoder_location>=<binary_path>...]
+
+decoder_location: code_hash:<hash> | type_id:<hash> | type_script:<code_hash>:<hash_type>:<args>";
+
+fn parse_hash(hexed: &str) -> Result<H256, String> {
+    let hash: [u8; 32] = hex::decode(hexed.trim_start_matches("0x"))
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| format!("invalid hash `{hexed}`"))?;
+    Ok(H256(hash))
+}
+
+// parse decoder location in `<type>:<hash>` format, or `type_script:<code_hash>:<hash_type>:<args>`
+fn parse_decoder_location(location: &str) -> Result<DOBDecoderFormat, String> {
+    let parts = location.split(':').collect::<Vec<_>>();
+    let decoder = match parts.as_slice() {
+        ["code_hash", hash] => DOBDecoderFormat {
+            location: DecoderLocationType::CodeHash,
+            hash: Some(parse_hash(hash)?),
+            script: None,
+        },
+        ["type_id", hash] => DOBDecoderFormat {
+            location: DecoderLocationType::TypeId,
+            hash: Some(parse_hash(hash)?),
+            script: None,
+        },
+        ["type_script", code_hash, hash_type, args] => {
+            let hash_type: ScriptHashType =
+                serde_json::from_value(serde_json::Value::String(hash_type.to_string()))
+                    .map_err(|_| format!("invalid hash type `{hash_type}`"))?;
+            let args = hex::decode(args.trim_start_matches("0x"))
+                .map_err(|_| format!("invalid script args `{args}`"))?;
+            DOBDecoderFormat {
+                location: DecoderLocationType::TypeScript,
+                hash: None,
+                script: Some(Script {
+                    code_hash: parse_hash(code_hash)?,
+                    hash_type,
+                    args: JsonBytes::from_vec(args),
+                }),
+            }
+        }
+        _ => return Err(format!("invalid decoder location `{location}`\n{USAGE}")),
+    };
+    Ok(decoder)
+}
+
+// run one-shot command under the loaded settings instead of starting server, results are printed
+// in JSON format, and error is returned if the command fails or finds problems
+pub async fn run_command(
+    decoder: &DOBDecoder,
+    command: &str,
+    args: &[String],
+) -> Result<(), String> {
+    match command {
+        "validate-cluster" => {
+            let [path, rest @ ..] = args else {
+                return Err(USAGE.to_owned());
+            };
+            let description =
+                fs::read_to_string(path).map_err(|error| format!("read {path}: {error}"))?;
+            let sample_dna = rest.first().map(String::as_str);
+            let report = decoder.validate_cluster(&description, sample_dna).await;
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            if !report.valid {
+                return Err("cluster description is invalid".to_owned());
+            }
+            Ok(())
+        }
+        "dry-run" => {
+            let [spore_id, substitutes @ ..] = args else {
+                return Err(USAGE.to_owned());
+            };
+            if substitutes.is_empty() {
+                return Err(USAGE.to_owned());
+            }
+            let spore_id = parse_hash(spore_id)?;
+            let substitutes = substitutes
+                .iter()
+                .map(|substitute| {
+                    let (location, binary_path) = substitute
+                        .split_once('=')
+                        .ok_or_else(|| format!("invalid substitute `{substitute}`\n{USAGE}"))?;
+                    Ok(DecoderSubstitute {
+                        decoder: parse_decoder_location(location)?,
+                        binary_path: binary_path.into(),
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let report = decoder
+                .dry_run_spore(spore_id.0, substitutes)
+                .await
+                .map_err(|error| error.to_string())?;
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            if !report.identical {
+                return Err("outputs of deployed and substitute decoders differ".to_owned());
+            }
+            Ok(())
+        }
+        _ => Err(USAGE.to_owned()),
+    }
+}
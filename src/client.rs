@@ -1,152 +0,0 @@
-use std::future::Future;
-use std::pin::Pin;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-
-use ckb_jsonrpc_types::{
-    CellWithStatus, JsonBytes, OutPoint, TransactionWithStatusResponse, Uint32,
-};
-use ckb_sdk::rpc::ckb_indexer::{Cell, Order, Pagination, SearchKey, Tx};
-use ckb_types::H256;
-use jsonrpc_core::futures::FutureExt;
-use reqwest::{Client, Url};
-
-use crate::types::Error;
-
-pub type Rpc<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'static>>;
-
-#[allow(clippy::upper_case_acronyms)]
-enum Target {
-    CKB,
-    Indexer,
-}
-
-macro_rules! jsonrpc {
-    ($method:expr, $id:expr, $self:ident, $return:ty$(, $params:ident$(,)?)*) => {{
-        let data = format!(
-            r#"{{"id": {}, "jsonrpc": "2.0", "method": "{}", "params": {}}}"#,
-            $self.id.load(Ordering::Relaxed),
-            $method,
-            serde_json::to_value(($($params,)*)).unwrap()
-        );
-        $self.id.fetch_add(1, Ordering::Relaxed);
-
-        let req_json: serde_json::Value = serde_json::from_str(&data).unwrap();
-
-        let url = match $id {
-            Target::CKB => $self.ckb_uri.clone(),
-            Target::Indexer => $self.indexer_uri.clone(),
-        };
-        let c = $self.raw.post(url).json(&req_json);
-        async {
-            let resp = c
-                .send()
-                .await
-                .map_err::<Error, _>(|e| Error::JsonRpcRequestError(e.to_string()))?;
-            let output = resp
-                .json::<jsonrpc_core::response::Output>()
-                .await
-                .map_err::<Error, _>(|e| Error::JsonRpcRequestError(e.to_string()))?;
-
-            match output {
-                jsonrpc_core::response::Output::Success(success) => {
-                    Ok(serde_json::from_value::<$return>(success.result).unwrap())
-                }
-                jsonrpc_core::response::Output::Failure(e) => {
-                    Err(Error::JsonRpcRequestError(e.error.to_string()))
-                }
-            }
-        }
-    }}
-}
-
-#[derive(Clone)]
-pub struct RpcClient {
-    raw: Client,
-    ckb_uri: Url,
-    indexer_uri: Url,
-    id: Arc<AtomicU64>,
-}
-
-impl RpcClient {
-    pub fn new(ckb_uri: &str, indexer_uri: &str) -> Self {
-        let ckb_uri = Url::parse(ckb_uri).expect("ckb uri, e.g. \"http://127.0.0.1:8114\"");
-        let indexer_uri = Url::parse(indexer_uri).expect("ckb uri, e.g. \"http://127.0.0.1:8116\"");
-
-        RpcClient {
-            raw: Client::new(),
-            ckb_uri,
-            indexer_uri,
-            id: Arc::new(AtomicU64::new(0)),
-        }
-    }
-}
-
-impl RpcClient {
-    pub fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
-        jsonrpc!(
-            "get_live_cell",
-            Target::CKB,
-            self,
-            CellWithStatus,
-            out_point,
-            with_data
-        )
-        .boxed()
-    }
-
-    pub fn get_cells(
-        &self,
-        search_key: SearchKey,
-        limit: u32,
-        cursor: Option<JsonBytes>,
-    ) -> Rpc<Pagination<Cell>> {
-        let order = Order::Asc;
-        let limit = Uint32::from(limit);
-
-        jsonrpc!(
-            "get_cells",
-            Target::Indexer,
-            self,
-            Pagination<Cell>,
-            search_key,
-            order,
-            limit,
-            cursor,
-        )
-        .boxed()
-    }
-
-    pub fn get_transactions(
-        &self,
-        search_key: SearchKey,
-        limit: u32,
-        cursor: Option<JsonBytes>,
-    ) -> Rpc<Pagination<Tx>> {
-        let order = Order::Asc;
-        let limit = Uint32::from(limit);
-
-        jsonrpc!(
-            "get_transactions",
-            Target::Indexer,
-            self,
-            Pagination<Tx>,
-            search_key,
-            order,
-            limit,
-            cursor,
-        )
-        .boxed()
-    }
-
-    pub fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
-        jsonrpc!(
-            "get_transaction",
-            Target::CKB,
-            self,
-            Option<TransactionWithStatusResponse>,
-            hash,
-        )
-        .boxed()
-    }
-}
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use ckb_jsonrpc_types::{OutPoint, TransactionWithStatusResponse};
+use ckb_sdk::rpc::ckb_indexer::Cell;
+use ckb_types::H256;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::types::RpcCacheSettings;
+
+// in-memory storage which evicts the earliest inserted item once capacity is reached
+struct MemoryCache<K, V> {
+    items: HashMap<K, V>,
+    order: VecDeque<K>,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> MemoryCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            items: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        self.items.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.items.insert(key.clone(), value).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(expired) = self.order.pop_front() {
+                self.items.remove(&expired);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        if self.items.remove(key).is_some() {
+            self.order.retain(|item| item != key);
+        }
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&V) -> bool) {
+        self.items.retain(|_, value| keep(value));
+        let items = &self.items;
+        self.order.retain(|key| items.contains_key(key));
+    }
+}
+
+// cache of chain data, committed transactions are immutable so that they are kept in memory and
+// persisted on disk, while live cells searched by type script hash can be consumed at any time, so
+// that they are only kept in memory for a short while, or until their out points are consumed
+pub struct ResponseCache {
+    transactions: Mutex<MemoryCache<H256, TransactionWithStatusResponse>>,
+    cells: Mutex<MemoryCache<H256, (Cell, Instant)>>,
+    live_cell_ttl: Duration,
+    directory: Option<PathBuf>,
+}
+
+impl ResponseCache {
+    pub fn new(settings: &RpcCacheSettings) -> Self {
+        Self {
+            transactions: Mutex::new(MemoryCache::new(settings.memory_capacity)),
+            cells: Mutex::new(MemoryCache::new(settings.memory_capacity)),
+            live_cell_ttl: Duration::from_secs(settings.live_cell_ttl_sec),
+            directory: settings.persistence_directory.clone(),
+        }
+    }
+
+    pub fn transaction(&self, tx_hash: &H256) -> Option<TransactionWithStatusResponse> {
+        if let Some(tx) = self.transactions.lock().unwrap().get(tx_hash) {
+            return Some(tx);
+        }
+        // disk is read without holding the lock, so that other lookups are not blocked
+        let tx: TransactionWithStatusResponse = self.read_from_disk("transactions", tx_hash)?;
+        self.transactions
+            .lock()
+            .unwrap()
+            .insert(tx_hash.clone(), tx.clone());
+        Some(tx)
+    }
+
+    pub fn insert_transaction(&self, tx_hash: H256, tx: TransactionWithStatusResponse) {
+        self.write_to_disk("transactions", &tx_hash, &tx);
+        self.transactions.lock().unwrap().insert(tx_hash, tx);
+    }
+
+    pub fn cell(&self, script_hash: &H256) -> Option<Cell> {
+        let mut cells = self.cells.lock().unwrap();
+        let (cell, cached_at) = cells.get(script_hash)?;
+        if cached_at.elapsed() >= self.live_cell_ttl {
+            cells.remove(script_hash);
+            return None;
+        }
+        Some(cell)
+    }
+
+    pub fn insert_cell(&self, script_hash: H256, cell: Cell) {
+        if self.live_cell_ttl.is_zero() {
+            return;
+        }
+        self.cells
+            .lock()
+            .unwrap()
+            .insert(script_hash, (cell, Instant::now()));
+    }
+
+    // drop cell that is no longer valid, e.g. the decoder cell upgraded by its owner
+    pub fn remove_cell(&self, script_hash: &H256) {
+        self.cells.lock().unwrap().remove(script_hash);
+    }
+
+    // drop cells whose out points are consumed, e.g. inputs of a newly committed transaction
+    pub fn remove_consumed_cells(&self, out_points: &[OutPoint]) {
+        self.cells
+            .lock()
+            .unwrap()
+            .retain(|(cell, _)| !out_points.contains(&cell.out_point));
+    }
+
+    fn cache_path(&self, category: &str, key: &H256) -> Option<PathBuf> {
+        let mut path = self.directory.clone()?;
+        path.push(category);
+        path.push(format!("{}.json", hex::encode(key)));
+        Some(path)
+    }
+
+    fn read_from_disk<T: DeserializeOwned>(&self, category: &str, key: &H256) -> Option<T> {
+        let path = self.cache_path(category, key)?;
+        let content = std::fs::read(path).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    // persistence is in best-effort, failure of writing only results in re-fetching from ckb node
+    fn write_to_disk<T: Serialize>(&self, category: &str, key: &H256, value: &T) {
+        let Some(path) = self.cache_path(category, key) else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = std::fs::write(path, serde_json::to_vec(value).unwrap());
+    }
+}
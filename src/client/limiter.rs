@@ -0,0 +1,58 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::types::RpcRateLimit;
+
+// limit both requests per second and requests in flight, exceeded requests are queued rather than failed
+pub struct RateLimiter {
+    in_flight: Option<Arc<Semaphore>>,
+    interval: Option<Duration>,
+    next_slot: Mutex<Instant>,
+}
+
+// keep holding until the response is received, so that the in-flight slot can be released
+pub struct RatePermit {
+    _in_flight: Option<OwnedSemaphorePermit>,
+}
+
+impl RateLimiter {
+    pub fn new(rate_limit: &RpcRateLimit) -> Self {
+        let in_flight = (rate_limit.max_in_flight > 0)
+            .then(|| Arc::new(Semaphore::new(rate_limit.max_in_flight)));
+        let interval = (rate_limit.requests_per_second > 0)
+            .then(|| Duration::from_secs(1) / rate_limit.requests_per_second);
+        Self {
+            in_flight,
+            interval,
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn acquire(&self) -> RatePermit {
+        let in_flight = match &self.in_flight {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore never closed"),
+            ),
+            None => None,
+        };
+        if let Some(interval) = self.interval {
+            let slot = {
+                let mut next_slot = self.next_slot.lock().unwrap();
+                let slot = (*next_slot).max(Instant::now());
+                *next_slot = slot + interval;
+                slot
+            };
+            tokio::time::sleep_until(slot).await;
+        }
+        RatePermit {
+            _in_flight: in_flight,
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use ckb_jsonrpc_types::{
+    BlockNumber, CellWithStatus, HeaderView, JsonBytes, OutPoint, Status,
+    TransactionWithStatusResponse, Uint32,
+};
+use ckb_sdk::rpc::ckb_indexer::{Cell, Order, Pagination, SearchKey, Tx};
+use ckb_types::{packed, prelude::Unpack, H256};
+use jsonrpc_core::futures::FutureExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, RequestBuilder, Url};
+
+use crate::types::{Error, RpcAuth, RpcCacheSettings, RpcEndpointOptions, RpcRateLimit};
+
+mod cache;
+mod limiter;
+#[cfg(feature = "standalone_server")]
+mod subscription;
+use cache::ResponseCache;
+use limiter::RateLimiter;
+#[cfg(feature = "standalone_server")]
+pub use subscription::{ChainEvent, SubscriptionClient, SubscriptionTopic};
+
+pub type Rpc<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'static>>;
+
+#[allow(clippy::upper_case_acronyms)]
+enum Target {
+    CKB,
+    Indexer,
+}
+
+macro_rules! jsonrpc {
+    ($method:expr, $id:expr, $self:ident, $return:ty$(, $params:ident$(,)?)*) => {{
+        let params = match serde_json::to_value(($($params,)*)).unwrap() {
+            serde_json::Value::Null => serde_json::Value::Array(vec![]),
+            params => params,
+        };
+        let data = format!(
+            r#"{{"id": {}, "jsonrpc": "2.0", "method": "{}", "params": {}}}"#,
+            $self.id.load(Ordering::Relaxed),
+            $method,
+            params
+        );
+        $self.id.fetch_add(1, Ordering::Relaxed);
+
+        let req_json: serde_json::Value = serde_json::from_str(&data).unwrap();
+
+        let endpoint = match $id {
+            Target::CKB => &$self.ckb,
+            Target::Indexer => &$self.indexer,
+        };
+        let c = endpoint.post(&$self.raw).json(&req_json);
+        let limiter = $self.limiter.clone();
+        async move {
+            let _permit = match &limiter {
+                Some(limiter) => Some(limiter.acquire().await),
+                None => None,
+            };
+            let resp = c
+                .send()
+                .await
+                .map_err::<Error, _>(|e| Error::JsonRpcRequestError(e.to_string()))?;
+            let output = resp
+                .json::<jsonrpc_core::response::Output>()
+                .await
+                .map_err::<Error, _>(|e| Error::JsonRpcRequestError(e.to_string()))?;
+
+            match output {
+                jsonrpc_core::response::Output::Success(success) => {
+                    Ok(serde_json::from_value::<$return>(success.result).unwrap())
+                }
+                jsonrpc_core::response::Output::Failure(e) => {
+                    Err(Error::JsonRpcRequestError(e.error.to_string()))
+                }
+            }
+        }
+    }}
+}
+
+enum Authorization {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+// rpc endpoint with its resolved headers and authentication
+struct Endpoint {
+    uri: Url,
+    headers: HeaderMap,
+    auth: Option<Authorization>,
+}
+
+impl Endpoint {
+    fn new(uri: Url, options: Option<&RpcEndpointOptions>) -> Result<Self, Error> {
+        let Some(options) = options else {
+            return Ok(Self {
+                uri,
+                headers: HeaderMap::new(),
+                auth: None,
+            });
+        };
+        let mut headers = HeaderMap::new();
+        for (name, value) in &options.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| Error::RpcHeaderInvalid(name.clone()))?;
+            let header_value = HeaderValue::from_str(&value.resolve()?)
+                .map_err(|_| Error::RpcHeaderInvalid(name.clone()))?;
+            headers.insert(header_name, header_value);
+        }
+        let auth = match &options.auth {
+            Some(RpcAuth::Basic { username, password }) => Some(Authorization::Basic {
+                username: username.clone(),
+                password: password.resolve()?,
+            }),
+            Some(RpcAuth::Bearer { token }) => Some(Authorization::Bearer(token.resolve()?)),
+            None => None,
+        };
+        Ok(Self { uri, headers, auth })
+    }
+
+    fn post(&self, raw: &Client) -> RequestBuilder {
+        let request = raw.post(self.uri.clone()).headers(self.headers.clone());
+        match &self.auth {
+            Some(Authorization::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(Authorization::Bearer(token)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RpcClient {
+    raw: Client,
+    ckb: Arc<Endpoint>,
+    indexer: Arc<Endpoint>,
+    limiter: Option<Arc<RateLimiter>>,
+    cache: Option<Arc<ResponseCache>>,
+    tip_number: Arc<AtomicU64>,
+    min_confirmations: u64,
+    id: Arc<AtomicU64>,
+}
+
+impl RpcClient {
+    #[allow(dead_code)]
+    pub fn new(ckb_uri: &str, indexer_uri: &str) -> Self {
+        Self::new_with_options(ckb_uri, None, indexer_uri, None).expect("endpoints without options")
+    }
+
+    // build client whose requests are decorated by headers and authentication of each endpoint
+    pub fn new_with_options(
+        ckb_uri: &str,
+        ckb_options: Option<&RpcEndpointOptions>,
+        indexer_uri: &str,
+        indexer_options: Option<&RpcEndpointOptions>,
+    ) -> Result<Self, Error> {
+        let parse_uri =
+            |uri: &str| Url::parse(uri).map_err(|_| Error::RpcUriInvalid(uri.to_owned()));
+        let ckb_uri = parse_uri(ckb_uri)?;
+        let indexer_uri = parse_uri(indexer_uri)?;
+
+        Ok(RpcClient {
+            raw: Client::new(),
+            ckb: Arc::new(Endpoint::new(ckb_uri, ckb_options)?),
+            indexer: Arc::new(Endpoint::new(indexer_uri, indexer_options)?),
+            limiter: None,
+            cache: None,
+            tip_number: Arc::new(AtomicU64::new(0)),
+            min_confirmations: 0,
+            id: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    // all clones of this client share the same limitation toward ckb node
+    pub fn with_rate_limit(mut self, rate_limit: &RpcRateLimit) -> Self {
+        self.limiter = Some(Arc::new(RateLimiter::new(rate_limit)));
+        self
+    }
+
+    // committed transactions are cached once fetched, and live cells searched by type script are
+    // cached for a short while
+    pub fn with_cache(mut self, cache_settings: &RpcCacheSettings) -> Self {
+        self.cache = Some(Arc::new(ResponseCache::new(cache_settings)));
+        self
+    }
+
+    // chain data is regarded as final only if confirmed by `min_confirmations` blocks
+    pub fn with_min_confirmations(mut self, min_confirmations: u64) -> Self {
+        self.min_confirmations = min_confirmations;
+        self
+    }
+
+    // the latest tip block number seen by this client, zero if never fetched
+    pub fn tip_block_number(&self) -> u64 {
+        self.tip_number.load(Ordering::Relaxed)
+    }
+
+    // record tip block number from outside, e.g. new tip header notifications
+    pub fn update_tip_block_number(&self, tip_number: u64) {
+        self.tip_number.fetch_max(tip_number, Ordering::Relaxed);
+    }
+
+    // check finality of data committed in `block_number` against the latest tip seen
+    pub fn is_final(&self, block_number: u64) -> bool {
+        is_final(
+            self.tip_block_number(),
+            block_number,
+            self.min_confirmations,
+        )
+    }
+}
+
+fn is_final(tip_number: u64, block_number: u64, min_confirmations: u64) -> bool {
+    if min_confirmations == 0 {
+        return true;
+    }
+    tip_number >= block_number && tip_number - block_number + 1 >= min_confirmations
+}
+
+impl RpcClient {
+    pub fn get_live_cell(&self, out_point: &OutPoint, with_data: bool) -> Rpc<CellWithStatus> {
+        jsonrpc!(
+            "get_live_cell",
+            Target::CKB,
+            self,
+            CellWithStatus,
+            out_point,
+            with_data
+        )
+        .boxed()
+    }
+
+    pub fn get_cells(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Cell>> {
+        let order = Order::Asc;
+        let limit = Uint32::from(limit);
+
+        jsonrpc!(
+            "get_cells",
+            Target::Indexer,
+            self,
+            Pagination<Cell>,
+            search_key,
+            order,
+            limit,
+            cursor,
+        )
+        .boxed()
+    }
+
+    pub fn get_transactions(
+        &self,
+        search_key: SearchKey,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Tx>> {
+        self.get_transactions_in_order(search_key, Order::Asc, limit, cursor)
+    }
+
+    pub fn get_transactions_in_order(
+        &self,
+        search_key: SearchKey,
+        order: Order,
+        limit: u32,
+        cursor: Option<JsonBytes>,
+    ) -> Rpc<Pagination<Tx>> {
+        let limit = Uint32::from(limit);
+
+        jsonrpc!(
+            "get_transactions",
+            Target::Indexer,
+            self,
+            Pagination<Tx>,
+            search_key,
+            order,
+            limit,
+            cursor,
+        )
+        .boxed()
+    }
+
+    pub fn get_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+        let Some(cache) = self.cache.clone() else {
+            return self.fetch_transaction(hash);
+        };
+        if let Some(tx) = cache.transaction(hash) {
+            return async move { Ok(Some(tx)) }.boxed();
+        }
+        let request = self.fetch_transaction(hash);
+        let hash = hash.clone();
+        let client = self.clone();
+        async move {
+            let tx = request.await?;
+            if let Some(tx) = &tx {
+                let committed_number = match tx.tx_status.status {
+                    Status::Committed => tx.tx_status.block_number.map(|number| number.value()),
+                    _ => None,
+                };
+                if committed_number.is_some_and(|number| client.is_final(number)) {
+                    cache.insert_transaction(hash, tx.clone());
+                }
+            }
+            Ok(tx)
+        }
+        .boxed()
+    }
+
+    // search the first live cell under the type script in `search_key`, which is cached by its script
+    // hash until it expires or its out point is consumed
+    pub fn get_cell_by_type(&self, search_key: SearchKey) -> Rpc<Option<Cell>> {
+        let script_hash: H256 = packed::Script::from(search_key.script.clone())
+            .calc_script_hash()
+            .unpack();
+        let cache = self.cache.clone();
+        if let Some(cell) = cache.as_ref().and_then(|cache| cache.cell(&script_hash)) {
+            return async move { Ok(Some(cell)) }.boxed();
+        }
+        let request = self.get_cells(search_key, 1, None);
+        let client = self.clone();
+        async move {
+            let cell = request.await?.objects.first().cloned();
+            if let (Some(cache), Some(cell)) = (cache, &cell) {
+                if client.is_final(cell.block_number.value()) {
+                    cache.insert_cell(script_hash, cell.clone());
+                }
+            }
+            Ok(cell)
+        }
+        .boxed()
+    }
+
+    pub fn get_header_by_number(&self, number: u64) -> Rpc<Option<HeaderView>> {
+        let number = BlockNumber::from(number);
+        jsonrpc!(
+            "get_header_by_number",
+            Target::CKB,
+            self,
+            Option<HeaderView>,
+            number,
+        )
+        .boxed()
+    }
+
+    // fetch tip block number from ckb node and track it for finality checking
+    pub fn get_tip_block_number(&self) -> Rpc<u64> {
+        let request = jsonrpc!("get_tip_block_number", Target::CKB, self, BlockNumber);
+        let tip_number = self.tip_number.clone();
+        async move {
+            let number = request.await?.value();
+            tip_number.fetch_max(number, Ordering::Relaxed);
+            Ok(number)
+        }
+        .boxed()
+    }
+
+    // drop cached cell under the type script, which happens when the cell is found updated
+    pub fn invalidate_cell_by_type(&self, script_hash: &H256) {
+        if let Some(cache) = &self.cache {
+            cache.remove_cell(script_hash);
+        }
+    }
+
+    // drop cached cells consumed by committed transactions
+    pub fn invalidate_consumed_cells(&self, out_points: &[OutPoint]) {
+        if let Some(cache) = &self.cache {
+            cache.remove_consumed_cells(out_points);
+        }
+    }
+
+    fn fetch_transaction(&self, hash: &H256) -> Rpc<Option<TransactionWithStatusResponse>> {
+        jsonrpc!(
+            "get_transaction",
+            Target::CKB,
+            self,
+            Option<TransactionWithStatusResponse>,
+            hash,
+        )
+        .boxed()
+    }
+}
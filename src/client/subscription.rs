@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use ckb_jsonrpc_types::{BlockView, HeaderView};
+use reqwest::Url;
+use serde_json::{json, Value};
+use soketto::connection::{Receiver, Sender};
+use soketto::handshake::{Client as WsClient, ServerResponse};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+use crate::types::Error;
+
+// notification topics of ckb node, refer to https://github.com/nervosnetwork/ckb/tree/develop/rpc#module-subscription
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SubscriptionTopic {
+    NewTipHeader,
+    NewTipBlock,
+}
+
+impl SubscriptionTopic {
+    fn name(&self) -> &'static str {
+        match self {
+            SubscriptionTopic::NewTipHeader => "new_tip_header",
+            SubscriptionTopic::NewTipBlock => "new_tip_block",
+        }
+    }
+}
+
+pub enum ChainEvent {
+    NewTipHeader(HeaderView),
+    NewTipBlock(Box<BlockView>),
+}
+
+enum Connection {
+    Tcp {
+        reader: BufReader<OwnedReadHalf>,
+        writer: OwnedWriteHalf,
+    },
+    WebSocket {
+        sender: Sender<Compat<TcpStream>>,
+        receiver: Receiver<Compat<TcpStream>>,
+    },
+}
+
+impl Connection {
+    async fn connect(uri: &Url) -> Result<Self, Error> {
+        let host = uri
+            .host_str()
+            .ok_or(Error::SubscriptionError("host not found".to_owned()))?;
+        let port = uri
+            .port_or_known_default()
+            .ok_or(Error::SubscriptionError("port not found".to_owned()))?;
+        let socket = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| Error::SubscriptionError(e.to_string()))?;
+        match uri.scheme() {
+            "tcp" => {
+                let (reader, writer) = socket.into_split();
+                Ok(Connection::Tcp {
+                    reader: BufReader::new(reader),
+                    writer,
+                })
+            }
+            "ws" => {
+                let resource = match uri.query() {
+                    Some(query) => format!("{}?{query}", uri.path()),
+                    None => uri.path().to_owned(),
+                };
+                let host = format!("{host}:{port}");
+                let mut client = WsClient::new(socket.compat(), &host, &resource);
+                match client
+                    .handshake()
+                    .await
+                    .map_err(|e| Error::SubscriptionError(e.to_string()))?
+                {
+                    ServerResponse::Accepted { .. } => {}
+                    _ => {
+                        return Err(Error::SubscriptionError(
+                            "websocket handshake rejected".to_owned(),
+                        ))
+                    }
+                }
+                let (sender, receiver) = client.into_builder().finish();
+                Ok(Connection::WebSocket { sender, receiver })
+            }
+            scheme => Err(Error::SubscriptionError(format!(
+                "unsupported scheme `{scheme}`, only `tcp` and `ws` are allowed"
+            ))),
+        }
+    }
+
+    async fn send(&mut self, message: String) -> Result<(), Error> {
+        match self {
+            Connection::Tcp { writer, .. } => writer
+                .write_all(format!("{message}\n").as_bytes())
+                .await
+                .map_err(|e| Error::SubscriptionError(e.to_string())),
+            Connection::WebSocket { sender, .. } => {
+                sender
+                    .send_text(message)
+                    .await
+                    .map_err(|e| Error::SubscriptionError(e.to_string()))?;
+                sender
+                    .flush()
+                    .await
+                    .map_err(|e| Error::SubscriptionError(e.to_string()))
+            }
+        }
+    }
+
+    // read next message, none means the connection is closed
+    async fn receive(&mut self) -> Result<Option<String>, Error> {
+        match self {
+            Connection::Tcp { reader, .. } => {
+                let mut line = String::new();
+                let size = reader
+                    .read_line(&mut line)
+                    .await
+                    .map_err(|e| Error::SubscriptionError(e.to_string()))?;
+                Ok((size > 0).then_some(line))
+            }
+            Connection::WebSocket { receiver, .. } => {
+                let mut message = Vec::new();
+                match receiver.receive_data(&mut message).await {
+                    Ok(_) => Ok(Some(String::from_utf8_lossy(&message).into_owned())),
+                    Err(soketto::connection::Error::Closed) => Ok(None),
+                    Err(e) => Err(Error::SubscriptionError(e.to_string())),
+                }
+            }
+        }
+    }
+}
+
+// subscribe notifications of ckb node through TCP (`tcp://`) or WebSocket (`ws://`) endpoint
+pub struct SubscriptionClient {
+    uri: Url,
+}
+
+impl SubscriptionClient {
+    pub fn new(uri: &str) -> Self {
+        let uri = Url::parse(uri).expect("subscription uri, e.g. \"tcp://127.0.0.1:18114\"");
+        SubscriptionClient { uri }
+    }
+
+    // events are sent into the returned receiver until the connection is closed or broken,
+    // in which case the receiver gets closed and caller is supposed to subscribe again
+    pub async fn subscribe(
+        &self,
+        topics: &[SubscriptionTopic],
+    ) -> Result<mpsc::UnboundedReceiver<ChainEvent>, Error> {
+        let mut connection = Connection::connect(&self.uri).await?;
+        let mut pending_topics = HashMap::new();
+        for (id, topic) in topics.iter().enumerate() {
+            let request = json!({
+                "id": id,
+                "jsonrpc": "2.0",
+                "method": "subscribe",
+                "params": [topic.name()],
+            });
+            connection.send(request.to_string()).await?;
+            pending_topics.insert(id as u64, *topic);
+        }
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut subscriptions = HashMap::new();
+            while let Ok(Some(message)) = connection.receive().await {
+                let Ok(message) = serde_json::from_str::<Value>(&message) else {
+                    continue;
+                };
+                // response of subscribe request, which carries the subscription id of topic
+                if let Some(id) = message.get("id").and_then(Value::as_u64) {
+                    if let (Some(topic), Some(subscription)) = (
+                        pending_topics.remove(&id),
+                        message.get("result").and_then(Value::as_str),
+                    ) {
+                        subscriptions.insert(subscription.to_owned(), topic);
+                    }
+                    continue;
+                }
+                let Some(params) = message.get("params") else {
+                    continue;
+                };
+                let topic = params
+                    .get("subscription")
+                    .and_then(Value::as_str)
+                    .and_then(|subscription| subscriptions.get(subscription));
+                let result = params.get("result").and_then(Value::as_str);
+                let (Some(topic), Some(result)) = (topic, result) else {
+                    continue;
+                };
+                let event = match topic {
+                    SubscriptionTopic::NewTipHeader => {
+                        serde_json::from_str(result).map(ChainEvent::NewTipHeader)
+                    }
+                    SubscriptionTopic::NewTipBlock => {
+                        serde_json::from_str(result).map(ChainEvent::NewTipBlock)
+                    }
+                };
+                let Ok(event) = event else {
+                    continue;
+                };
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+}
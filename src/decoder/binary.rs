@@ -0,0 +1,471 @@
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ckb_jsonrpc_types::OutPoint;
+use ckb_sdk::{rpc::ckb_indexer::Cell, traits::CellQueryOptions};
+use ckb_types::{packed, H256};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    client::RpcClient,
+    types::{DecoderUpgradePolicy, Error, OnchainDecoderDeployment, Settings},
+};
+
+use super::helpers::{
+    fetch_decoder_binary_directly, invalidate_dob_render_cache, unwrap_transaction_view,
+    write_file_atomically,
+};
+
+// file in decoders cache directory that persists out points of discovered code_hash decoders
+const DISCOVERED_DECODERS_FILE: &str = "discovered_decoders.json";
+// file in decoders cache directory that persists code hashes missed in the latest discovery
+const DISCOVERY_MISSES_FILE: &str = "decoder_discovery_misses.json";
+const DISCOVERY_PAGE_SIZE: u32 = 100;
+// maximum pages of live cells scanned under one deployer lock
+const MAX_DISCOVERY_PAGES: usize = 10;
+// code hash missed in discovery isn't searched again until the interval passes
+const DISCOVERY_MISS_TTL_SEC: u64 = 600;
+
+// serializes read-modify-write of discovered decoders and discovery misses files, so that
+// concurrent discoveries never drop records of each other
+static DISCOVERY_FILES_LOCK: Mutex<()> = Mutex::const_new(());
+
+// code_hash decoder that isn't found under any deployer lock
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DiscoveryMiss {
+    pub code_hash: H256,
+    pub missed_at: u64,
+}
+
+// verification record of cached decoder binary, which is persisted beside the binary file, size and
+// modification time of the binary file are recorded to detect tampering without hashing it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DecoderBinaryRecord {
+    pub data_hash: H256,
+    pub out_point: OutPoint,
+    pub verified_at: u64,
+    #[serde(default)]
+    pub binary_size: u64,
+    #[serde(default)]
+    pub binary_modified_at_ns: u64,
+    // data hash of upgraded on-chain decoder, which is only recorded under `pin` policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upgraded_data_hash: Option<H256>,
+}
+
+// outcome of revalidating cached decoder binary against the live decoder cell
+#[derive(PartialEq, Eq, Debug)]
+pub enum Revalidation {
+    // live binary is the cached one, or live decoder cell isn't found
+    Unchanged,
+    // live binary is upgraded and replaces the cached one under `follow` policy
+    Replace,
+    // live binary is upgraded but the cached one is kept under `pin` policy
+    Pinned(H256),
+}
+
+fn record_path(decoder_path: &Path) -> PathBuf {
+    decoder_path.with_extension("json")
+}
+
+fn read_record(decoder_path: &Path) -> Option<DecoderBinaryRecord> {
+    let content = std::fs::read(record_path(decoder_path)).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+// data hash of cached decoder binary in its verification record, which is checked on resolving
+pub(super) fn recorded_data_hash(decoder_path: &Path) -> Option<H256> {
+    read_record(decoder_path).map(|record| record.data_hash)
+}
+
+fn write_record(decoder_path: &Path, record: &DecoderBinaryRecord) -> Result<(), Error> {
+    let content = serde_json::to_vec(record).expect("serialize decoder record");
+    write_file_atomically(&record_path(decoder_path), &content)
+        .map_err(|_| Error::DecoderBinaryPathInvalid)
+}
+
+fn modified_at_ns(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+// record size and modification time of binary file as it's written
+pub fn stamp_record(decoder_path: &Path, record: &mut DecoderBinaryRecord) -> Result<(), Error> {
+    let metadata = std::fs::metadata(decoder_path).map_err(|_| Error::DecoderBinaryPathInvalid)?;
+    record.binary_size = metadata.len();
+    record.binary_modified_at_ns = modified_at_ns(&metadata);
+    Ok(())
+}
+
+pub(super) fn now() -> Result<u64, Error> {
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::SystemTimeError)?;
+    Ok(duration.as_secs())
+}
+
+// check whether cached decoder binary is still the one in record by its size and modification time,
+// and by its hash only if `rehash` is required, e.g. on revalidation
+pub fn is_intact(decoder_path: &Path, record: &DecoderBinaryRecord, rehash: bool) -> bool {
+    let Ok(metadata) = std::fs::metadata(decoder_path) else {
+        return false;
+    };
+    // records written by previous versions have no stamp, which are checked by hash
+    let stamped = record.binary_size > 0;
+    if stamped
+        && (metadata.len() != record.binary_size
+            || modified_at_ns(&metadata) != record.binary_modified_at_ns)
+    {
+        return false;
+    }
+    if stamped && !rehash {
+        return true;
+    }
+    std::fs::read(decoder_path)
+        .map(|binary| ckb_hash::blake2b_256(binary) == record.data_hash.0)
+        .unwrap_or(false)
+}
+
+fn write_decoder(decoder_path: &Path, binary: &[u8], out_point: OutPoint) -> Result<(), Error> {
+    write_file_atomically(decoder_path, binary).map_err(|_| Error::DecoderBinaryPathInvalid)?;
+    let mut record = DecoderBinaryRecord {
+        data_hash: H256(ckb_hash::blake2b_256(binary)),
+        out_point,
+        verified_at: now()?,
+        binary_size: 0,
+        binary_modified_at_ns: 0,
+        upgraded_data_hash: None,
+    };
+    stamp_record(decoder_path, &mut record)?;
+    write_record(decoder_path, &record)
+}
+
+// whether cached decoder binary is due to revalidate against the live decoder cell, which never
+// happens if `interval` is zero
+pub fn is_revalidation_due(record: &DecoderBinaryRecord, interval: u64, now: u64) -> bool {
+    interval > 0 && now >= record.verified_at.saturating_add(interval)
+}
+
+// update record by data hash and out point of the live decoder cell, and tell whether the cached
+// binary should be replaced
+pub fn revalidate_record(
+    record: &mut DecoderBinaryRecord,
+    live_decoder: Option<([u8; 32], OutPoint)>,
+    policy: DecoderUpgradePolicy,
+) -> Revalidation {
+    let Some((data_hash, out_point)) = live_decoder else {
+        return Revalidation::Unchanged;
+    };
+    if data_hash == record.data_hash.0 {
+        record.out_point = out_point;
+        record.upgraded_data_hash = None;
+        return Revalidation::Unchanged;
+    }
+    match policy {
+        DecoderUpgradePolicy::Follow => Revalidation::Replace,
+        DecoderUpgradePolicy::Pin => {
+            record.upgraded_data_hash = Some(H256(data_hash));
+            Revalidation::Pinned(H256(data_hash))
+        }
+    }
+}
+
+// search live decoder cell bypassing response cache, and return its binary along with out point
+async fn fetch_live_decoder(
+    rpc: &RpcClient,
+    decoder_search_option: CellQueryOptions,
+) -> Result<Option<(Vec<u8>, OutPoint)>, Error> {
+    let decoder_cell = rpc
+        .get_cells(decoder_search_option.into(), 1, None)
+        .await
+        .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?
+        .objects
+        .first()
+        .cloned();
+    Ok(decoder_cell.map(|cell| {
+        let binary = cell.output_data.unwrap_or_default().as_bytes().to_vec();
+        (binary, cell.out_point)
+    }))
+}
+
+// read decoder binary from the transaction that created it, no matter whether it's consumed
+async fn fetch_decoder_at(rpc: &RpcClient, out_point: &OutPoint) -> Result<Vec<u8>, Error> {
+    let cell_not_found =
+        || Error::DecoderCellNotFound(hex::encode(&out_point.tx_hash), out_point.index.value());
+    let tx = rpc
+        .get_transaction(&out_point.tx_hash)
+        .await
+        .map_err(|e| Error::FetchTransactionError(e.to_string()))?
+        .map(unwrap_transaction_view)
+        .transpose()?
+        .flatten()
+        .ok_or_else(cell_not_found)?;
+    tx.inner
+        .outputs_data
+        .get(out_point.index.value() as usize)
+        .map(|data| data.as_bytes().to_vec())
+        .ok_or_else(cell_not_found)
+}
+
+fn warn_pinned(decoder_path: &Path, upgraded_data_hash: &H256) {
+    tracing::warn!(
+        "decoder {} is pinned under `pin` policy, upgraded on-chain binary {upgraded_data_hash:#x} is ignored",
+        decoder_path.display()
+    );
+}
+
+// make sure decoder binary deployed under upgradable type script is cached and verified
+//
+// note: cached binary is revalidated against the live decoder cell every configured interval, the
+// upgraded binary replaces the cached one under `follow` policy, while under `pin` policy the
+// first-seen binary is kept and recovered from its creating transaction if cache is lost, rendered
+// DOBs in cache are dropped once the cached binary is replaced by an upgraded one
+pub async fn prepare_upgradable_decoder(
+    rpc: &RpcClient,
+    settings: &Settings,
+    decoder_search_option: CellQueryOptions,
+    decoder_path: &Path,
+) -> Result<(), Error> {
+    let policy = settings.decoder_upgrade_policy;
+    let interval = settings.decoder_revalidation_interval_sec;
+    let now = now()?;
+    let record = read_record(decoder_path);
+    let revalidating = record
+        .as_ref()
+        .is_some_and(|record| is_revalidation_due(record, interval, now));
+    match record {
+        Some(mut record) if is_intact(decoder_path, &record, revalidating) => {
+            if !revalidating {
+                return Ok(());
+            }
+            let live_decoder = fetch_live_decoder(rpc, decoder_search_option).await?;
+            let live_data_hash = live_decoder
+                .as_ref()
+                .map(|(binary, out_point)| (ckb_hash::blake2b_256(binary), out_point.clone()));
+            match revalidate_record(&mut record, live_data_hash, policy) {
+                Revalidation::Unchanged => {}
+                Revalidation::Replace => {
+                    let (binary, out_point) = live_decoder.expect("live decoder");
+                    write_decoder(decoder_path, &binary, out_point)?;
+                    invalidate_dob_render_cache(settings);
+                    return Ok(());
+                }
+                Revalidation::Pinned(upgraded_data_hash) => {
+                    warn_pinned(decoder_path, &upgraded_data_hash)
+                }
+            }
+            record.verified_at = now;
+            stamp_record(decoder_path, &mut record)?;
+            write_record(decoder_path, &record)
+        }
+        Some(mut record) if matches!(policy, DecoderUpgradePolicy::Pin) => {
+            let binary = fetch_decoder_at(rpc, &record.out_point).await?;
+            if ckb_hash::blake2b_256(&binary) != record.data_hash.0 {
+                return Err(Error::DecoderBinaryHashInvalid(decoder_path.to_path_buf()));
+            }
+            write_file_atomically(decoder_path, &binary)
+                .map_err(|_| Error::DecoderBinaryPathInvalid)?;
+            stamp_record(decoder_path, &mut record)?;
+            write_record(decoder_path, &record)
+        }
+        // binary cached by previous versions without record is the first-seen one under `pin`
+        // policy, which is adopted rather than overwritten
+        None if matches!(policy, DecoderUpgradePolicy::Pin) && decoder_path.exists() => {
+            let cached_binary =
+                std::fs::read(decoder_path).map_err(|_| Error::DecoderBinaryPathInvalid)?;
+            let data_hash = ckb_hash::blake2b_256(&cached_binary);
+            let (live_binary, out_point) = fetch_live_decoder(rpc, decoder_search_option)
+                .await?
+                .ok_or(Error::DecoderIdNotFound)?;
+            let live_data_hash = ckb_hash::blake2b_256(&live_binary);
+            let mut record = DecoderBinaryRecord {
+                data_hash: H256(data_hash),
+                out_point,
+                verified_at: now,
+                binary_size: 0,
+                binary_modified_at_ns: 0,
+                upgraded_data_hash: (live_data_hash != data_hash).then_some(H256(live_data_hash)),
+            };
+            if let Some(upgraded_data_hash) = &record.upgraded_data_hash {
+                warn_pinned(decoder_path, upgraded_data_hash);
+            }
+            stamp_record(decoder_path, &mut record)?;
+            write_record(decoder_path, &record)
+        }
+        previous_record => {
+            let (binary, out_point) = fetch_live_decoder(rpc, decoder_search_option)
+                .await?
+                .ok_or(Error::DecoderIdNotFound)?;
+            write_decoder(decoder_path, &binary, out_point)?;
+            let upgraded = previous_record
+                .is_some_and(|record| record.data_hash.0 != ckb_hash::blake2b_256(&binary));
+            if upgraded {
+                invalidate_dob_render_cache(settings);
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn read_discovered_decoders(settings: &Settings) -> Vec<OnchainDecoderDeployment> {
+    std::fs::read(
+        settings
+            .decoders_cache_directory
+            .join(DISCOVERED_DECODERS_FILE),
+    )
+    .ok()
+    .and_then(|content| serde_json::from_slice(&content).ok())
+    .unwrap_or_default()
+}
+
+pub async fn persist_discovered_decoder(
+    settings: &Settings,
+    deployment: OnchainDecoderDeployment,
+) -> Result<(), Error> {
+    let _guard = DISCOVERY_FILES_LOCK.lock().await;
+    let mut deployments = read_discovered_decoders(settings);
+    deployments.retain(|discovered| discovered.code_hash != deployment.code_hash);
+    deployments.push(deployment);
+    let content = serde_json::to_vec_pretty(&deployments).expect("serialize discovered decoders");
+    write_file_atomically(
+        &settings
+            .decoders_cache_directory
+            .join(DISCOVERED_DECODERS_FILE),
+        &content,
+    )
+    .map_err(|_| Error::DecoderBinaryPathInvalid)
+}
+
+pub fn read_discovery_misses(settings: &Settings) -> Vec<DiscoveryMiss> {
+    std::fs::read(
+        settings
+            .decoders_cache_directory
+            .join(DISCOVERY_MISSES_FILE),
+    )
+    .ok()
+    .and_then(|content| serde_json::from_slice(&content).ok())
+    .unwrap_or_default()
+}
+
+// whether `code_hash` is missed in discovery within `DISCOVERY_MISS_TTL_SEC` before `now`
+pub fn is_missed_since(misses: &[DiscoveryMiss], code_hash: &H256, now: u64) -> bool {
+    misses.iter().any(|miss| {
+        &miss.code_hash == code_hash && now < miss.missed_at.saturating_add(DISCOVERY_MISS_TTL_SEC)
+    })
+}
+
+// add the discovery miss of `code_hash` at `now`, expired misses are dropped meanwhile
+pub fn add_discovery_miss(misses: &mut Vec<DiscoveryMiss>, code_hash: &H256, now: u64) {
+    misses.retain(|miss| {
+        &miss.code_hash != code_hash && now < miss.missed_at.saturating_add(DISCOVERY_MISS_TTL_SEC)
+    });
+    misses.push(DiscoveryMiss {
+        code_hash: code_hash.clone(),
+        missed_at: now,
+    });
+}
+
+// record the discovery miss of `code_hash`
+pub async fn record_discovery_miss(settings: &Settings, code_hash: &H256) -> Result<(), Error> {
+    let _guard = DISCOVERY_FILES_LOCK.lock().await;
+    let mut misses = read_discovery_misses(settings);
+    add_discovery_miss(&mut misses, code_hash, now()?);
+    let content = serde_json::to_vec_pretty(&misses).expect("serialize discovery misses");
+    write_file_atomically(
+        &settings
+            .decoders_cache_directory
+            .join(DISCOVERY_MISSES_FILE),
+        &content,
+    )
+    .map_err(|_| Error::DecoderBinaryPathInvalid)
+}
+
+pub fn is_recently_missed(settings: &Settings, code_hash: &H256) -> Result<bool, Error> {
+    Ok(is_missed_since(
+        &read_discovery_misses(settings),
+        code_hash,
+        now()?,
+    ))
+}
+
+// pick the cell whose data hash is `code_hash`
+pub fn find_decoder_cell(cells: Vec<Cell>, code_hash: &H256) -> Option<(Vec<u8>, OutPoint)> {
+    cells.into_iter().find_map(|cell| {
+        let binary = cell.output_data.unwrap_or_default().as_bytes().to_vec();
+        (ckb_hash::blake2b_256(&binary) == code_hash.0).then_some((binary, cell.out_point))
+    })
+}
+
+// scan live cells under deployer lock to find the one whose data hash is `code_hash`, at most
+// `MAX_DISCOVERY_PAGES` pages are scanned
+async fn search_decoder_by_lock(
+    rpc: &RpcClient,
+    lock: &ckb_jsonrpc_types::Script,
+    code_hash: &H256,
+) -> Result<Option<(Vec<u8>, OutPoint)>, Error> {
+    let search_key = CellQueryOptions::new_lock(packed::Script::from(lock.clone())).into();
+    let mut cursor = None;
+    for _ in 0..MAX_DISCOVERY_PAGES {
+        let page = rpc
+            .get_cells(search_key.clone(), DISCOVERY_PAGE_SIZE, cursor)
+            .await
+            .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?;
+        let page_size = page.objects.len();
+        if let Some(decoder) = find_decoder_cell(page.objects, code_hash) {
+            return Ok(Some(decoder));
+        }
+        if page_size < DISCOVERY_PAGE_SIZE as usize {
+            break;
+        }
+        cursor = Some(page.last_cursor);
+    }
+    Ok(None)
+}
+
+// search code_hash decoder that isn't configured in `onchain_decoder_deployment`, from the out point
+// discovered before at first, then from live cells under `decoder_deployer_locks`
+//
+// note: out point of newly discovered decoder is persisted, so that the costly scanning only happens once,
+// and code hash not found is persisted as well, so that it's not scanned again in `DISCOVERY_MISS_TTL_SEC`
+pub async fn discover_code_hash_decoder(
+    rpc: &RpcClient,
+    settings: &Settings,
+    code_hash: &H256,
+) -> Result<Option<Vec<u8>>, Error> {
+    let discovered = read_discovered_decoders(settings)
+        .into_iter()
+        .find(|deployment| &deployment.code_hash == code_hash);
+    if let Some(deployment) = discovered {
+        // persisted out point may have been consumed, in which case discover it again
+        match fetch_decoder_binary_directly(rpc, deployment.tx_hash, deployment.out_index).await {
+            Ok(binary) if ckb_hash::blake2b_256(&binary) == code_hash.0 => return Ok(Some(binary)),
+            Ok(_) | Err(Error::DecoderCellNotFound(..) | Error::DecoderBinaryNotFoundInCell) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    if settings.decoder_deployer_locks.is_empty() || is_recently_missed(settings, code_hash)? {
+        return Ok(None);
+    }
+    for lock in &settings.decoder_deployer_locks {
+        if let Some((binary, out_point)) = search_decoder_by_lock(rpc, lock, code_hash).await? {
+            persist_discovered_decoder(
+                settings,
+                OnchainDecoderDeployment {
+                    code_hash: code_hash.clone(),
+                    tx_hash: out_point.tx_hash,
+                    out_index: out_point.index.value(),
+                },
+            )
+            .await?;
+            return Ok(Some(binary));
+        }
+    }
+    record_discovery_miss(settings, code_hash).await?;
+    Ok(None)
+}
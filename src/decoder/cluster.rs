@@ -0,0 +1,191 @@
+use ckb_jsonrpc_types::JsonBytes;
+use ckb_sdk::rpc::ckb_indexer::{Cell, SearchKey, SearchMode};
+use ckb_types::{packed, prelude::Unpack, H256};
+
+use crate::{
+    client::RpcClient,
+    types::{
+        ClusterResolution, ClusterSporesCursor, ClusterSporesPage, Error, ScriptId, Settings,
+        SporeCellInfo, SporeStatus,
+    },
+};
+
+use super::helpers::{build_script_id_search_option, decode_spore_data};
+
+// maximum indexer pages scanned in one round, in case of no target found in many pages
+const MAX_SCANNED_PAGES: usize = 10;
+// spore cells scanned in one indexer page at most, which bounds spores returned in one round
+const MAX_CLUSTER_SPORES_LIMIT: u32 = 100;
+// cluster proxy or agent cells referring to the same cluster searched under one script id at most
+const REFERENCE_PAGE_SIZE: u32 = 100;
+
+// scan live spore cells under all `available_spores` and pick out those belong to the cluster,
+// scanning stops once any spore found in a page or too many pages scanned, so that the returned
+// spores never exceed `limit`, which is clamped to `MAX_CLUSTER_SPORES_LIMIT`, and empty result
+// with `next_cursor` means scanning should go on
+pub async fn fetch_cluster_spores(
+    rpc: &RpcClient,
+    settings: &Settings,
+    cluster_id: [u8; 32],
+    limit: u32,
+    cursor: Option<ClusterSporesCursor>,
+) -> Result<ClusterSporesPage, Error> {
+    let limit = limit.clamp(1, MAX_CLUSTER_SPORES_LIMIT);
+    let mut cursor = cursor.unwrap_or(ClusterSporesCursor {
+        script_index: 0,
+        indexer_cursor: None,
+    });
+    let mut spores = Vec::new();
+    for _ in 0..MAX_SCANNED_PAGES {
+        let Some(script_id) = settings.available_spores.get(cursor.script_index) else {
+            break;
+        };
+        let search_option = build_script_id_search_option(script_id);
+        let page = rpc
+            .get_cells(search_option.into(), limit, cursor.indexer_cursor.clone())
+            .await
+            .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?;
+        let exhausted = page.objects.len() < limit as usize;
+        for cell in page.objects {
+            let Some(spore_id) = cell
+                .output
+                .type_
+                .as_ref()
+                .and_then(|type_script| <[u8; 32]>::try_from(type_script.args.as_bytes()).ok())
+            else {
+                continue;
+            };
+            let Ok(mut spore) = decode_spore_data(cell.output_data.unwrap_or_default().as_bytes())
+            else {
+                continue;
+            };
+            if spore.cluster_id == cluster_id {
+                spore.block_number = Some(cell.block_number.value());
+                spore.cell = Some(SporeCellInfo {
+                    status: SporeStatus::Live,
+                    owner: Some(cell.output.lock),
+                    out_point: cell.out_point,
+                });
+                spores.push((spore_id, spore));
+            }
+        }
+        cursor = if exhausted {
+            ClusterSporesCursor {
+                script_index: cursor.script_index + 1,
+                indexer_cursor: None,
+            }
+        } else {
+            ClusterSporesCursor {
+                script_index: cursor.script_index,
+                indexer_cursor: Some(page.last_cursor),
+            }
+        };
+        if !spores.is_empty() {
+            break;
+        }
+    }
+    let next_cursor = (cursor.script_index < settings.available_spores.len()).then_some(cursor);
+    Ok(ClusterSporesPage {
+        spores,
+        next_cursor,
+    })
+}
+
+// search live cells under any one of script ids, whose data starts with `data_prefix`, at most
+// `REFERENCE_PAGE_SIZE` cells under each script id are returned
+async fn fetch_cells_by_data(
+    rpc: &RpcClient,
+    data_prefix: &[u8; 32],
+    script_ids: &[ScriptId],
+) -> Result<Vec<Cell>, Error> {
+    let mut cells = Vec::new();
+    for script_id in script_ids {
+        let mut search_key: SearchKey = build_script_id_search_option(script_id).into();
+        let filter = search_key.filter.get_or_insert_with(Default::default);
+        filter.output_data = Some(JsonBytes::from_vec(data_prefix.to_vec()));
+        filter.output_data_filter_mode = Some(SearchMode::Prefix);
+        let page = rpc
+            .get_cells(search_key, REFERENCE_PAGE_SIZE, None)
+            .await
+            .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?;
+        cells.extend(page.objects);
+    }
+    Ok(cells)
+}
+
+// both cluster proxy and agent cells keep the id of cluster they refer to in the head of cell data
+fn referenced_cluster_id(cell: &Cell) -> Option<[u8; 32]> {
+    let data = cell.output_data.as_ref()?.as_bytes();
+    data.get(..32)?.try_into().ok()
+}
+
+fn type_hash(cell: &Cell) -> Option<[u8; 32]> {
+    let type_script = packed::Script::from(cell.output.type_.clone()?);
+    let hash: H256 = type_script.calc_script_hash().unpack();
+    Some(hash.0)
+}
+
+// pick the path through which cluster is referenced by proxy and agent cells, following the
+// layout of spore-contract:
+//
+// proxy: data = cluster_id, type args = proxy_id [+ minimal payment]
+// agent: data = cluster_id, type args = type hash of proxy
+//
+// agent is preferred since it refers to cluster through a proxy, and it's ignored if its proxy
+// isn't found among `proxies`
+pub fn match_cluster_reference(
+    cluster_id: [u8; 32],
+    proxies: &[Cell],
+    agents: &[Cell],
+) -> Option<ClusterResolution> {
+    let proxies = proxies
+        .iter()
+        .filter(|proxy| referenced_cluster_id(proxy) == Some(cluster_id))
+        .collect::<Vec<_>>();
+    for agent in agents {
+        if referenced_cluster_id(agent) != Some(cluster_id) {
+            continue;
+        }
+        let Some(agent_args) = agent
+            .output
+            .type_
+            .as_ref()
+            .map(|script| script.args.as_bytes())
+        else {
+            continue;
+        };
+        let proxy = proxies
+            .iter()
+            .find(|proxy| type_hash(proxy).is_some_and(|hash| hash[..] == agent_args[..]));
+        if let Some(proxy) = proxy {
+            return Some(ClusterResolution::Agent {
+                agent_out_point: agent.out_point.clone(),
+                proxy_out_point: proxy.out_point.clone(),
+                cluster_id: H256(cluster_id),
+            });
+        }
+    }
+    proxies.first().map(|proxy| ClusterResolution::Proxy {
+        proxy_out_point: proxy.out_point.clone(),
+        cluster_id: H256(cluster_id),
+    })
+}
+
+// search live cluster proxy or agent cells referring to the cluster, which keep the cluster
+// reachable when its own cell isn't live under `available_clusters`
+pub(crate) async fn resolve_cluster_indirectly(
+    rpc: &RpcClient,
+    settings: &Settings,
+    cluster_id: [u8; 32],
+) -> Result<Option<ClusterResolution>, Error> {
+    if settings.available_cluster_proxies.is_empty() {
+        return Ok(None);
+    }
+    let proxies =
+        fetch_cells_by_data(rpc, &cluster_id, &settings.available_cluster_proxies).await?;
+    if proxies.is_empty() {
+        return Ok(None);
+    }
+    let agents = fetch_cells_by_data(rpc, &cluster_id, &settings.available_cluster_agents).await?;
+    Ok(match_cluster_reference(cluster_id, &proxies, &agents))
+}
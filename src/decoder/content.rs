@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use ciborium::Value as CborValue;
+use ckb_types::{packed, prelude::Entity};
+use serde_json::{Map, Number, Value};
+
+use crate::types::Error;
+
+// nesting limit of CBOR payload, in case of malicious content exhausts the stack
+const MAX_CBOR_DEPTH: usize = 32;
+
+// MIME type declared in `content_type` field of spore, e.g. `dob/1;encoding=cbor`
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ContentType {
+    pub mime: String,
+    pub params: BTreeMap<String, String>,
+}
+
+impl ContentType {
+    pub fn parse(content_type: &str) -> Self {
+        let mut parts = content_type.split(';');
+        let mime = parts.next().unwrap_or_default().trim().to_lowercase();
+        let params = parts
+            .filter_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                let value = value.trim().trim_matches('"');
+                Some((key.trim().to_lowercase(), value.to_owned()))
+            })
+            .collect();
+        Self { mime, params }
+    }
+
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params.get(key).map(String::as_str)
+    }
+
+    // DOB version number declared in MIME type, e.g. `1` in `dob/1`, none if it's not a DOB type
+    pub fn dob_version(&self) -> Option<u8> {
+        self.mime.strip_prefix("dob/")?.parse().ok()
+    }
+
+    // only UTF-8 compatible charsets are accepted by JSON content
+    fn check_charset(&self) -> Result<(), Error> {
+        match self.param("charset").map(str::to_lowercase).as_deref() {
+            None | Some("utf-8") | Some("utf8") | Some("us-ascii") => Ok(()),
+            Some(charset) => Err(Error::SporeContentCharsetUnsupported(charset.to_owned())),
+        }
+    }
+}
+
+// encoding of spore content, declared by `encoding` parameter of content type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContentEncoding {
+    // JSON string, array or object, or raw DNA bytes prefixed with `0x00`, judged from content
+    Auto,
+    Json,
+    Cbor,
+    // molecule `Bytes` whose raw data is DNA bytes
+    Molecule,
+    // entire content is DNA bytes
+    Raw,
+}
+
+impl ContentEncoding {
+    pub fn from_content_type(content_type: &ContentType) -> Result<Self, Error> {
+        match content_type
+            .param("encoding")
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            None => Ok(Self::Auto),
+            Some("json") => Ok(Self::Json),
+            Some("cbor") => Ok(Self::Cbor),
+            Some("molecule") => Ok(Self::Molecule),
+            Some("raw") => Ok(Self::Raw),
+            Some(encoding) => Err(Error::SporeContentEncodingUnsupported(encoding.to_owned())),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Json => "json",
+            Self::Cbor => "cbor",
+            Self::Molecule => "molecule",
+            Self::Raw => "raw",
+        }
+    }
+}
+
+// decode spore content in the encoding declared by content type, and return content value and DNA
+pub fn decode_content(
+    content_type: &ContentType,
+    content: &[u8],
+) -> Result<(Value, String), Error> {
+    if content.is_empty() {
+        return Err(Error::SporeContentEmpty);
+    }
+    let encoding = ContentEncoding::from_content_type(content_type)?;
+    let value = match encoding {
+        ContentEncoding::Auto if content[0] == 0u8 => Value::String(hex::encode(&content[1..])),
+        ContentEncoding::Auto | ContentEncoding::Json => {
+            content_type.check_charset()?;
+            serde_json::from_slice(content).map_err(|e| {
+                Error::SporeContentMalformed(encoding.name().to_owned(), e.to_string())
+            })?
+        }
+        ContentEncoding::Cbor => decode_cbor(content)
+            .map_err(|e| Error::SporeContentMalformed(encoding.name().to_owned(), e))?,
+        ContentEncoding::Molecule => {
+            let bytes = packed::Bytes::from_slice(content).map_err(|e| {
+                Error::SporeContentMalformed(encoding.name().to_owned(), e.to_string())
+            })?;
+            Value::String(hex::encode(bytes.raw_data()))
+        }
+        ContentEncoding::Raw => Value::String(hex::encode(content)),
+    };
+    let dna = extract_dna(&value)?;
+    Ok((value, dna))
+}
+
+// DNA is either the content string, the first element of content array or `dna` field of content object
+fn extract_dna(value: &Value) -> Result<String, Error> {
+    let dna = match value {
+        Value::String(_) => value,
+        Value::Array(array) => array.first().ok_or(Error::SporeContentDNANotFound(
+            "content array is empty".to_owned(),
+        ))?,
+        Value::Object(object) => object.get("dna").ok_or(Error::SporeContentDNANotFound(
+            "content object has no `dna` field".to_owned(),
+        ))?,
+        _ => {
+            return Err(Error::SporeContentDNANotFound(
+                "content is neither string, array nor object".to_owned(),
+            ))
+        }
+    };
+    match dna {
+        Value::String(string) => Ok(string.to_owned()),
+        _ => Err(Error::SporeContentDNANotFound(
+            "DNA is not a string".to_owned(),
+        )),
+    }
+}
+
+// decode CBOR document into JSON value, byte strings are converted into hex strings and semantic
+// tags are ignored
+fn decode_cbor(content: &[u8]) -> Result<Value, String> {
+    let mut reader = content;
+    let value: CborValue =
+        ciborium::de::from_reader_with_recursion_limit(&mut reader, MAX_CBOR_DEPTH)
+            .map_err(|e| e.to_string())?;
+    if !reader.is_empty() {
+        return Err(format!(
+            "trailing bytes at offset {}",
+            content.len() - reader.len()
+        ));
+    }
+    cbor_to_json(value)
+}
+
+fn cbor_to_json(value: CborValue) -> Result<Value, String> {
+    let value = match value {
+        CborValue::Integer(integer) => {
+            let integer = i128::from(integer);
+            let number = u64::try_from(integer)
+                .map(Number::from)
+                .or_else(|_| i64::try_from(integer).map(Number::from))
+                .map_err(|_| "integer overflow".to_owned())?;
+            Value::Number(number)
+        }
+        CborValue::Float(float) => Number::from_f64(float)
+            .map(Value::Number)
+            .ok_or_else(|| "non-finite float".to_owned())?,
+        CborValue::Bytes(bytes) => Value::String(hex::encode(bytes)),
+        CborValue::Text(text) => Value::String(text),
+        CborValue::Bool(bool) => Value::Bool(bool),
+        CborValue::Null => Value::Null,
+        CborValue::Tag(_, value) => cbor_to_json(*value)?,
+        CborValue::Array(array) => Value::Array(
+            array
+                .into_iter()
+                .map(cbor_to_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        CborValue::Map(map) => {
+            let mut object = Map::new();
+            for (key, value) in map {
+                let CborValue::Text(key) = key else {
+                    return Err("map key is not a string".to_owned());
+                };
+                object.insert(key, cbor_to_json(value)?);
+            }
+            Value::Object(object)
+        }
+        _ => return Err("unsupported CBOR value".to_owned()),
+    };
+    Ok(value)
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ckb_types::H256;
+use serde_json::Value;
+
+use crate::types::{
+    ClusterDescriptionField, DOBDecoderFormat, DOBSporeFormat, DecoderDryRunReport,
+    DecoderLocationType, DecoderSubstitute, DecoderSubstituteReport, DryRunRender, Error,
+    OutputDifference, Settings,
+};
+
+use super::DOBDecoder;
+
+// directory under decoders cache directory, which stores substitute binaries uploaded for dry-run
+const SUBSTITUTES_DIRECTORY: &str = "substitutes";
+
+// distinguishes files of substitute binaries stored by this process
+static SUBSTITUTE_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    // substitute decoders of the dry-run that current task is running for
+    static DECODER_SUBSTITUTES: Vec<DecoderSubstitute>;
+}
+
+// decoders are located by hash for `code_hash` and `type_id`, or by script for `type_script`
+fn same_location(a: &DOBDecoderFormat, b: &DOBDecoderFormat) -> bool {
+    a.location == b.location
+        && match a.location {
+            DecoderLocationType::CodeHash | DecoderLocationType::TypeId => a.hash == b.hash,
+            DecoderLocationType::TypeScript => a.script == b.script,
+        }
+}
+
+// local binary substituted for the decoder location, none if current task isn't in dry-run
+pub(super) fn substitute_decoder_path(decoder: &DOBDecoderFormat) -> Option<PathBuf> {
+    DECODER_SUBSTITUTES
+        .try_with(|substitutes| {
+            substitutes
+                .iter()
+                .find(|substitute| same_location(&substitute.decoder, decoder))
+                .map(|substitute| substitute.binary_path.clone())
+        })
+        .ok()
+        .flatten()
+}
+
+// whether current task is in dry-run, whose decoder outputs are not supposed to be cached
+pub(crate) fn is_dry_run() -> bool {
+    DECODER_SUBSTITUTES.try_with(|_| ()).is_ok()
+}
+
+// uploaded substitute binary, which is stored for one dry-run and removed once dropped
+pub struct SubstituteBinary {
+    path: PathBuf,
+}
+
+impl SubstituteBinary {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SubstituteBinary {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// store uploaded substitute binary in a file of its own, so that concurrent dry-runs of the same
+// binary never remove each other's file
+pub fn store_substitute_binary(
+    settings: &Settings,
+    binary: &[u8],
+) -> Result<SubstituteBinary, Error> {
+    if binary.is_empty() {
+        return Err(Error::DecoderSubstituteInvalid("empty binary".to_owned()));
+    }
+    let directory = settings
+        .decoders_cache_directory
+        .join(SUBSTITUTES_DIRECTORY);
+    std::fs::create_dir_all(&directory).map_err(|_| Error::DecoderBinaryPathInvalid)?;
+    let binary_hash = ckb_hash::blake2b_256(binary);
+    let sequence = SUBSTITUTE_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let path = directory.join(format!("{}-{sequence}.bin", hex::encode(binary_hash)));
+    std::fs::write(&path, binary).map_err(|_| Error::DecoderBinaryPathInvalid)?;
+    Ok(SubstituteBinary { path })
+}
+
+// remove substitute binaries left by dry-runs interrupted before, e.g. by a crash
+pub fn clean_substitute_binaries(settings: &Settings) {
+    let directory = settings
+        .decoders_cache_directory
+        .join(SUBSTITUTES_DIRECTORY);
+    let _ = std::fs::remove_dir_all(directory);
+}
+
+fn binary_hash(binary_path: &Path) -> Result<H256, Error> {
+    let binary = std::fs::read(binary_path).map_err(|error| {
+        Error::DecoderSubstituteInvalid(format!("read {}: {error}", binary_path.display()))
+    })?;
+    Ok(H256(ckb_hash::blake2b_256(binary)))
+}
+
+fn diff_values(
+    path: String,
+    deployed: Option<&Value>,
+    dry_run: Option<&Value>,
+    differences: &mut Vec<OutputDifference>,
+) {
+    match (deployed, dry_run) {
+        (Some(Value::Object(deployed)), Some(Value::Object(dry_run))) => {
+            let keys = deployed
+                .keys()
+                .chain(dry_run.keys())
+                .collect::<BTreeSet<_>>();
+            for key in keys {
+                let key_path = format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+                diff_values(key_path, deployed.get(key), dry_run.get(key), differences);
+            }
+        }
+        (Some(Value::Array(deployed)), Some(Value::Array(dry_run))) => {
+            for i in 0..deployed.len().max(dry_run.len()) {
+                diff_values(
+                    format!("{path}/{i}"),
+                    deployed.get(i),
+                    dry_run.get(i),
+                    differences,
+                );
+            }
+        }
+        (deployed, dry_run) if deployed != dry_run => differences.push(OutputDifference {
+            path,
+            deployed: deployed.cloned(),
+            dry_run: dry_run.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+// compare render outputs value by value if both are in JSON, or as a whole string otherwise
+pub fn diff_render_outputs(deployed: &str, dry_run: &str) -> Vec<OutputDifference> {
+    let (deployed, dry_run) = match (
+        serde_json::from_str::<Value>(deployed),
+        serde_json::from_str::<Value>(dry_run),
+    ) {
+        (Ok(deployed), Ok(dry_run)) => (deployed, dry_run),
+        _ => (
+            Value::String(deployed.to_owned()),
+            Value::String(dry_run.to_owned()),
+        ),
+    };
+    let mut differences = Vec::new();
+    diff_values(
+        String::new(),
+        Some(&deployed),
+        Some(&dry_run),
+        &mut differences,
+    );
+    differences
+}
+
+async fn render(
+    decoder: &DOBDecoder,
+    spore: &DOBSporeFormat,
+    dob_metadata: &ClusterDescriptionField,
+) -> DryRunRender {
+    match decoder.decode_spore(spore, dob_metadata.clone()).await {
+        Ok(render_output) => DryRunRender {
+            render_output: Some(render_output),
+            render_error: None,
+        },
+        Err(error) => DryRunRender {
+            render_output: None,
+            render_error: Some(error.to_string()),
+        },
+    }
+}
+
+// render spore by the deployed decoders and again with substitute binaries in place of the
+// decoders at their locations, outputs of substitute binaries are never cached
+pub async fn dry_run_spore(
+    decoder: &DOBDecoder,
+    spore_id: [u8; 32],
+    substitutes: Vec<DecoderSubstitute>,
+) -> Result<DecoderDryRunReport, Error> {
+    let ingredients = decoder.fetch_dob_ingredients(spore_id).await?;
+    let dob_metadata = ingredients.cluster.metadata;
+    let cluster_decoders = decoder
+        .protocol(dob_metadata.dob_version())?
+        .parse_metadata(&dob_metadata.dob)?;
+    let substitute_reports = substitutes
+        .iter()
+        .map(|substitute| {
+            Ok(DecoderSubstituteReport {
+                decoder: substitute.decoder.clone(),
+                binary_hash: binary_hash(&substitute.binary_path)?,
+                matched: cluster_decoders
+                    .iter()
+                    .any(|cluster_decoder| same_location(cluster_decoder, &substitute.decoder)),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    if !substitute_reports.iter().any(|report| report.matched) {
+        return Err(Error::DecoderSubstituteUnmatched);
+    }
+
+    let spore = &ingredients.spore;
+    let deployed = render(decoder, spore, &dob_metadata).await;
+    let dry_run = DECODER_SUBSTITUTES
+        .scope(substitutes, render(decoder, spore, &dob_metadata))
+        .await;
+    let differences = match (&deployed.render_output, &dry_run.render_output) {
+        (Some(deployed), Some(dry_run)) => diff_render_outputs(deployed, dry_run),
+        _ => Vec::new(),
+    };
+    Ok(DecoderDryRunReport {
+        spore_id: hex::encode(spore_id),
+        dna: spore.dna.clone(),
+        substitutes: substitute_reports,
+        identical: deployed == dry_run,
+        deployed,
+        dry_run,
+        differences,
+    })
+}
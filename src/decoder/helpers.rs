@@ -1,7 +1,15 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use ckb_jsonrpc_types::Either;
-use ckb_sdk::{constants::TYPE_ID_CODE_HASH, rpc::ckb_indexer::Tx, traits::CellQueryOptions};
+use ckb_jsonrpc_types::{
+    Either, JsonBytes, OutPoint as JsonOutPoint, TransactionView, TransactionWithStatusResponse,
+};
+use ckb_sdk::{
+    constants::TYPE_ID_CODE_HASH,
+    rpc::ckb_indexer::{Cell, Tx},
+    traits::CellQueryOptions,
+};
 use ckb_types::{
     core::ScriptHashType,
     packed::{OutPoint, Script},
@@ -14,11 +22,19 @@ use spore_types::{generated::spore::ClusterData, SporeData};
 use crate::{
     client::RpcClient,
     types::{
-        ClusterDescriptionField, DOBDecoderFormat, DOBSporeFormat, DecoderLocationType, Error,
-        ScriptId, Settings,
+        ClusterDescriptionField, ClusterResolution, DOBClusterInfo, DOBDecoderFormat,
+        DOBSporeFormat, DecoderLocationType, Error, ScriptId, Settings, SporeCellInfo,
+        SporeResolutionMode, SporeStatus,
     },
 };
 
+use super::{
+    binary::{discover_code_hash_decoder, prepare_upgradable_decoder},
+    cluster::resolve_cluster_indirectly,
+    content::{decode_content, ContentType},
+    history::fetch_historical_cluster_cell,
+};
+
 fn build_type_id_search_option(type_id_args: [u8; 32]) -> CellQueryOptions {
     let type_script = Script::new_builder()
         .code_hash(TYPE_ID_CODE_HASH.0.pack())
@@ -32,7 +48,17 @@ fn build_type_script_search_option(type_script: Script) -> CellQueryOptions {
     CellQueryOptions::new_type(type_script)
 }
 
-fn build_batch_search_options(
+// search all cells under the script id, regardless of script args
+pub(crate) fn build_script_id_search_option(script_id: &ScriptId) -> CellQueryOptions {
+    let hash_type: ScriptHashType = (&script_id.hash_type).into();
+    let type_script = Script::new_builder()
+        .code_hash(script_id.code_hash.0.pack())
+        .hash_type(hash_type.into())
+        .build();
+    CellQueryOptions::new_type(type_script)
+}
+
+pub(crate) fn build_batch_search_options(
     type_args: &[u8; 32],
     available_script_ids: &[ScriptId],
 ) -> Vec<CellQueryOptions> {
@@ -55,6 +81,103 @@ fn build_batch_search_options(
         .collect()
 }
 
+// check if script is deployed under any one of script ids, e.g. spore or cluster script ids in settings
+pub fn script_matches(script: &ckb_jsonrpc_types::Script, script_ids: &[ScriptId]) -> bool {
+    script_ids.iter().any(|script_id| {
+        let hash_type: ScriptHashType = (&script_id.hash_type).into();
+        script.code_hash == script_id.code_hash
+            && script.hash_type == ckb_jsonrpc_types::ScriptHashType::from(hash_type)
+    })
+}
+
+// file names of cached binaries that type_script or type_id decoders located by the type script
+// are stored in
+pub fn decoder_cache_names(type_script: &ckb_jsonrpc_types::Script) -> Vec<String> {
+    let script: Script = type_script.clone().into();
+    let mut cached_names = vec![format!(
+        "type_script_{}.bin",
+        hex::encode(script.calc_script_hash().raw_data())
+    )];
+    if type_script.code_hash == TYPE_ID_CODE_HASH
+        && type_script.hash_type == ckb_jsonrpc_types::ScriptHashType::Type
+    {
+        cached_names.push(format!(
+            "type_id_{}.bin",
+            hex::encode(type_script.args.as_bytes())
+        ));
+    }
+    cached_names
+}
+
+// file names of all cached decoder binaries, which are listed in one pass so that type scripts of
+// a whole block are matched in memory rather than against disk one by one
+pub fn cached_decoder_names(settings: &Settings) -> HashSet<String> {
+    let Ok(entries) = std::fs::read_dir(&settings.decoders_cache_directory) else {
+        return HashSet::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".bin"))
+        .collect()
+}
+
+// cached binaries of type_script or type_id decoders located by the type script, empty if the
+// type script doesn't locate any decoder in use
+pub fn cached_decoder_paths(
+    settings: &Settings,
+    type_script: &ckb_jsonrpc_types::Script,
+) -> Vec<PathBuf> {
+    decoder_cache_names(type_script)
+        .into_iter()
+        .map(|name| settings.decoders_cache_directory.join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+// remove cached decoder binaries deployed under `type_script`, so that upgraded one will be downloaded again
+// under `follow` policy, or the pinned one recovered under `pin` policy
+pub fn invalidate_decoder_cache(
+    settings: &Settings,
+    type_script: &ckb_jsonrpc_types::Script,
+) -> Vec<PathBuf> {
+    cached_decoder_paths(settings, type_script)
+        .into_iter()
+        .filter(|path| std::fs::remove_file(path).is_ok())
+        .collect()
+}
+
+// write file through a temporary file beside it, so that readers never see a partially written one,
+// the temporary file is unique to each writing, so that concurrent writers never interleave
+pub fn write_file_atomically(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result =
+        std::fs::write(&temp_path, content).and_then(|_| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// remove all rendered DOBs in cache, which are rendered by decoders that have been upgraded
+pub fn invalidate_dob_render_cache(settings: &Settings) {
+    let Ok(entries) = std::fs::read_dir(&settings.dobs_cache_directory) else {
+        return;
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "dob"))
+        .for_each(|path| {
+            let _ = std::fs::remove_file(path);
+        });
+}
+
 pub fn decode_spore_data(spore_data: &[u8]) -> Result<DOBSporeFormat, Error> {
     let molecule_spore_data =
         SporeData::from_compatible_slice(spore_data).map_err(|_| Error::SporeDataUncompatible)?;
@@ -65,45 +188,79 @@ pub fn decode_spore_data(spore_data: &[u8]) -> Result<DOBSporeFormat, Error> {
         .to_opt()
         .ok_or(Error::ClusterIdNotSet)?
         .raw_data();
-    let (content, dna) = decode_spore_content(&molecule_spore_data.content().raw_data())?;
+    let (content, dna) = decode_content(
+        &ContentType::parse(&content_type),
+        &molecule_spore_data.content().raw_data(),
+    )?;
     Ok(DOBSporeFormat {
         content_type,
         content,
         dna,
         cluster_id: cluster_id.to_vec().try_into().unwrap(),
+        block_number: None,
+        cell: None,
     })
 }
 
+// decode spore content whose encoding is judged from content itself
 pub fn decode_spore_content(content: &[u8]) -> Result<(Value, String), Error> {
-    if content[0] == 0u8 {
-        let dna = hex::encode(&content[1..]);
-        return Ok((serde_json::Value::String(dna.clone()), dna));
+    decode_content(&ContentType::default(), content)
+}
+
+// generate DNA of the length in bytes from seed deterministically, by chaining blake2b hashes
+pub fn generate_dna(seed: &[u8], length: usize) -> String {
+    let mut dna = Vec::with_capacity(length);
+    let mut hash = ckb_hash::blake2b_256(seed);
+    while dna.len() < length {
+        dna.extend_from_slice(&hash);
+        hash = ckb_hash::blake2b_256(hash);
     }
+    dna.truncate(length);
+    hex::encode(dna)
+}
 
-    let value: Value = serde_json::from_slice(content).map_err(|_| Error::DOBContentUnexpected)?;
-    let dna = match &value {
-        serde_json::Value::String(_) => &value,
-        serde_json::Value::Array(array) => array.first().ok_or(Error::DOBContentUnexpected)?,
-        serde_json::Value::Object(object) => {
-            object.get("dna").ok_or(Error::DOBContentUnexpected)?
-        }
-        _ => return Err(Error::DOBContentUnexpected),
+// extract transaction view from `get_transaction` response, none if the transaction is unknown
+pub fn unwrap_transaction_view(
+    tx: TransactionWithStatusResponse,
+) -> Result<Option<TransactionView>, Error> {
+    let Some(tx) = tx.transaction else {
+        return Ok(None);
     };
-    let dna = match dna {
-        serde_json::Value::String(string) => string.to_owned(),
-        _ => return Err(Error::DOBContentUnexpected),
+    let tx = match tx.inner {
+        Either::Left(view) => view,
+        Either::Right(bytes) => serde_json::from_slice(&bytes.into_bytes())
+            .map_err(|e| Error::FetchTransactionError(e.to_string()))?,
     };
+    Ok(Some(tx))
+}
 
-    Ok((value, dna))
+// search live spore cell, none if spore is melted or never minted
+pub async fn fetch_live_spore_cell(
+    rpc: &RpcClient,
+    settings: &Settings,
+    spore_id: [u8; 32],
+) -> Result<Option<Cell>, Error> {
+    for spore_search_option in build_batch_search_options(&spore_id, &settings.available_spores) {
+        let spore_cell = rpc
+            .get_cells(spore_search_option.into(), 1, None)
+            .await
+            .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?
+            .objects
+            .first()
+            .cloned();
+        if spore_cell.is_some() {
+            return Ok(spore_cell);
+        }
+    }
+    Ok(None)
 }
 
-// search on-chain spore cell and return its content field, which represents dob content
-pub async fn fetch_dob_content(
+// search spore mint transaction and return the minted spore cell data, block number and out point
+async fn fetch_minted_spore_cell(
     rpc: &RpcClient,
     settings: &Settings,
     spore_id: [u8; 32],
-) -> Result<DOBSporeFormat, Error> {
-    let mut spore_cell_data = None;
+) -> Result<Option<(JsonBytes, u64, JsonOutPoint)>, Error> {
     for spore_search_option in build_batch_search_options(&spore_id, &settings.available_spores) {
         let spore_mint_tx = rpc
             .get_transactions(spore_search_option.into(), 1, None)
@@ -120,64 +277,200 @@ pub async fn fetch_dob_content(
             else {
                 continue;
             };
-            let Some(tx) = tx.transaction else {
+            let Some(tx) = unwrap_transaction_view(tx)? else {
                 continue;
             };
-            let tx = match tx.inner {
-                Either::Left(view) => view,
-                Either::Right(bytes) => serde_json::from_slice(&bytes.into_bytes())
-                    .map_err(|e| Error::FetchTransactionError(e.to_string()))?,
+            let spore_cell_data = tx
+                .inner
+                .outputs_data
+                .get(mint.io_index.value() as usize)
+                .cloned()
+                .unwrap_or_default();
+            let out_point = JsonOutPoint {
+                tx_hash: mint.tx_hash,
+                index: mint.io_index,
+            };
+            return Ok(Some((
+                spore_cell_data,
+                mint.block_number.value(),
+                out_point,
+            )));
+        }
+    }
+    Ok(None)
+}
+
+// search spore cell in the configured resolution mode, and return its data, block number and on-chain state
+async fn search_spore_cell(
+    rpc: &RpcClient,
+    settings: &Settings,
+    spore_id: [u8; 32],
+) -> Result<Option<(JsonBytes, u64, Option<SporeCellInfo>)>, Error> {
+    if let SporeResolutionMode::LiveCellFirst = settings.spore_resolution {
+        if let Some(live_cell) = fetch_live_spore_cell(rpc, settings, spore_id).await? {
+            let cell_info = SporeCellInfo {
+                status: SporeStatus::Live,
+                owner: Some(live_cell.output.lock),
+                out_point: live_cell.out_point,
             };
-            spore_cell_data = Some(
-                tx.inner
-                    .outputs_data
-                    .get(mint.io_index.value() as usize)
-                    .cloned()
-                    .unwrap_or_default(),
-            );
-            break;
+            return Ok(Some((
+                live_cell.output_data.unwrap_or_default(),
+                live_cell.block_number.value(),
+                Some(cell_info),
+            )));
         }
     }
-    let Some(spore_cell_data) = spore_cell_data else {
+    let Some((spore_cell_data, block_number, out_point)) =
+        fetch_minted_spore_cell(rpc, settings, spore_id).await?
+    else {
+        return Ok(None);
+    };
+    let cell_info = match settings.spore_resolution {
+        SporeResolutionMode::LiveCellFirst => Some(SporeCellInfo {
+            status: SporeStatus::Melted,
+            owner: None,
+            out_point,
+        }),
+        SporeResolutionMode::MintTransaction => None,
+    };
+    Ok(Some((spore_cell_data, block_number, cell_info)))
+}
+
+// search on-chain state of spore cell, none in `mint_transaction` resolution mode
+pub async fn fetch_spore_cell_info(
+    rpc: &RpcClient,
+    settings: &Settings,
+    spore_id: [u8; 32],
+) -> Result<Option<SporeCellInfo>, Error> {
+    if let SporeResolutionMode::MintTransaction = settings.spore_resolution {
+        return Ok(None);
+    }
+    let Some((_, _, cell_info)) = search_spore_cell(rpc, settings, spore_id).await? else {
+        return Err(Error::SporeIdNotFound(hex::encode(spore_id)));
+    };
+    Ok(cell_info)
+}
+
+// search on-chain spore cell and return its content field, which represents dob content
+pub async fn fetch_dob_content(
+    rpc: &RpcClient,
+    settings: &Settings,
+    spore_id: [u8; 32],
+) -> Result<DOBSporeFormat, Error> {
+    let Some((spore_cell_data, block_number, cell_info)) =
+        search_spore_cell(rpc, settings, spore_id).await?
+    else {
         return Err(Error::SporeIdNotFound(hex::encode(spore_id)));
     };
-    let dob = decode_spore_data(spore_cell_data.as_bytes())?;
-    if !dob.content_type.is_empty()
+    let mut dob = decode_spore_data(spore_cell_data.as_bytes())?;
+    dob.block_number = Some(block_number);
+    dob.cell = cell_info;
+    check_protocol_version(settings, &dob.content_type)?;
+    Ok(dob)
+}
+
+// check spore content type against `protocol_versions` in settings, spore without a content type
+// is accepted
+pub fn check_protocol_version(settings: &Settings, content_type: &str) -> Result<(), Error> {
+    let mime = ContentType::parse(content_type).mime;
+    if !content_type.is_empty()
         && !settings
             .protocol_versions
             .iter()
-            .any(|version| dob.content_type.starts_with(version))
+            .any(|version| version.eq_ignore_ascii_case(&mime))
     {
-        return Err(Error::DOBVersionUnexpected(dob.content_type));
+        return Err(Error::DOBVersionUnexpected(content_type.to_owned()));
     }
-    Ok(dob)
+    Ok(())
+}
+
+// check DOB version declared in spore content type against the one declared in cluster, spore
+// without a DOB content type is regarded as compatible with any cluster
+pub fn check_dob_version(
+    content_type: &str,
+    dob_metadata: &ClusterDescriptionField,
+) -> Result<(), Error> {
+    let Some(spore_version) = ContentType::parse(content_type).dob_version() else {
+        return Ok(());
+    };
+    let cluster_version = dob_metadata.dob_version();
+    if spore_version != cluster_version {
+        return Err(Error::DOBVersionMismatch(spore_version, cluster_version));
+    }
+    Ok(())
 }
 
 // search on-chain cluster cell and return its description field, which contains dob metadata
+#[allow(dead_code)]
 pub async fn fetch_dob_metadata(
     rpc: &RpcClient,
     settings: &Settings,
     cluster_id: [u8; 32],
 ) -> Result<ClusterDescriptionField, Error> {
-    let mut cluster_cell = None;
+    let cluster = fetch_dob_cluster(rpc, settings, cluster_id).await?;
+    Ok(cluster.metadata)
+}
+
+// search live cluster cell directly under `available_clusters`
+pub(crate) async fn fetch_cluster_cell(
+    rpc: &RpcClient,
+    settings: &Settings,
+    cluster_id: [u8; 32],
+) -> Result<Option<Cell>, Error> {
     for cluster_search_option in
         build_batch_search_options(&cluster_id, &settings.available_clusters)
     {
-        cluster_cell = rpc
-            .get_cells(cluster_search_option.into(), 1, None)
+        let cluster_cell = rpc
+            .get_cell_by_type(cluster_search_option.into())
             .await
-            .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?
-            .objects
-            .first()
-            .cloned();
+            .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?;
         if cluster_cell.is_some() {
-            break;
+            return Ok(cluster_cell);
         }
     }
-    let Some(cluster_cell) = cluster_cell else {
-        return Err(Error::ClusterIdNotFound(hex::encode(cluster_id)));
+    Ok(None)
+}
+
+// search on-chain cluster cell and return dob metadata along with the block number of cluster cell,
+// cluster is searched directly at first, then from the latest cluster transaction in case of the
+// cluster cell isn't live, and cluster proxy or agent cell referring to it is reported if any
+pub async fn fetch_dob_cluster(
+    rpc: &RpcClient,
+    settings: &Settings,
+    cluster_id: [u8; 32],
+) -> Result<DOBClusterInfo, Error> {
+    let Some(cluster_cell) = fetch_cluster_cell(rpc, settings, cluster_id).await? else {
+        let mut cluster = fetch_historical_dob_cluster(rpc, settings, cluster_id, None).await?;
+        if let Some(resolution) = resolve_cluster_indirectly(rpc, settings, cluster_id).await? {
+            cluster.resolution = resolution;
+        }
+        return Ok(cluster);
     };
-    decode_cluster_data(cluster_cell.output_data.unwrap_or_default().as_bytes())
+    let metadata = decode_cluster_data(cluster_cell.output_data.unwrap_or_default().as_bytes())?;
+    Ok(DOBClusterInfo {
+        metadata,
+        block_number: cluster_cell.block_number.value(),
+        resolution: ClusterResolution::Direct,
+    })
+}
+
+// search cluster data from the latest cluster transaction no later than `max_block_number`
+pub async fn fetch_historical_dob_cluster(
+    rpc: &RpcClient,
+    settings: &Settings,
+    cluster_id: [u8; 32],
+    max_block_number: Option<u64>,
+) -> Result<DOBClusterInfo, Error> {
+    let (cluster_cell_data, block_number, out_point) =
+        fetch_historical_cluster_cell(rpc, settings, cluster_id, max_block_number)
+            .await?
+            .ok_or(Error::ClusterIdNotFound(hex::encode(cluster_id)))?;
+    let metadata = decode_cluster_data(cluster_cell_data.as_bytes())?;
+    Ok(DOBClusterInfo {
+        metadata,
+        block_number,
+        resolution: ClusterResolution::Historical { out_point },
+    })
 }
 
 pub fn decode_cluster_data(cluster_data: &[u8]) -> Result<ClusterDescriptionField, Error> {
@@ -188,28 +481,8 @@ pub fn decode_cluster_data(cluster_data: &[u8]) -> Result<ClusterDescriptionFiel
     Ok(dob_metadata)
 }
 
-// search on-chain decoder cell, deployed with type_id feature enabled
-async fn fetch_decoder_binary(
-    rpc: &RpcClient,
-    decoder_search_option: CellQueryOptions,
-) -> Result<Vec<u8>, Error> {
-    let decoder_cell = rpc
-        .get_cells(decoder_search_option.into(), 1, None)
-        .await
-        .map_err(|e| Error::FetchLiveCellsError(e.to_string()))?
-        .objects
-        .first()
-        .cloned()
-        .ok_or(Error::DecoderIdNotFound)?;
-    Ok(decoder_cell
-        .output_data
-        .unwrap_or_default()
-        .as_bytes()
-        .into())
-}
-
 // search on-chain decoder cell, directly by its tx_hash and out_index
-async fn fetch_decoder_binary_directly(
+pub(crate) async fn fetch_decoder_binary_directly(
     rpc: &RpcClient,
     tx_hash: H256,
     out_index: u32,
@@ -238,41 +511,35 @@ pub async fn parse_decoder_path(
             let hash = decoder.hash.as_ref().ok_or(Error::DecoderHashNotFound)?;
             decoder_path.push(format!("code_hash_{}.bin", hex::encode(hash)));
             if !decoder_path.exists() {
-                let onchain_decoder =
-                    settings
-                        .onchain_decoder_deployment
-                        .iter()
-                        .find_map(|deployment| {
-                            if &deployment.code_hash == hash {
-                                Some(fetch_decoder_binary_directly(
-                                    rpc,
-                                    deployment.tx_hash.clone(),
-                                    deployment.out_index,
-                                ))
-                            } else {
-                                None
-                            }
-                        });
-                let Some(decoder_binary) = onchain_decoder else {
-                    return Err(Error::NativeDecoderNotFound);
+                let deployment = settings
+                    .onchain_decoder_deployment
+                    .iter()
+                    .find(|deployment| &deployment.code_hash == hash);
+                let decoder_file_content = match deployment {
+                    Some(deployment) => {
+                        fetch_decoder_binary_directly(
+                            rpc,
+                            deployment.tx_hash.clone(),
+                            deployment.out_index,
+                        )
+                        .await?
+                    }
+                    None => discover_code_hash_decoder(rpc, settings, hash)
+                        .await?
+                        .ok_or(Error::NativeDecoderNotFound)?,
                 };
-                let decoder_file_content = decoder_binary.await?;
                 if ckb_hash::blake2b_256(&decoder_file_content) != hash.0 {
                     return Err(Error::DecoderBinaryHashInvalid(decoder_path));
                 }
-                std::fs::write(decoder_path.clone(), decoder_file_content)
+                write_file_atomically(&decoder_path, &decoder_file_content)
                     .map_err(|_| Error::DecoderBinaryPathInvalid)?;
             }
         }
         DecoderLocationType::TypeId => {
             let hash = decoder.hash.as_ref().ok_or(Error::DecoderHashNotFound)?;
             decoder_path.push(format!("type_id_{}.bin", hex::encode(hash)));
-            if !decoder_path.exists() {
-                let decoder_search_option = build_type_id_search_option(hash.clone().into());
-                let decoder_binary = fetch_decoder_binary(rpc, decoder_search_option).await?;
-                std::fs::write(decoder_path.clone(), decoder_binary)
-                    .map_err(|_| Error::DecoderBinaryPathInvalid)?;
-            }
+            let decoder_search_option = build_type_id_search_option(hash.clone().into());
+            prepare_upgradable_decoder(rpc, settings, decoder_search_option, &decoder_path).await?;
         }
         DecoderLocationType::TypeScript => {
             let script: Script = decoder
@@ -284,12 +551,8 @@ pub async fn parse_decoder_path(
                 "type_script_{}.bin",
                 hex::encode(script.calc_script_hash().raw_data())
             ));
-            if !decoder_path.exists() {
-                let decoder_search_option = build_type_script_search_option(script);
-                let decoder_binary = fetch_decoder_binary(rpc, decoder_search_option).await?;
-                std::fs::write(decoder_path.clone(), decoder_binary)
-                    .map_err(|_| Error::DecoderBinaryPathInvalid)?;
-            }
+            let decoder_search_option = build_type_script_search_option(script);
+            prepare_upgradable_decoder(rpc, settings, decoder_search_option, &decoder_path).await?;
         }
     };
     Ok(decoder_path)
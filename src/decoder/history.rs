@@ -0,0 +1,323 @@
+use ckb_jsonrpc_types::{JsonBytes, OutPoint, Uint32};
+use ckb_sdk::{
+    rpc::ckb_indexer::{CellType, Order, SearchKey, Tx},
+    traits::ValueRangeOption,
+};
+use ckb_types::{packed, prelude::Entity, H256};
+
+use crate::{
+    client::RpcClient,
+    types::{Error, Settings, SporeEvent, SporeEventType, SporeHistoryCursor, SporeHistoryPage},
+};
+
+use super::helpers::{build_batch_search_options, unwrap_transaction_view};
+
+const HISTORY_PAGE_SIZE: u32 = 100;
+// maximum indexer pages scanned backwards for the latest cell output, in case of long histories
+const MAX_SCANNED_PAGES: usize = 10;
+
+// cell appearances of one transaction, which are adjacent in indexer results
+pub struct CellTransaction {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub consumed: bool,
+    pub output_index: Option<u32>,
+}
+
+// search the latest transaction that created a cell under the type script, page by page in
+// reverse chronological order, and return its hash, block number and output index, error is
+// returned if not found before scanning `MAX_SCANNED_PAGES` pages
+async fn fetch_latest_cell_output(
+    rpc: &RpcClient,
+    search_key: SearchKey,
+) -> Result<Option<(H256, u64, u32)>, Error> {
+    let mut cursor = None;
+    for _ in 0..MAX_SCANNED_PAGES {
+        let page = rpc
+            .get_transactions_in_order(search_key.clone(), Order::Desc, HISTORY_PAGE_SIZE, cursor)
+            .await
+            .map_err(|e| Error::FetchTransactionError(e.to_string()))?;
+        let page_size = page.objects.len();
+        for tx in page.objects {
+            let Tx::Ungrouped(tx) = tx else {
+                continue;
+            };
+            if let CellType::Output = tx.io_type {
+                return Ok(Some((
+                    tx.tx_hash,
+                    tx.block_number.value(),
+                    tx.io_index.value(),
+                )));
+            }
+        }
+        if page_size < HISTORY_PAGE_SIZE as usize {
+            return Ok(None);
+        }
+        cursor = Some(page.last_cursor);
+    }
+    let script: packed::Script = search_key.script.into();
+    Err(Error::CellScanLimitReached(hex::encode(
+        script.calc_script_hash().as_slice(),
+    )))
+}
+
+// position in indexer results, which is the cursor of one page and the cells skipped in that page,
+// so that a transaction split across two pages is never listed partially
+#[derive(Default)]
+struct IndexerPosition {
+    indexer_cursor: Option<JsonBytes>,
+    skipped_cells: u32,
+}
+
+// fold cells of one indexer page into transactions, starting from the `skipped_cells`-th cell, and
+// return the index of the first cell of the transaction beyond `limit`, none if all cells folded
+pub fn fold_cells_page(
+    cells: Vec<Tx>,
+    skipped_cells: u32,
+    limit: usize,
+    cell_txs: &mut Vec<CellTransaction>,
+) -> Option<u32> {
+    for (index, tx) in cells.into_iter().enumerate().skip(skipped_cells as usize) {
+        let Tx::Ungrouped(tx) = tx else {
+            continue;
+        };
+        if !cell_txs
+            .last()
+            .is_some_and(|last| last.tx_hash == tx.tx_hash)
+        {
+            if cell_txs.len() == limit {
+                return Some(index as u32);
+            }
+            cell_txs.push(CellTransaction {
+                tx_hash: tx.tx_hash.clone(),
+                block_number: tx.block_number.value(),
+                consumed: false,
+                output_index: None,
+            });
+        }
+        let cell_tx = cell_txs.last_mut().unwrap();
+        match tx.io_type {
+            CellType::Input => cell_tx.consumed = true,
+            CellType::Output => cell_tx.output_index = Some(tx.io_index.value()),
+        }
+    }
+    None
+}
+
+// search at most `limit` transactions under the type script from `position` in chronological order,
+// and return the position to continue from, none if all transactions are searched
+async fn fetch_cell_transactions_page(
+    rpc: &RpcClient,
+    search_key: SearchKey,
+    limit: usize,
+    position: IndexerPosition,
+) -> Result<(Vec<CellTransaction>, Option<IndexerPosition>), Error> {
+    let IndexerPosition {
+        mut indexer_cursor,
+        mut skipped_cells,
+    } = position;
+    let mut cell_txs = Vec::<CellTransaction>::new();
+    loop {
+        let page = rpc
+            .get_transactions(
+                search_key.clone(),
+                HISTORY_PAGE_SIZE,
+                indexer_cursor.clone(),
+            )
+            .await
+            .map_err(|e| Error::FetchTransactionError(e.to_string()))?;
+        let page_size = page.objects.len();
+        if let Some(index) = fold_cells_page(page.objects, skipped_cells, limit, &mut cell_txs) {
+            let next_position = IndexerPosition {
+                indexer_cursor,
+                skipped_cells: index,
+            };
+            return Ok((cell_txs, Some(next_position)));
+        }
+        if page_size < HISTORY_PAGE_SIZE as usize {
+            return Ok((cell_txs, None));
+        }
+        indexer_cursor = Some(page.last_cursor);
+        skipped_cells = 0;
+    }
+}
+
+// spore is minted by transaction creating it only, transferred by one both consuming and creating
+// it, and melted by one consuming it only
+pub fn classify_spore_event(cell_tx: &CellTransaction) -> Option<SporeEventType> {
+    match (cell_tx.consumed, cell_tx.output_index) {
+        (false, Some(_)) => Some(SporeEventType::Mint),
+        (true, Some(_)) => Some(SporeEventType::Transfer),
+        (true, None) => Some(SporeEventType::Melt),
+        (false, None) => None,
+    }
+}
+
+// search at most `limit` ordered transactions that minted, transferred and melted the spore from
+// `cursor`, spore not found is reported only when listing from the beginning
+pub async fn fetch_spore_history(
+    rpc: &RpcClient,
+    settings: &Settings,
+    spore_id: [u8; 32],
+    limit: usize,
+    cursor: Option<SporeHistoryCursor>,
+) -> Result<SporeHistoryPage, Error> {
+    let from_beginning = cursor.is_none();
+    let (script_index, mut position) = match cursor {
+        Some(cursor) => (
+            cursor.script_index,
+            IndexerPosition {
+                indexer_cursor: cursor.indexer_cursor,
+                skipped_cells: cursor.skipped_cells,
+            },
+        ),
+        None => (0, IndexerPosition::default()),
+    };
+    let mut spore_txs = Vec::new();
+    let mut next_cursor = None;
+    let search_options = build_batch_search_options(&spore_id, &settings.available_spores)
+        .into_iter()
+        .enumerate()
+        .skip(script_index);
+    for (script_index, spore_search_option) in search_options {
+        let (cell_txs, next_position) = fetch_cell_transactions_page(
+            rpc,
+            spore_search_option.into(),
+            limit,
+            std::mem::take(&mut position),
+        )
+        .await?;
+        next_cursor = next_position.map(|position| SporeHistoryCursor {
+            script_index,
+            indexer_cursor: position.indexer_cursor,
+            skipped_cells: position.skipped_cells,
+        });
+        spore_txs = cell_txs;
+        // spore is deployed under only one of spore scripts, which is found at the beginning
+        if !spore_txs.is_empty() || !from_beginning {
+            break;
+        }
+    }
+    let mut history = Vec::new();
+    for spore_tx in spore_txs {
+        let Some(event) = classify_spore_event(&spore_tx) else {
+            continue;
+        };
+        let (lock, out_point) = match spore_tx.output_index {
+            Some(index) => {
+                let tx = rpc
+                    .get_transaction(&spore_tx.tx_hash)
+                    .await
+                    .map_err(|e| Error::FetchTransactionError(e.to_string()))?
+                    .map(unwrap_transaction_view)
+                    .transpose()?
+                    .flatten()
+                    .ok_or(Error::FetchTransactionError(format!(
+                        "transaction {} not found",
+                        hex::encode(&spore_tx.tx_hash)
+                    )))?;
+                let lock = tx
+                    .inner
+                    .outputs
+                    .get(index as usize)
+                    .map(|output| output.lock.clone());
+                let out_point = OutPoint {
+                    tx_hash: spore_tx.tx_hash.clone(),
+                    index: Uint32::from(index),
+                };
+                (lock, Some(out_point))
+            }
+            None => (None, None),
+        };
+        let timestamp = rpc
+            .get_header_by_number(spore_tx.block_number)
+            .await?
+            .map(|header| header.inner.timestamp.value())
+            .ok_or(Error::BlockHeaderNotFound(spore_tx.block_number))?;
+        history.push(SporeEvent {
+            event,
+            tx_hash: spore_tx.tx_hash,
+            block_number: spore_tx.block_number,
+            timestamp,
+            lock,
+            out_point,
+        });
+    }
+    if history.is_empty() && from_beginning {
+        return Err(Error::SporeIdNotFound(hex::encode(spore_id)));
+    }
+    Ok(SporeHistoryPage {
+        history,
+        cursor: next_cursor,
+    })
+}
+
+// search the block number of spore mint transaction
+pub async fn fetch_spore_mint_block_number(
+    rpc: &RpcClient,
+    settings: &Settings,
+    spore_id: [u8; 32],
+) -> Result<Option<u64>, Error> {
+    for spore_search_option in build_batch_search_options(&spore_id, &settings.available_spores) {
+        let spore_mint_tx = rpc
+            .get_transactions(spore_search_option.into(), 1, None)
+            .await
+            .map_err(|e| Error::FetchTransactionError(e.to_string()))?
+            .objects
+            .first()
+            .cloned();
+        if let Some(Tx::Ungrouped(mint)) = spore_mint_tx {
+            return Ok(Some(mint.block_number.value()));
+        }
+    }
+    Ok(None)
+}
+
+// search the latest cluster transaction no later than `max_block_number`, and return the cluster
+// cell data it created, along with block number and out point, no matter whether it's consumed
+//
+// note: transactions are searched backwards within the block range, so that only the latest few
+// ones are scanned rather than the entire cluster history
+pub async fn fetch_historical_cluster_cell(
+    rpc: &RpcClient,
+    settings: &Settings,
+    cluster_id: [u8; 32],
+    max_block_number: Option<u64>,
+) -> Result<Option<(JsonBytes, u64, OutPoint)>, Error> {
+    for mut cluster_search_option in
+        build_batch_search_options(&cluster_id, &settings.available_clusters)
+    {
+        if let Some(max_block_number) = max_block_number {
+            cluster_search_option.block_range =
+                Some(ValueRangeOption::new(0, max_block_number.saturating_add(1)));
+        }
+        let Some((tx_hash, block_number, index)) =
+            fetch_latest_cell_output(rpc, cluster_search_option.into()).await?
+        else {
+            continue;
+        };
+        let tx = rpc
+            .get_transaction(&tx_hash)
+            .await
+            .map_err(|e| Error::FetchTransactionError(e.to_string()))?
+            .map(unwrap_transaction_view)
+            .transpose()?
+            .flatten()
+            .ok_or(Error::FetchTransactionError(format!(
+                "transaction {} not found",
+                hex::encode(&tx_hash)
+            )))?;
+        let cluster_cell_data = tx
+            .inner
+            .outputs_data
+            .get(index as usize)
+            .cloned()
+            .unwrap_or_default();
+        let out_point = OutPoint {
+            tx_hash,
+            index: Uint32::from(index),
+        };
+        return Ok(Some((cluster_cell_data, block_number, out_point)));
+    }
+    Ok(None)
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::types::{
+    ClusterTraitIndex, DOBTrait, IndexedSpore, TraitFilter, TraitSearchCursor, TraitSearchHit,
+    TraitSearchPage,
+};
+
+use super::{helpers::write_file_atomically, stats::ClusterTraits, DOBDecoder};
+
+// local trait index of clusters, which is kept in memory and stored in one JSON file per cluster
+pub struct TraitIndex {
+    directory: Option<PathBuf>,
+    clusters: RwLock<BTreeMap<String, ClusterTraitIndex>>,
+}
+
+impl TraitIndex {
+    // load stored cluster indexes from directory, unreadable files are skipped with a warning, and
+    // the clusters are reindexed on the next refresh
+    pub fn load(directory: Option<PathBuf>) -> Self {
+        let mut clusters = BTreeMap::new();
+        let entries = directory
+            .as_ref()
+            .and_then(|directory| std::fs::read_dir(directory).ok());
+        for entry in entries.into_iter().flatten().flatten() {
+            let path = entry.path();
+            if !path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                continue;
+            }
+            let index = std::fs::read(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|content| {
+                    serde_json::from_slice::<ClusterTraitIndex>(&content)
+                        .map_err(|error| error.to_string())
+                });
+            match index {
+                Ok(index) => {
+                    clusters.insert(index.cluster_id.clone(), index);
+                }
+                Err(error) => {
+                    tracing::warn!("skip trait index {}: {error}", path.display());
+                }
+            }
+        }
+        Self {
+            directory,
+            clusters: RwLock::new(clusters),
+        }
+    }
+
+    // replace index of cluster, which is stored in best effort
+    pub fn update(&self, index: ClusterTraitIndex) {
+        if let Some(directory) = &self.directory {
+            write_cluster_index(directory, &index);
+        }
+        self.clusters
+            .write()
+            .unwrap()
+            .insert(index.cluster_id.clone(), index);
+    }
+
+    pub fn indexed_at(&self, cluster_id: &str) -> Option<u64> {
+        let clusters = self.clusters.read().unwrap();
+        clusters.get(cluster_id).map(|index| index.indexed_at)
+    }
+
+    // search spores matching all filters, in all indexed clusters or the specified one, scanning
+    // stops right after the page is filled unless `count_total` is required
+    pub fn search(
+        &self,
+        cluster_id: Option<&str>,
+        filters: &[TraitFilter],
+        limit: usize,
+        cursor: Option<&TraitSearchCursor>,
+        count_total: bool,
+    ) -> TraitSearchPage {
+        let clusters = self.clusters.read().unwrap();
+        // clusters and spores are both sorted by id, so spores before cursor can be skipped
+        // without matching filters
+        let mut remaining = indexed_spores(&clusters, cluster_id)
+            .skip_while(|(index, spore)| match cursor {
+                Some(cursor) => {
+                    (index.cluster_id.as_str(), spore.spore_id.as_str())
+                        <= (cursor.cluster_id.as_str(), cursor.spore_id.as_str())
+                }
+                None => false,
+            })
+            .filter(|(_, spore)| filters.iter().all(|filter| filter_matches(filter, spore)));
+        let spores = remaining
+            .by_ref()
+            .take(limit)
+            .map(|(index, spore)| TraitSearchHit {
+                cluster_id: index.cluster_id.clone(),
+                spore_id: spore.spore_id.clone(),
+                traits: spore.traits.clone(),
+            })
+            .collect::<Vec<_>>();
+        let cursor = match (remaining.next(), spores.last()) {
+            (Some(_), Some(last)) => Some(TraitSearchCursor {
+                cluster_id: last.cluster_id.clone(),
+                spore_id: last.spore_id.clone(),
+            }),
+            _ => None,
+        };
+        let total = count_total.then(|| {
+            indexed_spores(&clusters, cluster_id)
+                .filter(|(_, spore)| filters.iter().all(|filter| filter_matches(filter, spore)))
+                .count()
+        });
+        TraitSearchPage {
+            spores,
+            total,
+            cursor,
+        }
+    }
+}
+
+// spores of all indexed clusters or the specified one, in order of cluster id and spore id
+fn indexed_spores<'a>(
+    clusters: &'a BTreeMap<String, ClusterTraitIndex>,
+    cluster_id: Option<&'a str>,
+) -> impl Iterator<Item = (&'a ClusterTraitIndex, &'a IndexedSpore)> {
+    clusters
+        .values()
+        .filter(move |index| match cluster_id {
+            Some(cluster_id) => index.cluster_id == cluster_id,
+            None => true,
+        })
+        .flat_map(|index| index.spores.iter().map(move |spore| (index, spore)))
+}
+
+// index file is replaced atomically, so that a crash while writing never leaves it truncated
+fn write_cluster_index(directory: &Path, index: &ClusterTraitIndex) {
+    let content = serde_json::to_vec(index).expect("serialize trait index");
+    let path = directory.join(format!("{}.json", index.cluster_id));
+    let result =
+        std::fs::create_dir_all(directory).and_then(|_| write_file_atomically(&path, &content));
+    if let Err(error) = result {
+        tracing::warn!("failed to store trait index {}: {error}", path.display());
+    }
+}
+
+// numeric values are compared in number, e.g. `35` equals to `35.0`
+fn trait_equals(value: &DOBTrait, expected: &serde_json::Value) -> bool {
+    match (value.as_f64(), expected.as_f64()) {
+        (Some(value), Some(expected)) => value == expected,
+        _ => &value.value() == expected,
+    }
+}
+
+fn filter_matches(filter: &TraitFilter, spore: &IndexedSpore) -> bool {
+    let Some(value) = spore.traits.get(&filter.name) else {
+        return false;
+    };
+    if let Some(expected) = &filter.equals {
+        if !trait_equals(value, expected) {
+            return false;
+        }
+    }
+    if filter.min.is_none() && filter.max.is_none() {
+        return true;
+    }
+    let Some(number) = value.as_f64() else {
+        return false;
+    };
+    let above_min = match filter.min {
+        Some(min) => number >= min,
+        None => true,
+    };
+    let below_max = match filter.max {
+        Some(max) => number <= max,
+        None => true,
+    };
+    above_min && below_max
+}
+
+// replace index of cluster with its rendered traits, return the number of indexed spores
+pub fn index_cluster(
+    decoder: &DOBDecoder,
+    cluster_id: [u8; 32],
+    cluster_traits: &ClusterTraits,
+    indexed_at: u64,
+) -> usize {
+    let mut spores = cluster_traits
+        .spores
+        .iter()
+        .map(|(spore_id, outputs)| IndexedSpore {
+            spore_id: hex::encode(spore_id),
+            traits: outputs
+                .iter()
+                .filter_map(|output| Some((output.name.clone(), output.traits.first()?.clone())))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    spores.sort_by(|a, b| a.spore_id.cmp(&b.spore_id));
+    let count = spores.len();
+    decoder.trait_index().update(ClusterTraitIndex {
+        cluster_id: hex::encode(cluster_id),
+        indexed_at,
+        spores,
+    });
+    count
+}
@@ -0,0 +1,565 @@
+use serde_json::Value;
+
+use crate::types::{
+    ClusterDescriptionField, ClusterDiagnostic, ClusterValidationReport, DOBClusterFormat,
+    DOBStageKind, DiagnosticSeverity, StandardDOBOutput,
+};
+
+use super::{helpers::generate_dna, stats::parse_render_traits, DOBDecoder};
+
+// value kinds of DOB/0 reference decoder, which picks trait value out of DNA bytes, legacy `raw`
+// kind reads a number or a hex string by the declared trait type
+const TRAITS_PATTERN_KINDS: [&str; 6] =
+    ["options", "range", "raw", "rawNumber", "rawString", "utf8"];
+// match kinds of DOB/1 reference SVG decoder
+const SVG_PATTERN_KINDS: [&str; 3] = ["options", "range", "raw"];
+// DNA bytes read by `rawNumber` kind, or `raw` kind of number trait, must fit in u64
+const MAX_RAW_NUMBER_LENGTH: u64 = 8;
+// length of sample DNA if the required length isn't known from patterns
+const DEFAULT_SAMPLE_DNA_LENGTH: usize = 32;
+// DNA bytes that patterns are allowed to read at most, which bounds the sample DNA in size
+pub const MAX_DNA_LENGTH: usize = 1024;
+
+// lint result of a pattern for DOB/0 reference decoder
+#[derive(Default)]
+pub struct TraitsPatternLint {
+    pub diagnostics: Vec<ClusterDiagnostic>,
+    pub trait_names: Vec<String>,
+    pub required_dna_length: usize,
+}
+
+impl TraitsPatternLint {
+    fn push(
+        &mut self,
+        severity: DiagnosticSeverity,
+        code: &'static str,
+        location: &str,
+        message: String,
+    ) {
+        self.diagnostics
+            .push(diagnostic(severity, code, location, message));
+    }
+}
+
+fn diagnostic(
+    severity: DiagnosticSeverity,
+    code: &'static str,
+    location: &str,
+    message: String,
+) -> ClusterDiagnostic {
+    ClusterDiagnostic {
+        severity,
+        code,
+        location: location.to_owned(),
+        message,
+    }
+}
+
+// lint pattern of DOB/0 reference decoder, whose items are
+// `[name, type, offset, length, kind, args]`, and DNA bytes read by traits are checked against DNA
+// length if provided
+pub fn lint_traits_pattern(
+    pattern: &Value,
+    location: &str,
+    dna_length: Option<usize>,
+) -> TraitsPatternLint {
+    let mut lint = TraitsPatternLint::default();
+    let Value::Array(items) = pattern else {
+        // legacy decoders accept patterns in their own string format
+        if pattern.is_string() {
+            lint.push(
+                DiagnosticSeverity::Info,
+                "pattern_not_linted",
+                location,
+                "pattern in string format is not linted".to_owned(),
+            );
+        } else {
+            lint.push(
+                DiagnosticSeverity::Error,
+                "pattern_not_array",
+                location,
+                "pattern should be an array of trait items".to_owned(),
+            );
+        }
+        return lint;
+    };
+    if items.is_empty() {
+        lint.push(
+            DiagnosticSeverity::Warning,
+            "pattern_empty",
+            location,
+            "pattern declares no traits".to_owned(),
+        );
+    }
+    for (i, item) in items.iter().enumerate() {
+        lint_traits_item(&mut lint, item, &format!("{location}[{i}]"), dna_length);
+    }
+    lint
+}
+
+fn lint_traits_item(
+    lint: &mut TraitsPatternLint,
+    item: &Value,
+    location: &str,
+    dna_length: Option<usize>,
+) {
+    let Some(item) = item.as_array().filter(|item| item.len() >= 5) else {
+        lint.push(
+            DiagnosticSeverity::Error,
+            "pattern_item_malformed",
+            location,
+            "trait item should be an array of `[name, type, offset, length, kind, args]`"
+                .to_owned(),
+        );
+        return;
+    };
+    let name = match item[0].as_str() {
+        Some(name) if !name.is_empty() => name,
+        _ => {
+            lint.push(
+                DiagnosticSeverity::Error,
+                "pattern_name_invalid",
+                location,
+                "trait name should be a non-empty string".to_owned(),
+            );
+            return;
+        }
+    };
+    if lint.trait_names.iter().any(|trait_name| trait_name == name) {
+        lint.push(
+            DiagnosticSeverity::Warning,
+            "pattern_name_duplicated",
+            location,
+            format!("trait `{name}` is declared more than once"),
+        );
+    } else {
+        lint.trait_names.push(name.to_owned());
+    }
+    let type_ = item[1].as_str().map(str::to_lowercase);
+    if !matches!(type_.as_deref(), Some("string") | Some("number")) {
+        lint.push(
+            DiagnosticSeverity::Error,
+            "pattern_type_unknown",
+            location,
+            format!(
+                "trait `{name}` has type {}, which should be `String` or `Number`",
+                item[1]
+            ),
+        );
+    }
+    let (Some(offset), Some(length)) = (item[2].as_u64(), item[3].as_u64()) else {
+        lint.push(
+            DiagnosticSeverity::Error,
+            "pattern_offset_invalid",
+            location,
+            format!("offset and length of trait `{name}` should be unsigned integers"),
+        );
+        return;
+    };
+    if length == 0 {
+        lint.push(
+            DiagnosticSeverity::Error,
+            "pattern_length_invalid",
+            location,
+            format!("trait `{name}` reads no DNA bytes"),
+        );
+    }
+    let end = usize::try_from(offset.saturating_add(length)).unwrap_or(usize::MAX);
+    if end > MAX_DNA_LENGTH {
+        lint.push(
+            DiagnosticSeverity::Error,
+            "pattern_offset_out_of_range",
+            location,
+            format!(
+                "trait `{name}` reads DNA bytes {offset}..{end}, beyond {MAX_DNA_LENGTH} bytes"
+            ),
+        );
+        return;
+    }
+    lint.required_dna_length = lint.required_dna_length.max(end);
+    if let Some(dna_length) = dna_length.filter(|dna_length| end > *dna_length) {
+        lint.push(
+            DiagnosticSeverity::Error,
+            "pattern_offset_out_of_range",
+            location,
+            format!(
+                "trait `{name}` reads DNA bytes {offset}..{end}, but DNA has {dna_length} bytes"
+            ),
+        );
+    }
+    let args = item.get(5);
+    let expected_type = match item[4].as_str() {
+        Some("options") => {
+            match args.and_then(Value::as_array) {
+                // reference decoder picks option by DNA value modulo option count
+                Some(options) if !options.is_empty() => {
+                    if length < 8 && options.len() as u64 > 256u64.pow(length as u32) {
+                        lint.push(
+                            DiagnosticSeverity::Warning,
+                            "pattern_options_unreachable",
+                            location,
+                            format!(
+                                "{} options of trait `{name}` outnumber values of {length} bytes",
+                                options.len()
+                            ),
+                        );
+                    }
+                }
+                _ => lint.push(
+                    DiagnosticSeverity::Error,
+                    "pattern_args_invalid",
+                    location,
+                    format!("options of trait `{name}` should be a non-empty array"),
+                ),
+            }
+            None
+        }
+        Some("range") => {
+            let range = args
+                .and_then(Value::as_array)
+                .filter(|range| range.len() == 2)
+                .and_then(|range| Some((range[0].as_f64()?, range[1].as_f64()?)));
+            if !matches!(range, Some((min, max)) if min <= max) {
+                lint.push(
+                    DiagnosticSeverity::Error,
+                    "pattern_args_invalid",
+                    location,
+                    format!("range of trait `{name}` should be `[min, max]` and min <= max"),
+                );
+            }
+            Some("number")
+        }
+        Some("raw") if type_.as_deref() != Some("number") => Some("string"),
+        Some("raw") | Some("rawNumber") => {
+            if length > MAX_RAW_NUMBER_LENGTH {
+                lint.push(
+                    DiagnosticSeverity::Error,
+                    "pattern_length_invalid",
+                    location,
+                    format!("raw number of trait `{name}` reads {length} DNA bytes, over 8 bytes"),
+                );
+            }
+            Some("number")
+        }
+        Some("rawString") | Some("utf8") => Some("string"),
+        _ => {
+            lint.push(
+                DiagnosticSeverity::Error,
+                "pattern_kind_unknown",
+                location,
+                format!(
+                    "trait `{name}` has kind {}, which should be one of {}",
+                    item[4],
+                    TRAITS_PATTERN_KINDS.join(", ")
+                ),
+            );
+            None
+        }
+    };
+    if let (Some(type_), Some(expected_type)) = (type_.as_deref(), expected_type) {
+        if matches!(type_, "string" | "number") && type_ != expected_type {
+            lint.push(
+                DiagnosticSeverity::Warning,
+                "pattern_type_mismatch",
+                location,
+                format!("trait `{name}` is declared as {type_}, but outputs {expected_type}"),
+            );
+        }
+    }
+}
+
+// lint pattern of DOB/1 reference SVG decoder, whose items are `[image, kind, trait, match, args]`,
+// traits referenced are checked against names produced by previous decoders if known
+pub fn lint_svg_pattern(
+    pattern: &Value,
+    location: &str,
+    trait_names: &[String],
+) -> Vec<ClusterDiagnostic> {
+    let mut diagnostics = Vec::new();
+    let Value::Array(items) = pattern else {
+        diagnostics.push(diagnostic(
+            DiagnosticSeverity::Error,
+            "pattern_not_array",
+            location,
+            "pattern should be an array of SVG items".to_owned(),
+        ));
+        return diagnostics;
+    };
+    for (i, item) in items.iter().enumerate() {
+        let location = format!("{location}[{i}]");
+        let mut push = |severity, code, message| {
+            diagnostics.push(diagnostic(severity, code, &location, message));
+        };
+        let Some(item) = item.as_array().filter(|item| item.len() >= 5) else {
+            push(
+                DiagnosticSeverity::Error,
+                "pattern_item_malformed",
+                "SVG item should be an array of `[image, kind, trait, match, args]`".to_owned(),
+            );
+            continue;
+        };
+        if !item[0].is_string() {
+            push(
+                DiagnosticSeverity::Error,
+                "pattern_name_invalid",
+                "image name should be a string".to_owned(),
+            );
+        }
+        match item[1].as_str() {
+            Some("attributes") => {
+                if !item[4].is_string() {
+                    push(
+                        DiagnosticSeverity::Error,
+                        "pattern_args_invalid",
+                        "attributes of image should be a string".to_owned(),
+                    );
+                }
+            }
+            Some("elements") => {
+                match item[2].as_str() {
+                    Some(trait_name)
+                        if trait_names.is_empty()
+                            || trait_names.iter().any(|name| name == trait_name) => {}
+                    Some(trait_name) => push(
+                        DiagnosticSeverity::Warning,
+                        "pattern_trait_unknown",
+                        format!("trait `{trait_name}` isn't produced by previous decoders"),
+                    ),
+                    None => push(
+                        DiagnosticSeverity::Error,
+                        "pattern_name_invalid",
+                        "trait name should be a string".to_owned(),
+                    ),
+                }
+                match item[3].as_str() {
+                    Some("raw") => {}
+                    Some(kind) if SVG_PATTERN_KINDS.contains(&kind) => {
+                        let cases_valid = item[4].as_array().is_some_and(|cases| {
+                            cases
+                                .iter()
+                                .all(|case| case.as_array().is_some_and(|case| case.len() == 2))
+                        });
+                        if !cases_valid {
+                            push(
+                                DiagnosticSeverity::Error,
+                                "pattern_args_invalid",
+                                format!("`{kind}` cases should be `[matcher, element]` pairs"),
+                            );
+                        }
+                    }
+                    _ => push(
+                        DiagnosticSeverity::Error,
+                        "pattern_kind_unknown",
+                        format!(
+                            "match kind {} should be one of {}",
+                            item[3],
+                            SVG_PATTERN_KINDS.join(", ")
+                        ),
+                    ),
+                }
+            }
+            _ => push(
+                DiagnosticSeverity::Error,
+                "pattern_kind_unknown",
+                format!("item kind {} should be `attributes` or `elements`", item[1]),
+            ),
+        }
+    }
+    diagnostics
+}
+
+// lint patterns of all decoders in cluster, and return the DNA length required by traits
+pub fn lint_patterns(
+    dob: &DOBClusterFormat,
+    version: u8,
+    dna_length: Option<usize>,
+    diagnostics: &mut Vec<ClusterDiagnostic>,
+) -> usize {
+    let mut trait_names = Vec::new();
+    let mut required_dna_length = 0;
+    let mut lint_traits = |pattern: &Value, location: String, trait_names: &mut Vec<String>| {
+        let lint = lint_traits_pattern(pattern, &location, dna_length);
+        trait_names.extend(lint.trait_names);
+        required_dna_length = required_dna_length.max(lint.required_dna_length);
+        lint.diagnostics
+    };
+    match version {
+        0 => {
+            if let Some(dob0) = &dob.dob_ver_0 {
+                let lint = lint_traits(&dob0.pattern, "dob.pattern".to_owned(), &mut trait_names);
+                diagnostics.extend(lint);
+            }
+        }
+        // the first decoder of DOB/1 is expected to be the traits decoder, and the rest are SVG
+        // decoders which render the traits
+        1 => {
+            let decoders = dob.dob_ver_1.iter().flat_map(|dob1| dob1.decoders.iter());
+            for (i, value) in decoders.enumerate() {
+                let location = format!("dob.decoders[{i}].pattern");
+                if i == 0 {
+                    diagnostics.extend(lint_traits(&value.pattern, location, &mut trait_names));
+                } else {
+                    diagnostics.extend(lint_svg_pattern(&value.pattern, &location, &trait_names));
+                }
+            }
+        }
+        2 => {
+            let stages = dob.dob_ver_2.iter().flat_map(|dob2| dob2.stages.iter());
+            for (i, stage) in stages.enumerate() {
+                let location = format!("dob.stages[{i}].pattern");
+                match stage.kind {
+                    DOBStageKind::Traits => {
+                        diagnostics.extend(lint_traits(&stage.pattern, location, &mut trait_names));
+                    }
+                    DOBStageKind::Render => {
+                        let lint = lint_svg_pattern(&stage.pattern, &location, &trait_names);
+                        diagnostics.extend(lint);
+                    }
+                }
+            }
+        }
+        _ => diagnostics.push(diagnostic(
+            DiagnosticSeverity::Info,
+            "pattern_not_linted",
+            "dob",
+            format!("patterns of DOB/{version} are not linted"),
+        )),
+    }
+    required_dna_length
+}
+
+fn decoder_location(version: u8, index: usize) -> String {
+    match version {
+        0 => "dob.decoder".to_owned(),
+        1 => format!("dob.decoders[{index}].decoder"),
+        2 => format!("dob.stages[{index}].decoder"),
+        _ => "dob".to_owned(),
+    }
+}
+
+// DNA length in bytes required by traits patterns of cluster, which falls back to a default length
+// if patterns aren't linted
+pub fn sample_dna_length(dob_metadata: &ClusterDescriptionField) -> usize {
+    let mut diagnostics = Vec::new();
+    match lint_patterns(
+        &dob_metadata.dob,
+        dob_metadata.dob_version(),
+        None,
+        &mut diagnostics,
+    ) {
+        0 => DEFAULT_SAMPLE_DNA_LENGTH,
+        length => length,
+    }
+}
+
+// validate cluster description in JSON format, lint patterns against the reference decoders,
+// check decoders are resolvable, and render a sample DNA if no errors found
+pub async fn validate_cluster(
+    decoder: &DOBDecoder,
+    description: &str,
+    sample_dna: Option<&str>,
+) -> ClusterValidationReport {
+    let mut report = ClusterValidationReport::default();
+    let diagnostics = &mut report.diagnostics;
+    let dob_metadata: ClusterDescriptionField = match serde_json::from_str(description) {
+        Ok(dob_metadata) => dob_metadata,
+        Err(error) => {
+            diagnostics.push(diagnostic(
+                DiagnosticSeverity::Error,
+                "description_invalid",
+                "",
+                error.to_string(),
+            ));
+            return report;
+        }
+    };
+    let version = dob_metadata.dob_version();
+    report.dob_version = Some(version);
+    let decoders = match decoder
+        .protocol(version)
+        .and_then(|protocol| protocol.parse_metadata(&dob_metadata.dob))
+    {
+        Ok(decoders) => decoders,
+        Err(error) => {
+            diagnostics.push(diagnostic(
+                DiagnosticSeverity::Error,
+                "metadata_invalid",
+                "dob",
+                error.to_string(),
+            ));
+            return report;
+        }
+    };
+    let sample_dna = sample_dna.map(|dna| dna.trim_start_matches("0x").to_owned());
+    let dna_length = match sample_dna.as_ref().map(hex::decode) {
+        Some(Ok(dna)) => Some(dna.len()),
+        Some(Err(_)) => {
+            diagnostics.push(diagnostic(
+                DiagnosticSeverity::Error,
+                "sample_dna_invalid",
+                "",
+                "sample DNA is not in hex format".to_owned(),
+            ));
+            return report;
+        }
+        None => None,
+    };
+
+    let required_dna_length = lint_patterns(&dob_metadata.dob, version, dna_length, diagnostics);
+    if required_dna_length > 0 {
+        report.required_dna_length = Some(required_dna_length);
+    }
+    for (i, decoder_format) in decoders.iter().enumerate() {
+        if let Err(error) = decoder.resolve_decoder(decoder_format).await {
+            report.diagnostics.push(diagnostic(
+                DiagnosticSeverity::Error,
+                "decoder_unresolved",
+                &decoder_location(version, i),
+                error.to_string(),
+            ));
+        }
+    }
+    let has_error = |report: &ClusterValidationReport| {
+        report
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == DiagnosticSeverity::Error)
+    };
+    if has_error(&report) {
+        return report;
+    }
+
+    // sample DNA is derived from description, so that validating the same cluster always renders
+    // the same sample
+    let sample_dna = sample_dna.unwrap_or_else(|| {
+        let length = report
+            .required_dna_length
+            .unwrap_or(DEFAULT_SAMPLE_DNA_LENGTH);
+        generate_dna(description.as_bytes(), length)
+    });
+    match decoder.decode_dna(&sample_dna, dob_metadata).await {
+        Ok(render_output) => {
+            // traits whose values don't meet their types are still rendered unless the server is
+            // strict about them, so that they're reported as warnings
+            let outputs = parse_render_traits(&render_output).unwrap_or_default();
+            for issue in StandardDOBOutput::trait_issues(&outputs) {
+                report.diagnostics.push(diagnostic(
+                    DiagnosticSeverity::Warning,
+                    "sample_trait_invalid",
+                    "",
+                    format!("trait `{}` is invalid: {}", issue.name, issue.reason),
+                ));
+            }
+            report.sample_render = Some(render_output);
+        }
+        Err(error) => report.diagnostics.push(diagnostic(
+            DiagnosticSeverity::Error,
+            "sample_render_failed",
+            "",
+            error.to_string(),
+        )),
+    }
+    report.sample_dna = Some(sample_dna);
+    report.valid = !has_error(&report);
+    report
+}
@@ -1,27 +1,98 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use ckb_jsonrpc_types::{OutPoint, Transaction};
+use ckb_types::{packed, prelude::Unpack, H256};
 use serde_json::Value;
 
+#[cfg(feature = "standalone_server")]
+use crate::types::{DecoderDryRunReport, DecoderSubstitute};
 use crate::{
     client::RpcClient,
     types::{
-        ClusterDescriptionField, DOBClusterFormatV0, DOBClusterFormatV1, Dob, Error, Settings,
-        StandardDOBOutput,
+        ClusterDataSource, ClusterDescriptionField, ClusterSporesCursor, ClusterSporesPage,
+        ClusterStats, ClusterValidationReport, DOBClusterInfo, DOBDecoderFormat, DOBDecoderStage,
+        DOBSporeFormat, DecoderLocationType, Error, PatternAnalysis, RenderedDOB, Settings,
+        SporeCellInfo, SporeHistoryCursor, SporeHistoryPage, SporeRarity, SporeResolutionMode,
+        TransactionDOB,
     },
 };
 
-pub(crate) mod helpers;
+pub mod binary;
+pub mod cluster;
+pub mod content;
+#[cfg(feature = "standalone_server")]
+pub mod dryrun;
+pub mod helpers;
+pub mod history;
+pub mod index;
+pub mod lint;
+pub mod pattern;
+pub mod protocol;
+pub mod stats;
+pub mod transaction;
 use helpers::*;
+use index::TraitIndex;
+use protocol::{Dob0Protocol, Dob1Protocol, Dob2Protocol, DobProtocol};
+
+// ingredients of DOB rendering fetched from chain, unfinalized ones are not supposed to be cached
+pub struct DOBIngredients {
+    pub spore: DOBSporeFormat,
+    pub cluster: DOBClusterInfo,
+    pub finalized: bool,
+}
 
 pub struct DOBDecoder {
     rpc: RpcClient,
     settings: Settings,
+    protocols: BTreeMap<u8, Box<dyn DobProtocol>>,
+    trait_index: TraitIndex,
 }
 
 impl DOBDecoder {
     pub fn new(settings: Settings) -> Self {
+        let (indexer_rpc, indexer_rpc_options) = match &settings.ckb_indexer_rpc {
+            Some(indexer_rpc) => (indexer_rpc, settings.ckb_indexer_rpc_options.as_ref()),
+            None => (&settings.ckb_rpc, settings.ckb_rpc_options.as_ref()),
+        };
+        let mut rpc = RpcClient::new_with_options(
+            &settings.ckb_rpc,
+            settings.ckb_rpc_options.as_ref(),
+            indexer_rpc,
+            indexer_rpc_options,
+        )
+        .expect("build rpc client, please check rpc options in settings");
+        if let Some(rate_limit) = &settings.ckb_rpc_rate_limit {
+            rpc = rpc.with_rate_limit(rate_limit);
+        }
+        if let Some(cache_settings) = &settings.ckb_rpc_cache {
+            rpc = rpc.with_cache(cache_settings);
+        }
+        rpc = rpc.with_min_confirmations(settings.min_confirmations);
+        let trait_index = TraitIndex::load(settings.trait_index_directory.clone());
         Self {
-            rpc: RpcClient::new(&settings.ckb_rpc, &settings.ckb_rpc),
+            rpc,
             settings,
+            protocols: BTreeMap::new(),
+            trait_index,
         }
+        .with_protocol(Dob0Protocol)
+        .with_protocol(Dob1Protocol)
+        .with_protocol(Dob2Protocol)
+    }
+
+    // register DOB protocol version, which replaces the registered one under the same version
+    pub fn with_protocol<P: DobProtocol + 'static>(mut self, protocol: P) -> Self {
+        self.protocols
+            .insert(protocol.version(), Box::new(protocol));
+        self
+    }
+
+    pub fn protocol(&self, version: u8) -> Result<&dyn DobProtocol, Error> {
+        self.protocols
+            .get(&version)
+            .map(AsRef::as_ref)
+            .ok_or(Error::DOBVersionNumberUndefined)
     }
 
     pub fn protocol_versions(&self) -> Vec<String> {
@@ -32,99 +103,318 @@ impl DOBDecoder {
         &self.settings
     }
 
+    // directory of DOB/1 stage outputs, which is disabled in dry-run since substitute binaries are
+    // temporary
+    pub fn stages_cache_directory(&self) -> Option<&Path> {
+        #[cfg(feature = "standalone_server")]
+        if dryrun::is_dry_run() {
+            return None;
+        }
+        self.settings.decoder_stages_cache_directory.as_deref()
+    }
+
+    // data hash of resolved decoder binary, which is known by `code_hash` location or from the
+    // verification record of upgradable decoder, so that binary is hashed only if neither exists,
+    // e.g. substitute binary in dry-run
+    pub fn decoder_binary_hash(
+        &self,
+        decoder: &DOBDecoderFormat,
+        decoder_path: &Path,
+    ) -> Result<H256, Error> {
+        #[cfg(feature = "standalone_server")]
+        let substituted = dryrun::substitute_decoder_path(decoder).is_some();
+        #[cfg(not(feature = "standalone_server"))]
+        let substituted = false;
+        if !substituted {
+            let known_hash = match decoder.location {
+                DecoderLocationType::CodeHash => decoder.hash.clone(),
+                DecoderLocationType::TypeId | DecoderLocationType::TypeScript => {
+                    binary::recorded_data_hash(decoder_path)
+                }
+            };
+            if let Some(hash) = known_hash {
+                return Ok(hash);
+            }
+        }
+        let binary = std::fs::read(decoder_path).map_err(|_| Error::DecoderBinaryPathInvalid)?;
+        Ok(H256(ckb_hash::blake2b_256(binary)))
+    }
+
+    // remove expired DOB/1 stage outputs in cache, and return the number of removed ones
+    pub fn clean_stages_cache(&self) -> usize {
+        match &self.settings.decoder_stages_cache_directory {
+            Some(directory) => protocol::clean_stage_cache(
+                directory,
+                self.settings.decoder_stages_cache_expiration_sec,
+            ),
+            None => 0,
+        }
+    }
+
+    pub fn trait_index(&self) -> &TraitIndex {
+        &self.trait_index
+    }
+
+    pub fn rpc(&self) -> &RpcClient {
+        &self.rpc
+    }
+
+    // search on-chain state of spore cell, which is mutable and never cached
+    pub async fn fetch_spore_cell_info(
+        &self,
+        spore_id: [u8; 32],
+    ) -> Result<Option<SporeCellInfo>, Error> {
+        fetch_spore_cell_info(&self.rpc, &self.settings, spore_id).await
+    }
+
+    // search ordered mint, transfer and melt steps of spore page by page
+    pub async fn fetch_spore_history(
+        &self,
+        spore_id: [u8; 32],
+        limit: usize,
+        cursor: Option<SporeHistoryCursor>,
+    ) -> Result<SporeHistoryPage, Error> {
+        history::fetch_spore_history(&self.rpc, &self.settings, spore_id, limit, cursor).await
+    }
+
+    // search on-chain cluster cell and return dob metadata in it
+    pub async fn fetch_dob_cluster(&self, cluster_id: [u8; 32]) -> Result<DOBClusterInfo, Error> {
+        fetch_dob_cluster(&self.rpc, &self.settings, cluster_id).await
+    }
+
+    // scan one round of live spores belonging to the cluster
+    pub async fn fetch_cluster_spores(
+        &self,
+        cluster_id: [u8; 32],
+        limit: u32,
+        cursor: Option<ClusterSporesCursor>,
+    ) -> Result<ClusterSporesPage, Error> {
+        cluster::fetch_cluster_spores(&self.rpc, &self.settings, cluster_id, limit, cursor).await
+    }
+
+    // render spore data committed at the block ahead of time, e.g. spore in a newly committed
+    // block, and return the render along with its ingredients so that caller is able to cache it
+    pub async fn prerender_spore(
+        &self,
+        spore_data: &[u8],
+        block_number: u64,
+    ) -> Result<(String, DOBIngredients), Error> {
+        let mut spore = decode_spore_data(spore_data)?;
+        spore.block_number = Some(block_number);
+        let cluster = fetch_dob_cluster(&self.rpc, &self.settings, spore.cluster_id).await?;
+        let render_output = self.decode_spore(&spore, cluster.metadata.clone()).await?;
+        let finalized = self.rpc.is_final(block_number) && self.rpc.is_final(cluster.block_number);
+        Ok((
+            render_output,
+            DOBIngredients {
+                spore,
+                cluster,
+                finalized,
+            },
+        ))
+    }
+
+    // download decoders referenced by cluster data into cache directory in advance
+    pub async fn prepare_cluster_decoders(&self, cluster_data: &[u8]) -> Result<(), Error> {
+        let dob_metadata = decode_cluster_data(cluster_data)?;
+        let decoders = self
+            .protocol(dob_metadata.dob_version())?
+            .parse_metadata(&dob_metadata.dob)?;
+        for decoder in decoders {
+            self.resolve_decoder(&decoder).await?;
+        }
+        Ok(())
+    }
+
+    // drop cached decoder binaries and cells under the type script, return removed binary files,
+    // type scripts that don't locate any cached decoder are ignored
+    pub fn invalidate_decoder(&self, type_script: &ckb_jsonrpc_types::Script) -> Vec<PathBuf> {
+        if cached_decoder_paths(&self.settings, type_script).is_empty() {
+            return Vec::new();
+        }
+        let script: packed::Script = type_script.clone().into();
+        self.rpc
+            .invalidate_cell_by_type(&script.calc_script_hash().unpack());
+        invalidate_decoder_cache(&self.settings, type_script)
+    }
+
+    #[allow(dead_code)]
     pub async fn fetch_decode_ingredients(
         &self,
         spore_id: [u8; 32],
     ) -> Result<(Value, String, ClusterDescriptionField), Error> {
+        let ingredients = self.fetch_dob_ingredients(spore_id).await?;
+        Ok((
+            ingredients.spore.content,
+            ingredients.spore.dna,
+            ingredients.cluster.metadata,
+        ))
+    }
+
+    // fetch spore and cluster from chain, and check whether both of them are final
+    pub async fn fetch_dob_ingredients(&self, spore_id: [u8; 32]) -> Result<DOBIngredients, Error> {
         let spore = fetch_dob_content(&self.rpc, &self.settings, spore_id).await?;
-        let dob_metadata = fetch_dob_metadata(&self.rpc, &self.settings, spore.cluster_id).await?;
-        Ok((spore.content, spore.dna, dob_metadata))
+        let cluster = match self.settings.cluster_data_source {
+            ClusterDataSource::Latest => {
+                fetch_dob_cluster(&self.rpc, &self.settings, spore.cluster_id).await?
+            }
+            ClusterDataSource::SporeMint => {
+                let mint_block_number = match self.settings.spore_resolution {
+                    SporeResolutionMode::MintTransaction => spore.block_number,
+                    SporeResolutionMode::LiveCellFirst => {
+                        history::fetch_spore_mint_block_number(&self.rpc, &self.settings, spore_id)
+                            .await?
+                    }
+                };
+                // fall back to the latest cluster data if no cluster transaction is found up to the
+                // mint height
+                match fetch_historical_dob_cluster(
+                    &self.rpc,
+                    &self.settings,
+                    spore.cluster_id,
+                    mint_block_number,
+                )
+                .await
+                {
+                    Err(Error::ClusterIdNotFound(_)) => {
+                        fetch_dob_cluster(&self.rpc, &self.settings, spore.cluster_id).await?
+                    }
+                    result => result?,
+                }
+            }
+        };
+        if self.settings.min_confirmations > 0 {
+            self.rpc.get_tip_block_number().await?;
+        }
+        let finalized = self.rpc.is_final(spore.block_number.unwrap_or_default())
+            && self.rpc.is_final(cluster.block_number);
+        Ok(DOBIngredients {
+            spore,
+            cluster,
+            finalized,
+        })
+    }
+
+    // decode DNA of spore, whose declared DOB version must be consistent with cluster
+    pub async fn decode_spore(
+        &self,
+        spore: &DOBSporeFormat,
+        dob_metadata: ClusterDescriptionField,
+    ) -> Result<String, Error> {
+        // every render path goes through here, e.g. pre-rendering, cluster listing and statistics,
+        // so that spores of unconfigured protocol versions are never rendered
+        check_protocol_version(&self.settings, &spore.content_type)?;
+        check_dob_version(&spore.content_type, &dob_metadata)?;
+        self.protocol(dob_metadata.dob_version())?
+            .validate_content(spore)?;
+        self.decode_dna(&spore.dna, dob_metadata).await
     }
 
-    // decode DNA under target spore_id
+    // decode DNA under the DOB protocol version declared by cluster, whose metadata is checked by
+    // the protocol while decoding
     pub async fn decode_dna(
         &self,
         dna: &str,
         dob_metadata: ClusterDescriptionField,
     ) -> Result<String, Error> {
-        let dob = dob_metadata.unbox_dob()?;
-        match dob {
-            Dob::V0(dob0) => self.decode_dob0_dna(dna, dob0).await,
-            Dob::V1(dob1) => self.decode_dob1_dna(dna, dob1).await,
+        let protocol = self.protocol(dob_metadata.dob_version())?;
+        let output = protocol.decode(self, dna, &dob_metadata.dob).await?;
+        protocol.shape_output(output)
+    }
+
+    // decode DNA of spore and return output of every decoder stage, which is only supported by
+    // protocols whose decoders run in chain, e.g. DOB/1 and DOB/2
+    pub async fn decode_spore_stages(
+        &self,
+        spore: &DOBSporeFormat,
+        dob_metadata: ClusterDescriptionField,
+    ) -> Result<Vec<DOBDecoderStage>, Error> {
+        check_protocol_version(&self.settings, &spore.content_type)?;
+        check_dob_version(&spore.content_type, &dob_metadata)?;
+        let protocol = self.protocol(dob_metadata.dob_version())?;
+        protocol.validate_content(spore)?;
+        protocol
+            .decode_stages(self, &spore.dna, &dob_metadata.dob)
+            .await
+    }
+
+    // stored trait distributions and spore rarities of cluster, which are computed in background
+    pub fn fetch_cluster_stats(&self, cluster_id: [u8; 32]) -> Result<ClusterStats, Error> {
+        stats::fetch_cluster_stats(self, cluster_id)
+    }
+
+    // rarity of spore in the stored statistics of its cluster
+    pub async fn fetch_spore_rarity(&self, spore_id: [u8; 32]) -> Result<SporeRarity, Error> {
+        let ingredients = self.fetch_dob_ingredients(spore_id).await?;
+        stats::fetch_spore_rarity(self, spore_id, ingredients.spore.cluster_id)
+    }
+
+    // render all live spores in cluster once, and refresh its trait index and statistics from the
+    // same renders, return the number of rendered spores
+    pub async fn refresh_cluster(
+        &self,
+        cluster_id: [u8; 32],
+        update_index: bool,
+        update_stats: bool,
+    ) -> Result<usize, Error> {
+        let cluster_traits = stats::render_cluster_traits(self, cluster_id).await?;
+        let rendered_at = binary::now()?;
+        if update_index {
+            index::index_cluster(self, cluster_id, &cluster_traits, rendered_at);
+        }
+        if update_stats {
+            stats::store_cluster_stats(self, cluster_id, &cluster_traits, rendered_at)?;
         }
+        Ok(cluster_traits.spores.len())
     }
 
-    // decode specificly for objects under DOB/0 protocol
-    async fn decode_dob0_dna(&self, dna: &str, dob0: &DOBClusterFormatV0) -> Result<String, Error> {
-        let decoder_path = parse_decoder_path(&self.rpc, &dob0.decoder, &self.settings).await?;
-        let pattern = match &dob0.pattern {
-            Value::String(string) => string.to_owned(),
-            pattern => pattern.to_string(),
-        };
-        let raw_render_result = {
-            let (exit_code, outputs) = crate::vm::execute_riscv_binary(
-                &decoder_path.to_string_lossy(),
-                vec![dna.to_owned().into(), pattern.into()],
-            )
-            .map_err(|e| Error::DecoderExecutionError(e.to_string()))?;
-            #[cfg(feature = "render_debug")]
-            {
-                println!("\n-------- DOB/0 DECODE RESULT ({exit_code}) ---------");
-                outputs.iter().for_each(|output| println!("{output}"));
-                println!("-------- DOB/0 DECODE RESULT END ---------");
-            }
-            if exit_code != 0 {
-                return Err(Error::DecoderExecutionInternalError(exit_code));
-            }
-            outputs.first().ok_or(Error::DecoderOutputEmpty)?.clone()
-        };
-        Ok(raw_render_result)
-    }
-
-    // decode specificly for objects under DOB/1 protocol
-    async fn decode_dob1_dna(&self, dna: &str, dob1: &DOBClusterFormatV1) -> Result<String, Error> {
-        let mut output = Option::<Vec<StandardDOBOutput>>::None;
-        for (i, value) in dob1.decoders.iter().enumerate() {
-            let decoder_path =
-                parse_decoder_path(&self.rpc, &value.decoder, &self.settings).await?;
-            let pattern = match &value.pattern {
-                Value::String(string) => string.to_owned(),
-                pattern => pattern.to_string(),
-            };
-            let raw_render_result = {
-                let args = if let Some(previous_output) = &output {
-                    vec![
-                        dna.to_owned().into(),
-                        pattern.into(),
-                        serde_json::to_string(previous_output)
-                            .expect("parsed_dna")
-                            .into(),
-                    ]
-                } else {
-                    vec![dna.to_owned().into(), pattern.into()]
-                };
-                let (exit_code, outputs) =
-                    crate::vm::execute_riscv_binary(&decoder_path.to_string_lossy(), args)
-                        .map_err(|e| Error::DecoderExecutionError(e.to_string()))?;
-                #[cfg(feature = "render_debug")]
-                {
-                    println!("\n-------- DOB/1 DECODE RESULT ({i} => {exit_code}) ---------");
-                    outputs.iter().for_each(|output| println!("{output}"));
-                    println!("-------- DOB/1 DECODE RESULT END ---------");
-                }
-                if exit_code != 0 {
-                    return Err(Error::DecoderExecutionInternalError(exit_code));
-                }
-                outputs.first().ok_or(Error::DecoderOutputEmpty)?.clone()
-            };
-            output = Some(
-                serde_json::from_str(&raw_render_result)
-                    .map_err(|_| Error::DecoderOutputInvalid)?,
-            );
+    // validate cluster description in JSON format and render a sample DNA under it, which is
+    // derived from the description if not provided
+    pub async fn validate_cluster(
+        &self,
+        description: &str,
+        sample_dna: Option<&str>,
+    ) -> ClusterValidationReport {
+        lint::validate_cluster(self, description, sample_dna).await
+    }
+
+    // map DNA bytes to traits under the traits pattern of on-chain cluster
+    pub async fn analyze_cluster_pattern(
+        &self,
+        cluster_id: [u8; 32],
+        dna: Option<&str>,
+    ) -> Result<PatternAnalysis, Error> {
+        let dob_metadata = self.fetch_dob_cluster(cluster_id).await?.metadata;
+        pattern::analyze_traits_pattern(pattern::traits_pattern(&dob_metadata)?, dna)
+    }
+
+    // render spore cells in transaction outputs, which is not required to be submitted
+    pub async fn decode_transaction(&self, tx: &Transaction) -> Vec<TransactionDOB> {
+        transaction::decode_transaction(self, tx).await
+    }
+
+    // render spore cell located at the out point, whether it's live or not
+    pub async fn decode_by_outpoint(&self, out_point: &OutPoint) -> Result<RenderedDOB, Error> {
+        transaction::decode_by_outpoint(self, out_point).await
+    }
+
+    // render spore by deployed decoders and by local substitute binaries, and compare the outputs
+    #[cfg(feature = "standalone_server")]
+    pub async fn dry_run_spore(
+        &self,
+        spore_id: [u8; 32],
+        substitutes: Vec<DecoderSubstitute>,
+    ) -> Result<DecoderDryRunReport, Error> {
+        dryrun::dry_run_spore(self, spore_id, substitutes).await
+    }
+
+    // resolve decoder binary into the local path, which is downloaded from chain if needed, or
+    // the substitute binary if running in dry-run
+    pub async fn resolve_decoder(&self, decoder: &DOBDecoderFormat) -> Result<PathBuf, Error> {
+        #[cfg(feature = "standalone_server")]
+        if let Some(binary_path) = dryrun::substitute_decoder_path(decoder) {
+            return Ok(binary_path);
         }
-        let Some(output) = output else {
-            return Err(Error::DecoderChainIsEmpty);
-        };
-        Ok(serde_json::to_string(&output).unwrap())
+        parse_decoder_path(&self.rpc, decoder, &self.settings).await
     }
 }
@@ -0,0 +1,206 @@
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+use crate::types::{
+    ClusterDescriptionField, DNAByteUsage, DOBStageKind, Error, PatternAnalysis, TraitByteMap,
+    TraitValueSet,
+};
+
+use super::stats::is_hint_trait;
+
+// DNA bytes that one pattern is allowed to read at most, which bounds the byte map in size
+const MAX_ANALYZED_DNA_LENGTH: usize = 1024;
+
+// number of values taken by DNA bytes of length, none if it overflows u128
+fn dna_bytes_space(length: usize) -> Option<u128> {
+    256u128.checked_pow(length as u32)
+}
+
+// bits of the number of values, which are counted from length of DNA bytes if the number overflows
+fn space_bits(space: Option<u128>, length: usize) -> f64 {
+    match space {
+        Some(space) => (space as f64).log2(),
+        None => length as f64 * 8.0,
+    }
+}
+
+// traits pattern of cluster, which is the pattern of the first decoder of DOB/1, or the first
+// traits stage of DOB/2
+pub fn traits_pattern(dob_metadata: &ClusterDescriptionField) -> Result<&Value, Error> {
+    let dob = &dob_metadata.dob;
+    let pattern = match dob_metadata.dob_version() {
+        0 => dob.dob_ver_0.as_ref().map(|dob0| &dob0.pattern),
+        1 => dob
+            .dob_ver_1
+            .as_ref()
+            .and_then(|dob1| dob1.decoders.first())
+            .map(|decoder| &decoder.pattern),
+        2 => dob
+            .dob_ver_2
+            .as_ref()
+            .and_then(|dob2| {
+                dob2.stages
+                    .iter()
+                    .find(|stage| stage.kind == DOBStageKind::Traits)
+            })
+            .map(|stage| &stage.pattern),
+        version => {
+            return Err(Error::PatternAnalysisUnsupported(format!(
+                "DOB/{version} has no known traits pattern"
+            )))
+        }
+    };
+    pattern.ok_or(Error::ClusterDataUncompatible)
+}
+
+fn parse_trait_item(item: &Value, dna: Option<&[u8]>) -> Result<TraitByteMap, Error> {
+    let invalid = |reason: &str| Error::PatternAnalysisUnsupported(format!("{item}: {reason}"));
+    let item = item
+        .as_array()
+        .filter(|item| item.len() >= 5)
+        .ok_or_else(|| invalid("not in `[name, type, offset, length, kind, args]` format"))?;
+    let name = item[0]
+        .as_str()
+        .ok_or_else(|| invalid("name is not a string"))?;
+    let type_ = item[1]
+        .as_str()
+        .ok_or_else(|| invalid("type is not a string"))?;
+    let (Some(offset), Some(length)) = (item[2].as_u64(), item[3].as_u64()) else {
+        return Err(invalid("offset or length is not an unsigned integer"));
+    };
+    let (offset, length) = (offset as usize, length as usize);
+    if offset.saturating_add(length) > MAX_ANALYZED_DNA_LENGTH {
+        return Err(invalid(&format!(
+            "reads beyond {MAX_ANALYZED_DNA_LENGTH} DNA bytes"
+        )));
+    }
+    let bytes_space = dna_bytes_space(length);
+    // countable values are capped by values of DNA bytes, which may overflow though
+    let capped = |count: u128| Some(bytes_space.map_or(count, |space| count.min(space)));
+    let (values, cardinality) = match item[4].as_str() {
+        Some("options") => {
+            let options = item
+                .get(5)
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("options are not an array"))?;
+            let values = TraitValueSet::Options {
+                values: options.clone(),
+            };
+            (values, capped(options.len() as u128))
+        }
+        Some("range") => {
+            let range = item
+                .get(5)
+                .and_then(Value::as_array)
+                .filter(|range| range.len() == 2)
+                .ok_or_else(|| invalid("range is not in `[min, max]` format"))?;
+            // only integer ranges are countable, or all values of DNA bytes are taken
+            let count = match (range[0].as_i64(), range[1].as_i64()) {
+                (Some(min), Some(max)) if min <= max => {
+                    capped((max as i128 - min as i128) as u128 + 1)
+                }
+                _ => bytes_space,
+            };
+            let values = TraitValueSet::Range {
+                min: range[0].clone(),
+                max: range[1].clone(),
+            };
+            (values, count)
+        }
+        Some("raw") => (TraitValueSet::Raw, bytes_space),
+        Some("rawNumber") => (TraitValueSet::RawNumber, bytes_space),
+        Some("rawString") => (TraitValueSet::RawString, bytes_space),
+        Some("utf8") => (TraitValueSet::Utf8, bytes_space),
+        _ => return Err(invalid("kind is unknown")),
+    };
+    let dna_bytes = dna.map(|dna| {
+        let start = offset.min(dna.len());
+        let end = (offset + length).min(dna.len());
+        hex::encode(&dna[start..end])
+    });
+    let byte_map = TraitByteMap {
+        name: name.to_owned(),
+        type_: type_.to_owned(),
+        offset,
+        length,
+        values,
+        cardinality,
+        hint: is_hint_trait(name),
+        dna_bytes,
+    };
+    Ok(byte_map)
+}
+
+// parse pattern of DOB/0 reference decoder into a map of which DNA bytes feed which traits, and
+// count the trait space of features, DNA bytes of each trait are reported if DNA is provided
+pub fn analyze_traits_pattern(
+    pattern: &Value,
+    dna: Option<&str>,
+) -> Result<PatternAnalysis, Error> {
+    let items = pattern.as_array().ok_or_else(|| {
+        Error::PatternAnalysisUnsupported("pattern is not an array of trait items".to_owned())
+    })?;
+    let dna = dna
+        .map(|dna| hex::decode(dna.trim_start_matches("0x")))
+        .transpose()
+        .map_err(|_| Error::PatternAnalysisUnsupported("DNA is not in hex format".to_owned()))?;
+
+    let mut traits = Vec::new();
+    let mut trait_space = Some(1u128);
+    let mut trait_space_bits = 0.0;
+    let mut feature_bytes = BTreeSet::new();
+    for item in items {
+        let byte_map = parse_trait_item(item, dna.as_deref())?;
+        if !byte_map.hint {
+            trait_space = trait_space
+                .zip(byte_map.cardinality)
+                .and_then(|(space, cardinality)| space.checked_mul(cardinality));
+            trait_space_bits += space_bits(byte_map.cardinality, byte_map.length);
+            feature_bytes.extend(byte_map.offset..byte_map.offset.saturating_add(byte_map.length));
+        }
+        traits.push(byte_map);
+    }
+    // traits reading the same bytes are correlated, so the space is capped by values of the bytes
+    let feature_space = dna_bytes_space(feature_bytes.len());
+    let trait_space = match (trait_space, feature_space) {
+        (Some(space), Some(feature_space)) => Some(space.min(feature_space)),
+        (Some(space), None) => Some(space),
+        (None, feature_space) => feature_space,
+    };
+    let trait_space_bits = trait_space_bits.min(space_bits(feature_space, feature_bytes.len()));
+
+    let dna_length = traits
+        .iter()
+        .map(|byte_map| byte_map.offset.saturating_add(byte_map.length))
+        .max()
+        .unwrap_or_default()
+        .max(dna.as_ref().map(Vec::len).unwrap_or_default())
+        .min(MAX_ANALYZED_DNA_LENGTH);
+    let bytes = (0..dna_length)
+        .map(|index| DNAByteUsage {
+            index,
+            traits: traits
+                .iter()
+                .filter(|byte_map| {
+                    (byte_map.offset..byte_map.offset.saturating_add(byte_map.length))
+                        .contains(&index)
+                })
+                .map(|byte_map| byte_map.name.clone())
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let unused_bytes = bytes
+        .iter()
+        .filter(|usage| usage.traits.is_empty())
+        .map(|usage| usage.index)
+        .collect();
+    Ok(PatternAnalysis {
+        dna_length,
+        traits,
+        bytes,
+        unused_bytes,
+        trait_space_size: trait_space,
+        trait_space_bits: trait_space_bits.max(0.0),
+    })
+}
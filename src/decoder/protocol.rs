@@ -0,0 +1,503 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use futures::future::{BoxFuture, FutureExt};
+use serde_json::Value;
+
+use crate::types::{
+    DOBClusterFormat, DOBClusterFormatV0, DOBDecoderFormat, DOBDecoderStage, DOBSporeFormat,
+    DOBStageFormat, DOBStageKind, DOBStageOutput, Error, StandardDOBOutput,
+};
+
+use super::{helpers::write_file_atomically, DOBDecoder};
+
+// a version of DOB protocol, which interprets cluster metadata and renders spore DNA under it
+//
+// note: protocols are registered into `DOBDecoder` by the `ver` field of cluster metadata, DOB/0
+// and DOB/1 are built-in, and library users are able to register their own versions
+pub trait DobProtocol: Send + Sync {
+    // version number declared by `ver` field of cluster metadata
+    fn version(&self) -> u8;
+
+    // check cluster metadata, and return decoders it references in executing order, `decode` and
+    // `decode_stages` check metadata in the same way by themselves, so that it's parsed only once
+    // per decoding
+    fn parse_metadata(&self, dob: &DOBClusterFormat) -> Result<Vec<DOBDecoderFormat>, Error>;
+
+    // check spore content before decoding, spore is accepted if DNA exists by default
+    fn validate_content(&self, _spore: &DOBSporeFormat) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // run decode pipeline over DNA, and return the raw output
+    fn decode<'a>(
+        &'a self,
+        decoder: &'a DOBDecoder,
+        dna: &'a str,
+        dob: &'a DOBClusterFormat,
+    ) -> BoxFuture<'a, Result<String, Error>>;
+
+    // shape raw output of decode pipeline into render output, which is kept as is by default
+    fn shape_output(&self, output: String) -> Result<String, Error> {
+        Ok(output)
+    }
+
+    // run decode pipeline and return output of every decoder, only supported by protocols whose
+    // decoders run in chain
+    fn decode_stages<'a>(
+        &'a self,
+        _decoder: &'a DOBDecoder,
+        _dna: &'a str,
+        _dob: &'a DOBClusterFormat,
+    ) -> BoxFuture<'a, Result<Vec<DOBDecoderStage>, Error>> {
+        let version = self.version();
+        async move { Err(Error::DOBStagesUnsupported(version)) }.boxed()
+    }
+}
+
+// result of running decoder binary in ckb-vm
+pub struct DecoderExecution {
+    pub exit_code: i8,
+    pub outputs: Vec<String>,
+    pub cycles: u64,
+}
+
+// run decoder binary in ckb-vm with arguments
+pub fn execute_decoder(decoder_path: &Path, args: Vec<String>) -> Result<DecoderExecution, Error> {
+    let (exit_code, outputs, cycles) = crate::vm::execute_riscv_binary(
+        &decoder_path.to_string_lossy(),
+        args.into_iter().map(Into::into).collect(),
+    )
+    .map_err(|e| Error::DecoderExecutionError(e.to_string()))?;
+    Ok(DecoderExecution {
+        exit_code,
+        outputs,
+        cycles,
+    })
+}
+
+// pattern is passed to decoder in its original string, or in JSON format otherwise
+pub fn pattern_argument(pattern: &Value) -> String {
+    match pattern {
+        Value::String(string) => string.to_owned(),
+        pattern => pattern.to_string(),
+    }
+}
+
+// DOB/0, which renders DNA by a single decoder with pattern
+pub struct Dob0Protocol;
+
+impl Dob0Protocol {
+    fn metadata<'a>(&self, dob: &'a DOBClusterFormat) -> Result<&'a DOBClusterFormatV0, Error> {
+        dob.dob_ver_0.as_ref().ok_or(Error::ClusterDataUncompatible)
+    }
+}
+
+impl DobProtocol for Dob0Protocol {
+    fn version(&self) -> u8 {
+        0
+    }
+
+    fn parse_metadata(&self, dob: &DOBClusterFormat) -> Result<Vec<DOBDecoderFormat>, Error> {
+        Ok(vec![self.metadata(dob)?.decoder.clone()])
+    }
+
+    fn decode<'a>(
+        &'a self,
+        decoder: &'a DOBDecoder,
+        dna: &'a str,
+        dob: &'a DOBClusterFormat,
+    ) -> BoxFuture<'a, Result<String, Error>> {
+        async move {
+            let dob0 = self.metadata(dob)?;
+            let decoder_path = decoder.resolve_decoder(&dob0.decoder).await?;
+            let args = vec![dna.to_owned(), pattern_argument(&dob0.pattern)];
+            let DecoderExecution {
+                exit_code, outputs, ..
+            } = execute_decoder(&decoder_path, args)?;
+            #[cfg(feature = "render_debug")]
+            {
+                println!("\n-------- DOB/0 DECODE RESULT ({exit_code}) ---------");
+                outputs.iter().for_each(|output| println!("{output}"));
+                println!("-------- DOB/0 DECODE RESULT END ---------");
+            }
+            if exit_code != 0 {
+                return Err(Error::DecoderExecutionInternalError(exit_code));
+            }
+            let raw_render_result = outputs.first().ok_or(Error::DecoderOutputEmpty)?;
+            // validate traits only, raw output is returned as it is
+            check_traits(decoder, &StandardDOBOutput::parse_list(raw_render_result)?)?;
+            Ok(raw_render_result.clone())
+        }
+        .boxed()
+    }
+}
+
+// DOB/1, which renders DNA by a chain of decoders, each takes output of the previous one
+pub struct Dob1Protocol;
+
+impl Dob1Protocol {
+    fn metadata<'a>(&self, dob: &'a DOBClusterFormat) -> Result<&'a [DOBClusterFormatV0], Error> {
+        let decoders = &dob
+            .dob_ver_1
+            .as_ref()
+            .ok_or(Error::ClusterDataUncompatible)?
+            .decoders;
+        if decoders.is_empty() {
+            return Err(Error::DecoderChainIsEmpty);
+        }
+        Ok(decoders)
+    }
+}
+
+impl DobProtocol for Dob1Protocol {
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn parse_metadata(&self, dob: &DOBClusterFormat) -> Result<Vec<DOBDecoderFormat>, Error> {
+        let decoders = self.metadata(dob)?;
+        Ok(decoders.iter().map(|value| value.decoder.clone()).collect())
+    }
+
+    fn decode<'a>(
+        &'a self,
+        decoder: &'a DOBDecoder,
+        dna: &'a str,
+        dob: &'a DOBClusterFormat,
+    ) -> BoxFuture<'a, Result<String, Error>> {
+        async move {
+            let stages = self.decode_stages(decoder, dna, dob).await?;
+            let Some(last_stage) = stages.last() else {
+                return Err(Error::DecoderChainIsEmpty);
+            };
+            Ok(serde_json::to_string(&last_stage.output).unwrap())
+        }
+        .boxed()
+    }
+
+    fn decode_stages<'a>(
+        &'a self,
+        decoder: &'a DOBDecoder,
+        dna: &'a str,
+        dob: &'a DOBClusterFormat,
+    ) -> BoxFuture<'a, Result<Vec<DOBDecoderStage>, Error>> {
+        async move {
+            let mut output = Option::<Vec<StandardDOBOutput>>::None;
+            let mut stages = Vec::new();
+            let mut stage_key = ckb_hash::blake2b_256(dna);
+            for (i, value) in self.metadata(dob)?.iter().enumerate() {
+                let stage = run_decoder_stage(
+                    decoder,
+                    StageRun {
+                        index: i,
+                        label: format!("DOB/1 {i}"),
+                        decoder: &value.decoder,
+                        pattern: &value.pattern,
+                        dna,
+                        traits: output.as_ref(),
+                    },
+                    &mut stage_key,
+                    traits_output,
+                )
+                .await?;
+                output = Some(
+                    serde_json::from_value(stage.output.clone())
+                        .map_err(|_| Error::DecoderOutputInvalid)?,
+                );
+                stages.push(stage);
+            }
+            Ok(stages)
+        }
+        .boxed()
+    }
+}
+
+// stage of decoder chain to run, `traits` are the latest traits output by previous stages, and
+// `label` tells the stage in debug output
+struct StageRun<'a> {
+    index: usize,
+    label: String,
+    decoder: &'a DOBDecoderFormat,
+    pattern: &'a Value,
+    dna: &'a str,
+    traits: Option<&'a Vec<StandardDOBOutput>>,
+}
+
+// traits whose values don't meet their types fail the render under `strict_dob_traits`, and are
+// only warned otherwise, so that outputs of existing clusters keep rendering
+fn check_traits(decoder: &DOBDecoder, outputs: &[StandardDOBOutput]) -> Result<(), Error> {
+    for issue in StandardDOBOutput::trait_issues(outputs) {
+        if decoder.setting().strict_dob_traits {
+            return Err(Error::DecoderOutputTraitInvalid(issue.name, issue.reason));
+        }
+        tracing::warn!(
+            "trait `{}` in decoder output is invalid: {}",
+            issue.name,
+            issue.reason
+        );
+    }
+    Ok(())
+}
+
+// output of traits stage, which must be traits in `StandardDOBOutput` format
+fn traits_output(decoder: &DOBDecoder, raw_output: &str) -> Result<Value, Error> {
+    let traits = StandardDOBOutput::parse_list(raw_output)?;
+    check_traits(decoder, &traits)?;
+    Ok(serde_json::to_value(&traits).unwrap())
+}
+
+// run decoder of the stage, or load its output from stage cache if exists, `stage_key` is chained
+// with the decoder binary and pattern of this stage, and `parse_output` turns the raw decoder
+// output into stage output
+async fn run_decoder_stage(
+    decoder: &DOBDecoder,
+    run: StageRun<'_>,
+    stage_key: &mut [u8; 32],
+    parse_output: fn(&DOBDecoder, &str) -> Result<Value, Error>,
+) -> Result<DOBDecoderStage, Error> {
+    let cache_directory = decoder.stages_cache_directory();
+    let cache_expiration = decoder.setting().decoder_stages_cache_expiration_sec;
+    let decoder_path = decoder.resolve_decoder(run.decoder).await?;
+    let binary_hash = decoder.decoder_binary_hash(run.decoder, &decoder_path)?;
+    let pattern = pattern_argument(run.pattern);
+    *stage_key = next_stage_key(stage_key, &binary_hash.0, &pattern);
+    let cached_stage = cache_directory
+        .and_then(|directory| read_stage_cache(directory, stage_key, cache_expiration));
+    if let Some(stage) = cached_stage {
+        // the same stage output may be cached under another cluster, where the stage is at
+        // different index or located by different decoder location
+        return Ok(DOBDecoderStage {
+            index: run.index,
+            decoder: run.decoder.clone(),
+            ..stage
+        });
+    }
+    let mut args = vec![run.dna.to_owned(), pattern];
+    if let Some(traits) = run.traits {
+        args.push(serde_json::to_string(traits).expect("parsed_dna"));
+    }
+    let execution = execute_decoder(&decoder_path, args)?;
+    #[cfg(feature = "render_debug")]
+    {
+        let (label, exit_code) = (&run.label, execution.exit_code);
+        println!("\n-------- {label} DECODE RESULT ({exit_code}) ---------");
+        execution
+            .outputs
+            .iter()
+            .for_each(|output| println!("{output}"));
+        println!("-------- {label} DECODE RESULT END ---------");
+    }
+    #[cfg(not(feature = "render_debug"))]
+    let _ = run.label;
+    if execution.exit_code != 0 {
+        return Err(Error::DecoderExecutionInternalError(execution.exit_code));
+    }
+    let raw_output = execution.outputs.first().ok_or(Error::DecoderOutputEmpty)?;
+    let stage = DOBDecoderStage {
+        index: run.index,
+        decoder: run.decoder.clone(),
+        binary_hash,
+        cycles: execution.cycles,
+        output: parse_output(decoder, raw_output)?,
+        cached: false,
+    };
+    if let Some(directory) = cache_directory {
+        write_stage_cache(directory, stage_key, &stage);
+    }
+    Ok(stage)
+}
+
+// key of stage output, which chains DNA and all of the decoder binaries and patterns before, so
+// that changing a later decoder keeps earlier stage outputs reusable
+fn next_stage_key(previous_key: &[u8; 32], binary_hash: &[u8; 32], pattern: &str) -> [u8; 32] {
+    let mut hasher = ckb_hash::new_blake2b();
+    hasher.update(previous_key);
+    hasher.update(binary_hash);
+    hasher.update(pattern.as_bytes());
+    let mut key = [0u8; 32];
+    hasher.finalize(&mut key);
+    key
+}
+
+// whether stage cache file was written more than `expiration` seconds ago, zero means never expire
+fn is_stage_cache_expired(path: &Path, expiration: u64) -> bool {
+    if expiration == 0 {
+        return false;
+    }
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > Duration::from_secs(expiration))
+}
+
+fn read_stage_cache(
+    directory: &Path,
+    stage_key: &[u8; 32],
+    expiration: u64,
+) -> Option<DOBDecoderStage> {
+    let path = directory.join(format!("{}.json", hex::encode(stage_key)));
+    if is_stage_cache_expired(&path, expiration) {
+        let _ = std::fs::remove_file(&path);
+        return None;
+    }
+    let content = std::fs::read(&path).ok()?;
+    let mut stage: DOBDecoderStage = serde_json::from_slice(&content).ok()?;
+    stage.cached = true;
+    Some(stage)
+}
+
+// stage cache is written in best effort, failure only leads to rerunning the decoder next time
+fn write_stage_cache(directory: &Path, stage_key: &[u8; 32], stage: &DOBDecoderStage) {
+    if std::fs::create_dir_all(directory).is_ok() {
+        let content = serde_json::to_vec(stage).expect("serialize decoder stage");
+        let path = directory.join(format!("{}.json", hex::encode(stage_key)));
+        let _ = write_file_atomically(&path, &content);
+    }
+}
+
+// remove expired stage cache files in directory, and return the number of removed ones
+pub fn clean_stage_cache(directory: &Path, expiration: u64) -> usize {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter(|path| is_stage_cache_expired(path, expiration))
+        .filter(|path| std::fs::remove_file(path).is_ok())
+        .count()
+}
+
+// DOB/2, which renders DNA by named stages, `traits` stages parse DNA into traits in chain like
+// DOB/1, and `render` stages render artifacts from the latest traits
+pub struct Dob2Protocol;
+
+impl Dob2Protocol {
+    // stages of cluster metadata, which must be non-empty and uniquely named
+    fn metadata<'a>(&self, dob: &'a DOBClusterFormat) -> Result<&'a [DOBStageFormat], Error> {
+        let stages = &dob
+            .dob_ver_2
+            .as_ref()
+            .ok_or(Error::ClusterDataUncompatible)?
+            .stages;
+        if stages.is_empty() {
+            return Err(Error::DecoderChainIsEmpty);
+        }
+        for (i, stage) in stages.iter().enumerate() {
+            if stage.name.is_empty() {
+                return Err(Error::DOBStageInvalid(
+                    i.to_string(),
+                    "empty name".to_owned(),
+                ));
+            }
+            if stages[..i]
+                .iter()
+                .any(|previous| previous.name == stage.name)
+            {
+                return Err(Error::DOBStageInvalid(
+                    stage.name.clone(),
+                    "duplicate name".to_owned(),
+                ));
+            }
+        }
+        Ok(stages)
+    }
+
+    // run stages of checked metadata in order, each `traits` stage takes the latest traits output
+    // before it, and so does each `render` stage
+    async fn run_stages(
+        &self,
+        decoder: &DOBDecoder,
+        dna: &str,
+        stages: &[DOBStageFormat],
+    ) -> Result<Vec<DOBDecoderStage>, Error> {
+        let mut traits = Option::<Vec<StandardDOBOutput>>::None;
+        let mut outputs = Vec::new();
+        // keyed apart from DOB/1 stages, since render stages keep their outputs as they are
+        // rather than in traits
+        let mut stage_key = ckb_hash::blake2b_256(format!("dob/2:{dna}"));
+        for (i, stage) in stages.iter().enumerate() {
+            let parse_output = match stage.kind {
+                DOBStageKind::Traits => traits_output,
+                DOBStageKind::Render => render_output,
+            };
+            let output = run_decoder_stage(
+                decoder,
+                StageRun {
+                    index: i,
+                    label: format!("DOB/2 {}", stage.name),
+                    decoder: &stage.decoder,
+                    pattern: &stage.pattern,
+                    dna,
+                    traits: traits.as_ref(),
+                },
+                &mut stage_key,
+                parse_output,
+            )
+            .await?;
+            if stage.kind == DOBStageKind::Traits {
+                traits = Some(
+                    serde_json::from_value(output.output.clone())
+                        .map_err(|_| Error::DecoderOutputInvalid)?,
+                );
+            }
+            outputs.push(output);
+        }
+        Ok(outputs)
+    }
+}
+
+// output of render stage, which is kept in JSON if possible, otherwise in plain string
+fn render_output(_decoder: &DOBDecoder, raw_output: &str) -> Result<Value, Error> {
+    Ok(serde_json::from_str(raw_output).unwrap_or_else(|_| Value::String(raw_output.to_owned())))
+}
+
+impl DobProtocol for Dob2Protocol {
+    fn version(&self) -> u8 {
+        2
+    }
+
+    fn parse_metadata(&self, dob: &DOBClusterFormat) -> Result<Vec<DOBDecoderFormat>, Error> {
+        let stages = self.metadata(dob)?;
+        Ok(stages.iter().map(|stage| stage.decoder.clone()).collect())
+    }
+
+    fn decode<'a>(
+        &'a self,
+        decoder: &'a DOBDecoder,
+        dna: &'a str,
+        dob: &'a DOBClusterFormat,
+    ) -> BoxFuture<'a, Result<String, Error>> {
+        async move {
+            let stages = self.metadata(dob)?;
+            let stage_outputs = self
+                .run_stages(decoder, dna, stages)
+                .await?
+                .into_iter()
+                .zip(stages)
+                .map(|(output, stage)| DOBStageOutput {
+                    stage: stage.name.clone(),
+                    kind: stage.kind,
+                    media_type: stage.media_type.clone(),
+                    output: output.output,
+                })
+                .collect::<Vec<_>>();
+            Ok(serde_json::to_string(&stage_outputs).unwrap())
+        }
+        .boxed()
+    }
+
+    fn decode_stages<'a>(
+        &'a self,
+        decoder: &'a DOBDecoder,
+        dna: &'a str,
+        dob: &'a DOBClusterFormat,
+    ) -> BoxFuture<'a, Result<Vec<DOBDecoderStage>, Error>> {
+        async move { self.run_stages(decoder, dna, self.metadata(dob)?).await }.boxed()
+    }
+}
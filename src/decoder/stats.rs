@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::types::{
+    ClusterStats, DOBTrait, Error, SporeRarity, SporeTraitRarity, StandardDOBOutput,
+    TraitValueStats,
+};
+
+use super::{helpers::write_file_atomically, DOBDecoder};
+
+// spores scanned in one round while rendering the whole cluster
+const CLUSTER_SCAN_PAGE_SIZE: u32 = 100;
+
+// rendered traits of live spores in cluster, along with the number of spores failed to render
+pub struct ClusterTraits {
+    pub spores: Vec<([u8; 32], Vec<StandardDOBOutput>)>,
+    pub failed_count: usize,
+}
+
+// parse traits out of render output, which are in the output of the last traits stage for DOB/2
+pub fn parse_render_traits(render_output: &str) -> Result<Vec<StandardDOBOutput>, Error> {
+    let error = match StandardDOBOutput::parse_list(render_output) {
+        Ok(traits) => return Ok(traits),
+        Err(error) => error,
+    };
+    let stages: Vec<Value> = serde_json::from_str(render_output).map_err(|_| error)?;
+    let traits_stage = stages
+        .into_iter()
+        .rev()
+        .find(|stage| stage.get("kind").and_then(Value::as_str) == Some("traits"))
+        .and_then(|stage| stage.get("output").cloned())
+        .ok_or(Error::DecoderOutputInvalid)?;
+    StandardDOBOutput::parse_list(&traits_stage.to_string())
+}
+
+// render all live spores in cluster through the decoding pipeline, and parse their traits
+pub async fn render_cluster_traits(
+    decoder: &DOBDecoder,
+    cluster_id: [u8; 32],
+) -> Result<ClusterTraits, Error> {
+    let dob_metadata = decoder.fetch_dob_cluster(cluster_id).await?.metadata;
+    let mut traits = ClusterTraits {
+        spores: Vec::new(),
+        failed_count: 0,
+    };
+    let mut cursor = None;
+    loop {
+        let page = decoder
+            .fetch_cluster_spores(cluster_id, CLUSTER_SCAN_PAGE_SIZE, cursor)
+            .await?;
+        for (spore_id, spore) in page.spores {
+            let render_output = decoder.decode_spore(&spore, dob_metadata.clone()).await;
+            match render_output.and_then(|render_output| parse_render_traits(&render_output)) {
+                Ok(outputs) => traits.spores.push((spore_id, outputs)),
+                Err(_) => traits.failed_count += 1,
+            }
+        }
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(traits)
+}
+
+// traits which are rendering hints rather than features of spore
+pub(crate) fn is_hint_trait(name: &str) -> bool {
+    name.starts_with('_') || name.starts_with("prev")
+}
+
+// features of spore, which are the first trait value under each non-hint name
+fn spore_features(outputs: &[StandardDOBOutput]) -> impl Iterator<Item = (&str, &DOBTrait)> {
+    outputs
+        .iter()
+        .filter(|output| !is_hint_trait(&output.name))
+        .filter_map(|output| Some((output.name.as_str(), output.first_trait()?)))
+}
+
+// aggregate rendered traits into value distributions, and score spores by the inverse frequency
+// of their trait values
+pub fn aggregate_cluster_stats(
+    cluster_id: [u8; 32],
+    cluster_traits: &ClusterTraits,
+    computed_at: u64,
+) -> ClusterStats {
+    let rendered_count = cluster_traits.spores.len();
+    let mut distributions = BTreeMap::<String, BTreeMap<String, TraitValueStats>>::new();
+    for (_, outputs) in &cluster_traits.spores {
+        for (name, value) in spore_features(outputs) {
+            let key = serde_json::to_string(value).unwrap();
+            let stats = distributions
+                .entry(name.to_owned())
+                .or_default()
+                .entry(key)
+                .or_insert_with(|| TraitValueStats {
+                    value: value.clone(),
+                    count: 0,
+                    frequency: 0.0,
+                });
+            stats.count += 1;
+        }
+    }
+    distributions
+        .values_mut()
+        .flat_map(|values| values.values_mut())
+        .for_each(|stats| stats.frequency = stats.count as f64 / rendered_count as f64);
+
+    let mut spores = cluster_traits
+        .spores
+        .iter()
+        .map(|(spore_id, outputs)| {
+            let traits = spore_features(outputs)
+                .map(|(name, value)| {
+                    let key = serde_json::to_string(value).unwrap();
+                    SporeTraitRarity {
+                        name: name.to_owned(),
+                        value: value.clone(),
+                        frequency: distributions[name][&key].frequency,
+                    }
+                })
+                .collect::<Vec<_>>();
+            SporeRarity {
+                spore_id: hex::encode(spore_id),
+                score: traits.iter().map(|value| 1.0 / value.frequency).sum(),
+                rank: 0,
+                traits,
+            }
+        })
+        .collect::<Vec<_>>();
+    spores.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut previous = None;
+    for (i, spore) in spores.iter_mut().enumerate() {
+        spore.rank = match previous {
+            Some((score, rank)) if score == spore.score => rank,
+            _ => i + 1,
+        };
+        previous = Some((spore.score, spore.rank));
+    }
+
+    let traits = distributions
+        .into_iter()
+        .map(|(name, values)| {
+            let mut values = values.into_values().collect::<Vec<_>>();
+            values.sort_by(|a, b| b.count.cmp(&a.count));
+            (name, values)
+        })
+        .collect();
+    ClusterStats {
+        cluster_id: hex::encode(cluster_id),
+        computed_at,
+        spore_count: rendered_count + cluster_traits.failed_count,
+        failed_count: cluster_traits.failed_count,
+        traits,
+        spores,
+    }
+}
+
+fn stats_path(directory: &Path, cluster_id: &[u8; 32]) -> PathBuf {
+    directory.join(format!("{}.json", hex::encode(cluster_id)))
+}
+
+// load stored statistics of cluster, none if not stored or unreadable
+pub fn read_cluster_stats(directory: &Path, cluster_id: &[u8; 32]) -> Option<ClusterStats> {
+    let content = std::fs::read(stats_path(directory, cluster_id)).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn write_cluster_stats(
+    directory: &Path,
+    cluster_id: &[u8; 32],
+    stats: &ClusterStats,
+) -> Result<(), Error> {
+    let path = stats_path(directory, cluster_id);
+    let content = serde_json::to_vec(stats).expect("serialize cluster stats");
+    std::fs::create_dir_all(directory)
+        .and_then(|_| write_file_atomically(&path, &content))
+        .map_err(|_| Error::ClusterStatsUnwritable(path.display().to_string()))
+}
+
+// compute statistics of cluster from its rendered traits and store them
+pub fn store_cluster_stats(
+    decoder: &DOBDecoder,
+    cluster_id: [u8; 32],
+    cluster_traits: &ClusterTraits,
+    computed_at: u64,
+) -> Result<ClusterStats, Error> {
+    let Some(directory) = &decoder.setting().cluster_stats_directory else {
+        return Err(Error::ClusterStatsNotComputed(hex::encode(cluster_id)));
+    };
+    let stats = aggregate_cluster_stats(cluster_id, cluster_traits, computed_at);
+    write_cluster_stats(directory, &cluster_id, &stats)?;
+    Ok(stats)
+}
+
+// stored statistics of cluster, rpc requests never trigger rendering the whole cluster
+pub fn fetch_cluster_stats(
+    decoder: &DOBDecoder,
+    cluster_id: [u8; 32],
+) -> Result<ClusterStats, Error> {
+    decoder
+        .setting()
+        .cluster_stats_directory
+        .as_ref()
+        .and_then(|directory| read_cluster_stats(directory, &cluster_id))
+        .ok_or_else(|| Error::ClusterStatsNotComputed(hex::encode(cluster_id)))
+}
+
+// rarity of spore in the stored statistics of its cluster, spores minted after the statistics
+// computed are not found until the next round of background job
+pub fn fetch_spore_rarity(
+    decoder: &DOBDecoder,
+    spore_id: [u8; 32],
+    cluster_id: [u8; 32],
+) -> Result<SporeRarity, Error> {
+    let hexed_spore_id = hex::encode(spore_id);
+    fetch_cluster_stats(decoder, cluster_id)?
+        .spores
+        .into_iter()
+        .find(|spore| spore.spore_id == hexed_spore_id)
+        .ok_or(Error::SporeRarityNotFound(hexed_spore_id))
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use ckb_jsonrpc_types::{JsonBytes, OutPoint, Status, Transaction, TxStatus};
+
+use crate::types::{Error, RenderedDOB, Settings, TransactionDOB};
+
+use super::{helpers::*, DOBDecoder};
+
+// spore cell in transaction outputs, which is `(out_index, spore_id, spore_data)`
+pub type TransactionSpore = (u32, [u8; 32], JsonBytes);
+
+// cluster data in transaction outputs by cluster id
+pub fn transaction_clusters(settings: &Settings, tx: &Transaction) -> HashMap<[u8; 32], JsonBytes> {
+    let mut clusters = HashMap::new();
+    for (output, data) in tx.outputs.iter().zip(tx.outputs_data.iter()) {
+        let Some(type_script) = &output.type_ else {
+            continue;
+        };
+        if script_matches(type_script, &settings.available_clusters) {
+            if let Ok(cluster_id) = <[u8; 32]>::try_from(type_script.args.as_bytes()) {
+                clusters.insert(cluster_id, data.clone());
+            }
+        }
+    }
+    clusters
+}
+
+// spore cells in transaction outputs, only the one at `only_index` is returned if provided
+pub fn transaction_spores(
+    settings: &Settings,
+    tx: &Transaction,
+    only_index: Option<u32>,
+) -> Vec<TransactionSpore> {
+    let mut spores = Vec::new();
+    for (index, output) in tx.outputs.iter().enumerate() {
+        let out_index = index as u32;
+        if only_index.is_some_and(|only_index| only_index != out_index) {
+            continue;
+        }
+        let Some(type_script) = &output.type_ else {
+            continue;
+        };
+        if !script_matches(type_script, &settings.available_spores) {
+            continue;
+        }
+        let Ok(spore_id) = <[u8; 32]>::try_from(type_script.args.as_bytes()) else {
+            continue;
+        };
+        let spore_data = tx.outputs_data.get(index).cloned().unwrap_or_default();
+        spores.push((out_index, spore_id, spore_data));
+    }
+    spores
+}
+
+// block number of committed transaction, none if it's still in pool, and error if it's rejected
+// or unknown to the node
+pub fn committed_block_number(tx_status: &TxStatus) -> Result<Option<u64>, Error> {
+    match tx_status.status {
+        Status::Committed => Ok(tx_status.block_number.map(|number| number.value())),
+        Status::Pending | Status::Proposed => Ok(None),
+        Status::Rejected | Status::Unknown => Err(Error::FetchTransactionError(format!(
+            "transaction status is {:?}",
+            tx_status.status
+        ))),
+    }
+}
+
+// render spore cells in transaction outputs, which is not required to be submitted, and clusters
+// are resolved from cluster cells in the same transaction first, then from chain
+pub async fn decode_transaction(decoder: &DOBDecoder, tx: &Transaction) -> Vec<TransactionDOB> {
+    let clusters = transaction_clusters(decoder.setting(), tx);
+    let mut dobs = Vec::new();
+    for (out_index, spore_id, spore_data) in transaction_spores(decoder.setting(), tx, None) {
+        let result = render_spore_data(decoder, &spore_data, &clusters, None).await;
+        dobs.push(TransactionDOB {
+            out_index,
+            spore_id,
+            result,
+        });
+    }
+    dobs
+}
+
+// render spore cell located at the out point, whether it's live or not, which is finalized only if
+// both of its transaction and cluster are confirmed by enough blocks, the same as spores searched
+// by id, and transactions still in pool are rendered but never finalized
+pub async fn decode_by_outpoint(
+    decoder: &DOBDecoder,
+    out_point: &OutPoint,
+) -> Result<RenderedDOB, Error> {
+    let not_found = || {
+        Error::FetchTransactionError(format!(
+            "transaction {} not found",
+            hex::encode(&out_point.tx_hash)
+        ))
+    };
+    let tx = decoder
+        .rpc()
+        .get_transaction(&out_point.tx_hash)
+        .await?
+        .ok_or_else(not_found)?;
+    let block_number = committed_block_number(&tx.tx_status)?;
+    let tx_view = unwrap_transaction_view(tx)?.ok_or_else(not_found)?;
+    let out_index = out_point.index.value();
+    let clusters = transaction_clusters(decoder.setting(), &tx_view.inner);
+    let (_, _, spore_data) = transaction_spores(decoder.setting(), &tx_view.inner, Some(out_index))
+        .pop()
+        .ok_or(Error::SporeCellNotFound(
+            hex::encode(&out_point.tx_hash),
+            out_index,
+        ))?;
+    if block_number.is_some() && decoder.setting().min_confirmations > 0 {
+        decoder.rpc().get_tip_block_number().await?;
+    }
+    render_spore_data(decoder, &spore_data, &clusters, block_number).await
+}
+
+// render spore data committed at `block_number`, none if the transaction isn't committed
+async fn render_spore_data(
+    decoder: &DOBDecoder,
+    spore_data: &JsonBytes,
+    clusters: &HashMap<[u8; 32], JsonBytes>,
+    block_number: Option<u64>,
+) -> Result<RenderedDOB, Error> {
+    let mut spore = decode_spore_data(spore_data.as_bytes())?;
+    spore.block_number = block_number;
+    check_protocol_version(decoder.setting(), &spore.content_type)?;
+    let (dob_metadata, cluster_resolution, cluster_block_number) =
+        match clusters.get(&spore.cluster_id) {
+            Some(cluster_data) => (
+                decode_cluster_data(cluster_data.as_bytes())?,
+                None,
+                block_number,
+            ),
+            None => {
+                let cluster = decoder.fetch_dob_cluster(spore.cluster_id).await?;
+                (
+                    cluster.metadata,
+                    Some(cluster.resolution),
+                    Some(cluster.block_number),
+                )
+            }
+        };
+    let finalized = block_number.is_some_and(|number| decoder.rpc().is_final(number))
+        && cluster_block_number.is_some_and(|number| decoder.rpc().is_final(number));
+    let render_output = decoder.decode_spore(&spore, dob_metadata).await?;
+    Ok(RenderedDOB {
+        spore,
+        render_output,
+        cluster_in_transaction: cluster_resolution.is_none(),
+        cluster_resolution,
+        finalized,
+    })
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use jsonrpsee::tracing;
+
+use crate::decoder::DOBDecoder;
+
+// whether a result refreshed at `refreshed_at` is due to refresh, results are refreshed once
+// they are older than the interval, or only produced once if the interval is zero
+fn is_due(refreshed_at: Option<u64>, interval: u64, now: u64) -> bool {
+    match refreshed_at {
+        Some(refreshed_at) => interval != 0 && now >= refreshed_at.saturating_add(interval),
+        None => true,
+    }
+}
+
+// keep trait index and statistics of configured clusters up to date, a cluster due to either is
+// rendered once and both of its trait index and statistics are refreshed from the same renders
+pub async fn refresh_clusters(decoder: Arc<DOBDecoder>) {
+    let settings = decoder.setting();
+    let index_interval = settings.trait_index_refresh_interval_sec;
+    let stats_interval = settings.cluster_stats_refresh_interval_sec;
+    let stats_enabled = settings.cluster_stats_directory.is_some();
+    if !stats_enabled && !settings.stats_clusters.is_empty() {
+        tracing::warn!("`cluster_stats_directory` isn't set, skip computing cluster stats");
+    }
+    let indexed_clusters = settings
+        .indexed_clusters
+        .iter()
+        .map(|cluster_id| cluster_id.0)
+        .collect::<BTreeSet<_>>();
+    let stats_clusters = settings
+        .stats_clusters
+        .iter()
+        .map(|cluster_id| cluster_id.0)
+        .filter(|_| stats_enabled)
+        .collect::<BTreeSet<_>>();
+    let intervals = [
+        (!indexed_clusters.is_empty()).then_some(index_interval),
+        (!stats_clusters.is_empty()).then_some(stats_interval),
+    ];
+    let sleep_interval = intervals.into_iter().flatten().filter(|i| *i != 0).min();
+    loop {
+        for cluster_id in indexed_clusters.union(&stats_clusters) {
+            let hexed_cluster_id = hex::encode(cluster_id);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let update_index = indexed_clusters.contains(cluster_id);
+            let update_stats = stats_clusters.contains(cluster_id);
+            let index_due = update_index
+                && is_due(
+                    decoder.trait_index().indexed_at(&hexed_cluster_id),
+                    index_interval,
+                    now,
+                );
+            let stats_due = update_stats
+                && is_due(
+                    decoder
+                        .fetch_cluster_stats(*cluster_id)
+                        .ok()
+                        .map(|stats| stats.computed_at),
+                    stats_interval,
+                    now,
+                );
+            if !index_due && !stats_due {
+                continue;
+            }
+            match decoder
+                .refresh_cluster(*cluster_id, update_index, update_stats)
+                .await
+            {
+                Ok(count) => {
+                    tracing::info!("refreshed {count} spores of cluster_id {hexed_cluster_id}")
+                }
+                Err(error) => {
+                    tracing::error!("refreshing cluster_id {hexed_cluster_id} failed: {error}")
+                }
+            }
+        }
+        let Some(sleep_interval) = sleep_interval else {
+            break;
+        };
+        tokio::time::sleep(Duration::from_secs(sleep_interval)).await;
+    }
+}
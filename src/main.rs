@@ -1,16 +1,48 @@
 use std::fs;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::Duration;
 
 use jsonrpsee::{server::ServerBuilder, tracing};
-use server::DecoderRpcServer;
+use server::{DecoderAdminRpcServer, DecoderRpcServer};
 use tracing_subscriber::EnvFilter;
 
+mod cli;
 mod client;
 mod decoder;
+mod indexer;
 mod server;
 mod types;
 mod vm;
+mod watcher;
 
 const SETTINGS_FILE: &str = "./settings.toml";
+const STAGES_CACHE_CLEANING_INTERVAL: Duration = Duration::from_secs(3600);
+
+// sweep expired decoder stage outputs periodically, which are otherwise only dropped on reading
+async fn clean_stages_cache(decoder: Arc<decoder::DOBDecoder>) {
+    loop {
+        let cleaning_decoder = decoder.clone();
+        match tokio::task::spawn_blocking(move || cleaning_decoder.clean_stages_cache()).await {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!("removed {removed} expired decoder stage outputs"),
+            Err(error) => tracing::error!("cleaning decoder stage outputs aborted: {error}"),
+        }
+        tokio::time::sleep(STAGES_CACHE_CLEANING_INTERVAL).await;
+    }
+}
+
+// admin rpc server runs user-supplied decoder binaries without authentication, so that it's only
+// allowed to listen on loopback addresses
+fn is_loopback_address(address: &str) -> bool {
+    match address.to_socket_addrs() {
+        Ok(addresses) => {
+            let addresses = addresses.collect::<Vec<_>>();
+            !addresses.is_empty() && addresses.iter().all(|address| address.ip().is_loopback())
+        }
+        Err(_) => false,
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -26,8 +58,44 @@ async fn main() {
         serde_json::to_string_pretty(&settings).unwrap()
     );
     let rpc_server_address = settings.rpc_server_address.clone();
+    let admin_rpc_server_address = settings.admin_rpc_server_address.clone();
+    if let Some(address) = &admin_rpc_server_address {
+        assert!(
+            is_loopback_address(address),
+            "admin_rpc_server_address `{address}` is not a loopback address"
+        );
+    }
     let cache_expiration = settings.dobs_cache_expiration_sec;
-    let decoder = decoder::DOBDecoder::new(settings);
+    let subscription_uri = settings.ckb_subscription.clone();
+    let decoder = Arc::new(decoder::DOBDecoder::new(settings));
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some((command, args)) = args.split_first() {
+        if let Err(error) = cli::run_command(&decoder, command, args).await {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(subscription_uri) = subscription_uri {
+        tracing::info!("watching chain events from {subscription_uri}");
+        tokio::spawn(watcher::watch_chain_events(
+            decoder.clone(),
+            subscription_uri,
+        ));
+    }
+
+    if decoder.setting().decoder_stages_cache_directory.is_some() {
+        tokio::spawn(clean_stages_cache(decoder.clone()));
+    }
+
+    if !decoder.setting().indexed_clusters.is_empty()
+        || !decoder.setting().stats_clusters.is_empty()
+    {
+        tracing::info!("indexing traits and computing stats of configured clusters");
+        tokio::spawn(indexer::refresh_clusters(decoder.clone()));
+    }
 
     tracing::info!("running decoder server at {}", rpc_server_address);
     let http_server = ServerBuilder::new()
@@ -36,10 +104,27 @@ async fn main() {
         .await
         .expect("build http_server");
 
+    let admin_handler = match admin_rpc_server_address {
+        Some(admin_rpc_server_address) => {
+            tracing::info!("running decoder admin server at {admin_rpc_server_address}");
+            let admin_http_server = ServerBuilder::new()
+                .http_only()
+                .build(admin_rpc_server_address)
+                .await
+                .expect("build admin http_server");
+            let admin_rpc_methods = server::DecoderAdminServer::new(decoder.clone());
+            Some(admin_http_server.start(admin_rpc_methods.into_rpc()))
+        }
+        None => None,
+    };
+
     let rpc_methods = server::DecoderStandaloneServer::new(decoder, cache_expiration);
     let handler = http_server.start(rpc_methods.into_rpc());
 
     tokio::signal::ctrl_c().await.unwrap();
     tracing::info!("stopping decoder server");
     handler.stop().unwrap();
+    if let Some(admin_handler) = admin_handler {
+        admin_handler.stop().unwrap();
+    }
 }
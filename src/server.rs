@@ -1,21 +1,119 @@
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use ckb_jsonrpc_types::{OutPoint, Transaction, Uint32};
+use ckb_types::H256;
 use jsonrpsee::core::async_trait;
 use jsonrpsee::{proc_macros::rpc, tracing, types::error::ErrorObjectOwned};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::decoder::helpers::{decode_cluster_data, decode_spore_data};
+use crate::decoder::dryrun::{clean_substitute_binaries, store_substitute_binary};
+use crate::decoder::helpers::{decode_cluster_data, decode_spore_data, generate_dna};
+use crate::decoder::lint::{sample_dna_length, MAX_DNA_LENGTH};
+use crate::decoder::pattern::{analyze_traits_pattern, traits_pattern};
 use crate::decoder::DOBDecoder;
-use crate::types::Error;
+use crate::types::{
+    ClusterDescriptionField, ClusterResolution, ClusterSporesCursor, DOBDecoderFormat,
+    DOBDecoderStage, DecoderSubstitute, Error, SporeCellInfo, SporeHistoryCursor, TraitFilter,
+    TraitSearchCursor,
+};
+
+// previewing renders DNAs in ckb-vm one by one, so that the count is limited
+const MAX_PREVIEW_DNA_COUNT: usize = 100;
+// spores returned in one page of trait search at most
+const MAX_SEARCH_LIMIT: usize = 100;
+// steps returned in one page of spore history at most
+const MAX_HISTORY_LIMIT: usize = 100;
 
 // decoding result contains rendered result from native decoder and DNA string for optional use
+//
+// note: `finalized` only exists for DOBs fetched from chain, which indicates whether spore and
+// cluster are confirmed by enough blocks, unfinalized result won't be cached, and `spore` reports
+// status, owner and out point of spore cell if resolved in `live_cell_first` mode, and
+// `cluster_resolution` reports whether cluster is resolved directly or via cluster proxy/agent
 #[derive(Serialize, Clone, Debug)]
 pub struct ServerDecodeResult {
     render_output: String,
     dob_content: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finalized: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spore: Option<SporeCellInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster_resolution: Option<ClusterResolution>,
+}
+
+// spore found in cluster, `render_output` or `render_error` only exists if rendering is required
+#[derive(Serialize, Clone, Debug)]
+pub struct ServerClusterSpore {
+    spore_id: String,
+    dob_content: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spore: Option<SporeCellInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    render_output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    render_error: Option<String>,
+}
+
+// pass `cursor` back to continue scanning, null `cursor` means all spores are scanned
+#[derive(Serialize, Clone, Debug)]
+pub struct ServerClusterSporesPage {
+    spores: Vec<ServerClusterSpore>,
+    cursor: Option<ClusterSporesCursor>,
+}
+
+// rendering result of spore cell in transaction outputs, `cluster_in_transaction` indicates the
+// cluster is found in the same transaction, and `error` exists if rendering failed
+#[derive(Serialize, Clone, Debug)]
+pub struct ServerTransactionDecodeResult {
+    out_index: u32,
+    spore_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    render_output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dob_content: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster_in_transaction: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// decoding result along with output of every decoder stage, `render_output` is the output of
+// the last stage
+#[derive(Serialize, Clone)]
+pub struct ServerStagesDecodeResult {
+    render_output: String,
+    dob_content: Value,
+    stages: Vec<DOBDecoderStage>,
+}
+
+// rendering result of DNA in cluster preview, `render_error` exists if rendering failed
+#[derive(Serialize, Clone, Debug)]
+pub struct ServerPreviewRender {
+    dna: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    render_output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    render_error: Option<String>,
+}
+
+// `seed` only exists if random DNAs are generated, which reproduces the same DNAs if passed back
+#[derive(Serialize, Clone, Debug)]
+pub struct ServerPreviewResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    renders: Vec<ServerPreviewRender>,
+}
+
+// substitute decoder binary uploaded in hex format for dry-run
+#[derive(Deserialize, Clone)]
+pub struct ServerDecoderSubstitute {
+    decoder: DOBDecoderFormat,
+    binary: String,
 }
 
 #[rpc(server)]
@@ -32,21 +130,91 @@ trait DecoderRpc {
         hexed_spore_ids: Vec<String>,
     ) -> Result<Vec<String>, ErrorObjectOwned>;
 
+    #[method(name = "dob_decode_by_outpoint")]
+    async fn decode_by_outpoint(
+        &self,
+        hexed_tx_hash: String,
+        out_index: u32,
+    ) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_decode_transaction")]
+    async fn decode_transaction(&self, tx: Transaction) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_spore_history")]
+    async fn spore_history(
+        &self,
+        hexed_spore_id: String,
+        limit: Option<u32>,
+        cursor: Option<SporeHistoryCursor>,
+    ) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_decode_stages")]
+    async fn decode_stages(&self, hexed_spore_id: String) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_list_cluster_spores")]
+    async fn list_cluster_spores(
+        &self,
+        hexed_cluster_id: String,
+        limit: u32,
+        cursor: Option<ClusterSporesCursor>,
+        with_render: Option<bool>,
+    ) -> Result<String, ErrorObjectOwned>;
+
     #[method(name = "dob_raw_decode")]
     async fn raw_decode(
         &self,
         spore_data: String,
         cluster_data: String,
     ) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_preview")]
+    async fn preview(
+        &self,
+        cluster_description: Value,
+        dna_list: Option<Vec<String>>,
+        random_count: Option<u32>,
+        seed: Option<u64>,
+    ) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_cluster_stats")]
+    async fn cluster_stats(&self, hexed_cluster_id: String) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_spore_rarity")]
+    async fn spore_rarity(&self, hexed_spore_id: String) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_search")]
+    async fn search(
+        &self,
+        filters: Vec<TraitFilter>,
+        hexed_cluster_id: Option<String>,
+        limit: u32,
+        cursor: Option<TraitSearchCursor>,
+        count_total: Option<bool>,
+    ) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_validate_cluster")]
+    async fn validate_cluster(
+        &self,
+        cluster_description: Value,
+        sample_dna: Option<String>,
+    ) -> Result<String, ErrorObjectOwned>;
+
+    #[method(name = "dob_analyze_pattern")]
+    async fn analyze_pattern(
+        &self,
+        cluster_description: Option<Value>,
+        hexed_cluster_id: Option<String>,
+        dna: Option<String>,
+    ) -> Result<String, ErrorObjectOwned>;
 }
 
 pub struct DecoderStandaloneServer {
-    decoder: DOBDecoder,
+    decoder: Arc<DOBDecoder>,
     cache_expiration: u64,
 }
 
 impl DecoderStandaloneServer {
-    pub fn new(decoder: DOBDecoder, cache_expiration: u64) -> Self {
+    pub fn new(decoder: Arc<DOBDecoder>, cache_expiration: u64) -> Self {
         Self {
             decoder,
             cache_expiration,
@@ -57,11 +225,29 @@ impl DecoderStandaloneServer {
         &self,
         spore_id: [u8; 32],
         cache_path: PathBuf,
-    ) -> Result<(String, Value), Error> {
-        let (content, dna, metadata) = self.decoder.fetch_decode_ingredients(spore_id).await?;
-        let render_output = self.decoder.decode_dna(&dna, metadata).await?;
-        write_dob_to_cache(&render_output, &content, cache_path, self.cache_expiration)?;
-        Ok((render_output, content))
+    ) -> Result<ServerDecodeResult, Error> {
+        let ingredients = self.decoder.fetch_dob_ingredients(spore_id).await?;
+        let render_output = self
+            .decoder
+            .decode_spore(&ingredients.spore, ingredients.cluster.metadata)
+            .await?;
+        let dob_content = ingredients.spore.content;
+        if ingredients.finalized {
+            write_dob_to_cache(
+                &render_output,
+                &dob_content,
+                &ingredients.cluster.resolution,
+                cache_path,
+                self.cache_expiration,
+            )?;
+        }
+        Ok(ServerDecodeResult {
+            render_output,
+            dob_content,
+            finalized: Some(ingredients.finalized),
+            spore: ingredients.spore.cell,
+            cluster_resolution: Some(ingredients.cluster.resolution),
+        })
     }
 }
 
@@ -74,23 +260,26 @@ impl DecoderRpcServer for DecoderStandaloneServer {
     // decode DNA in particular spore DOB cell
     async fn decode(&self, hexed_spore_id: String) -> Result<String, ErrorObjectOwned> {
         tracing::info!("decoding spore_id {hexed_spore_id}");
-        let spore_id: [u8; 32] = hex::decode(trim_0x(&hexed_spore_id))
-            .map_err(|_| Error::HexedSporeIdParseError)?
-            .try_into()
-            .map_err(|_| Error::SporeIdLengthInvalid)?;
+        let spore_id = parse_spore_id(&hexed_spore_id)?;
         let mut cache_path = self.decoder.setting().dobs_cache_directory.clone();
         cache_path.push(format!("{}.dob", hex::encode(spore_id)));
-        let (render_output, dob_content) =
-            if let Some(cache) = read_dob_from_cache(cache_path.clone(), self.cache_expiration)? {
-                cache
-            } else {
-                self.cache_decode(spore_id, cache_path).await?
-            };
-        let result = serde_json::to_string(&ServerDecodeResult {
-            render_output,
-            dob_content,
-        })
-        .unwrap();
+        let decode_result = if let Some((render_output, dob_content, cluster_resolution)) =
+            read_dob_from_cache(cache_path.clone(), self.cache_expiration)?
+        {
+            // on-chain state of spore changes regardless of render, so that it's not cached but
+            // queried on demand, which only happens in opt-in `live_cell_first` mode
+            let spore = self.decoder.fetch_spore_cell_info(spore_id).await?;
+            ServerDecodeResult {
+                render_output,
+                dob_content,
+                finalized: Some(true),
+                spore,
+                cluster_resolution,
+            }
+        } else {
+            self.cache_decode(spore_id, cache_path).await?
+        };
+        let result = serde_json::to_string(&decode_result).unwrap();
         tracing::info!("spore_id {hexed_spore_id}, result: {result}");
         Ok(result)
     }
@@ -115,6 +304,156 @@ impl DecoderRpcServer for DecoderStandaloneServer {
         Ok(results)
     }
 
+    // decode DNA in spore cell located at particular out point
+    async fn decode_by_outpoint(
+        &self,
+        hexed_tx_hash: String,
+        out_index: u32,
+    ) -> Result<String, ErrorObjectOwned> {
+        tracing::info!("decoding out point {hexed_tx_hash}:{out_index}");
+        let out_point = OutPoint {
+            tx_hash: parse_tx_hash(&hexed_tx_hash)?,
+            index: Uint32::from(out_index),
+        };
+        let dob = self.decoder.decode_by_outpoint(&out_point).await?;
+        let result = serde_json::to_string(&ServerDecodeResult {
+            render_output: dob.render_output,
+            dob_content: dob.spore.content,
+            finalized: Some(dob.finalized),
+            spore: None,
+            cluster_resolution: dob.cluster_resolution,
+        })
+        .unwrap();
+        tracing::info!("out point {hexed_tx_hash}:{out_index}, result: {result}");
+        Ok(result)
+    }
+
+    // decode DNA in all spore cells of transaction outputs, the transaction can be unsubmitted
+    async fn decode_transaction(&self, tx: Transaction) -> Result<String, ErrorObjectOwned> {
+        let results = self
+            .decoder
+            .decode_transaction(&tx)
+            .await
+            .into_iter()
+            .map(|dob| {
+                let mut result = ServerTransactionDecodeResult {
+                    out_index: dob.out_index,
+                    spore_id: hex::encode(dob.spore_id),
+                    render_output: None,
+                    dob_content: None,
+                    cluster_in_transaction: None,
+                    error: None,
+                };
+                match dob.result {
+                    Ok(dob) => {
+                        result.render_output = Some(dob.render_output);
+                        result.dob_content = Some(dob.spore.content);
+                        result.cluster_in_transaction = Some(dob.cluster_in_transaction);
+                    }
+                    Err(error) => result.error = Some(error.to_string()),
+                }
+                result
+            })
+            .collect::<Vec<_>>();
+        let result = serde_json::to_string(&results).unwrap();
+        tracing::info!("transaction, result: {result}");
+        Ok(result)
+    }
+
+    // list mint, transfer and melt steps of particular spore, pass `cursor` back to continue
+    // listing, null `cursor` means all steps are listed
+    async fn spore_history(
+        &self,
+        hexed_spore_id: String,
+        limit: Option<u32>,
+        cursor: Option<SporeHistoryCursor>,
+    ) -> Result<String, ErrorObjectOwned> {
+        tracing::info!("fetching history of spore_id {hexed_spore_id}");
+        let spore_id = parse_spore_id(&hexed_spore_id)?;
+        let limit = match limit {
+            Some(0) => return Err(Error::PageLimitInvalid.into()),
+            Some(limit) => (limit as usize).min(MAX_HISTORY_LIMIT),
+            None => MAX_HISTORY_LIMIT,
+        };
+        let page = self
+            .decoder
+            .fetch_spore_history(spore_id, limit, cursor)
+            .await?;
+        Ok(serde_json::to_string(&page).unwrap())
+    }
+
+    // decode DNA in particular spore DOB cell, and return output of every decoder stage
+    async fn decode_stages(&self, hexed_spore_id: String) -> Result<String, ErrorObjectOwned> {
+        tracing::info!("decoding stages of spore_id {hexed_spore_id}");
+        let spore_id = parse_spore_id(&hexed_spore_id)?;
+        let ingredients = self.decoder.fetch_dob_ingredients(spore_id).await?;
+        let stages = self
+            .decoder
+            .decode_spore_stages(&ingredients.spore, ingredients.cluster.metadata)
+            .await?;
+        let render_output = stages
+            .last()
+            .map(|stage| serde_json::to_string(&stage.output).unwrap())
+            .unwrap_or_default();
+        let result = serde_json::to_string(&ServerStagesDecodeResult {
+            render_output,
+            dob_content: ingredients.spore.content,
+            stages,
+        })
+        .unwrap();
+        Ok(result)
+    }
+
+    // list live spores belonging to particular cluster, and optionally render them
+    async fn list_cluster_spores(
+        &self,
+        hexed_cluster_id: String,
+        limit: u32,
+        cursor: Option<ClusterSporesCursor>,
+        with_render: Option<bool>,
+    ) -> Result<String, ErrorObjectOwned> {
+        tracing::info!("listing spores of cluster_id {hexed_cluster_id}");
+        let cluster_id = parse_cluster_id(&hexed_cluster_id)?;
+        let page = self
+            .decoder
+            .fetch_cluster_spores(cluster_id, limit, cursor)
+            .await?;
+        let dob_metadata = if with_render.unwrap_or_default() && !page.spores.is_empty() {
+            Some(self.decoder.fetch_dob_cluster(cluster_id).await?.metadata)
+        } else {
+            None
+        };
+        let mut spores = Vec::new();
+        for (spore_id, spore) in page.spores {
+            let (render_output, render_error) = match &dob_metadata {
+                Some(dob_metadata) => {
+                    match self
+                        .decoder
+                        .decode_spore(&spore, dob_metadata.clone())
+                        .await
+                    {
+                        Ok(render_output) => (Some(render_output), None),
+                        Err(error) => (None, Some(error.to_string())),
+                    }
+                }
+                None => (None, None),
+            };
+            spores.push(ServerClusterSpore {
+                spore_id: hex::encode(spore_id),
+                dob_content: spore.content,
+                spore: spore.cell,
+                render_output,
+                render_error,
+            });
+        }
+        let result = serde_json::to_string(&ServerClusterSporesPage {
+            spores,
+            cursor: page.next_cursor,
+        })
+        .unwrap();
+        Ok(result)
+    }
+
     // decode directly from spore and cluster data
     async fn raw_decode(
         &self,
@@ -127,32 +466,275 @@ impl DecoderRpcServer for DecoderStandaloneServer {
             .map_err(|_| Error::ClusterDataUncompatible)?;
         let dob = decode_spore_data(&spore_data)?;
         let dob_metadata = decode_cluster_data(&cluster_data)?;
-        let render_output = self.decoder.decode_dna(&dob.dna, dob_metadata).await?;
+        let render_output = self.decoder.decode_spore(&dob, dob_metadata).await?;
         let result = serde_json::to_string(&ServerDecodeResult {
             render_output,
             dob_content: dob.content,
+            finalized: None,
+            spore: None,
+            cluster_resolution: None,
         })
         .unwrap();
         tracing::info!("raw, result: {result}");
         Ok(result)
     }
+
+    // render DNAs under cluster description which isn't required to be on chain, DNAs are either
+    // passed in or generated randomly from seed, and the description is accepted in either JSON
+    // object or JSON string
+    async fn preview(
+        &self,
+        cluster_description: Value,
+        dna_list: Option<Vec<String>>,
+        random_count: Option<u32>,
+        seed: Option<u64>,
+    ) -> Result<String, ErrorObjectOwned> {
+        let dob_metadata: ClusterDescriptionField = match cluster_description {
+            Value::String(description) => serde_json::from_str(&description),
+            description => serde_json::from_value(description),
+        }
+        .map_err(|_| Error::DOBMetadataUnexpected)?;
+        self.decoder.protocol(dob_metadata.dob_version())?;
+        let mut dna_list = dna_list.unwrap_or_default();
+        // check the requested amount before generating anything
+        let requested_count = dna_list.len() + random_count.unwrap_or_default() as usize;
+        if requested_count == 0 {
+            return Err(Error::PreviewDNAListEmpty.into());
+        }
+        if requested_count > MAX_PREVIEW_DNA_COUNT {
+            return Err(Error::PreviewDNACountExceeded(MAX_PREVIEW_DNA_COUNT).into());
+        }
+        if dna_list
+            .iter()
+            .any(|dna| trim_0x(dna).len() > MAX_DNA_LENGTH * 2)
+        {
+            return Err(Error::PreviewDNALengthExceeded(MAX_DNA_LENGTH).into());
+        }
+        let seed = match random_count {
+            Some(random_count) => {
+                let seed = match seed {
+                    Some(seed) => seed,
+                    None => now()?.as_nanos() as u64,
+                };
+                let length = sample_dna_length(&dob_metadata).min(MAX_DNA_LENGTH);
+                dna_list.extend((0..random_count).map(|i| {
+                    let dna_seed = [seed.to_le_bytes(), (i as u64).to_le_bytes()].concat();
+                    generate_dna(&dna_seed, length)
+                }));
+                Some(seed)
+            }
+            None => None,
+        };
+        tracing::info!("previewing {} DNAs", dna_list.len());
+        let mut renders = Vec::new();
+        for dna in dna_list {
+            let dna = trim_0x(&dna).to_owned();
+            let (render_output, render_error) =
+                match self.decoder.decode_dna(&dna, dob_metadata.clone()).await {
+                    Ok(render_output) => (Some(render_output), None),
+                    Err(error) => (None, Some(error.to_string())),
+                };
+            renders.push(ServerPreviewRender {
+                dna,
+                render_output,
+                render_error,
+            });
+        }
+        Ok(serde_json::to_string(&ServerPreviewResult { seed, renders }).unwrap())
+    }
+
+    // trait distributions and spore rarities of cluster, which are only served once computed by
+    // the background job
+    async fn cluster_stats(&self, hexed_cluster_id: String) -> Result<String, ErrorObjectOwned> {
+        tracing::info!("fetching stats of cluster_id {hexed_cluster_id}");
+        let cluster_id = parse_cluster_id(&hexed_cluster_id)?;
+        let stats = self.decoder.fetch_cluster_stats(cluster_id)?;
+        Ok(serde_json::to_string(&stats).unwrap())
+    }
+
+    // rarity score and rank of spore in its cluster
+    async fn spore_rarity(&self, hexed_spore_id: String) -> Result<String, ErrorObjectOwned> {
+        tracing::info!("fetching rarity of spore_id {hexed_spore_id}");
+        let spore_id = parse_spore_id(&hexed_spore_id)?;
+        let rarity = self.decoder.fetch_spore_rarity(spore_id).await?;
+        Ok(serde_json::to_string(&rarity).unwrap())
+    }
+
+    // search spores in trait index of indexed clusters, matching all of the filters
+    async fn search(
+        &self,
+        filters: Vec<TraitFilter>,
+        hexed_cluster_id: Option<String>,
+        limit: u32,
+        cursor: Option<TraitSearchCursor>,
+        count_total: Option<bool>,
+    ) -> Result<String, ErrorObjectOwned> {
+        if limit == 0 {
+            return Err(Error::PageLimitInvalid.into());
+        }
+        let cluster_id = hexed_cluster_id
+            .map(|hexed_cluster_id| parse_cluster_id(&hexed_cluster_id).map(hex::encode))
+            .transpose()?;
+        let limit = (limit as usize).min(MAX_SEARCH_LIMIT);
+        let page = self.decoder.trait_index().search(
+            cluster_id.as_deref(),
+            &filters,
+            limit,
+            cursor.as_ref(),
+            count_total.unwrap_or_default(),
+        );
+        tracing::info!("searching traits, {} spores returned", page.spores.len());
+        Ok(serde_json::to_string(&page).unwrap())
+    }
+
+    // lint cluster description against reference decoders and render a sample DNA, the
+    // description is accepted in either JSON object or JSON string
+    async fn validate_cluster(
+        &self,
+        cluster_description: Value,
+        sample_dna: Option<String>,
+    ) -> Result<String, ErrorObjectOwned> {
+        let description = match cluster_description {
+            Value::String(description) => description,
+            description => description.to_string(),
+        };
+        let report = self
+            .decoder
+            .validate_cluster(&description, sample_dna.as_deref())
+            .await;
+        tracing::info!("validate cluster, valid: {}", report.valid);
+        Ok(serde_json::to_string(&report).unwrap())
+    }
+
+    // map DNA bytes to traits under the traits pattern of either the passed-in cluster description
+    // or the on-chain cluster, and slice DNA bytes of each trait if DNA is provided
+    async fn analyze_pattern(
+        &self,
+        cluster_description: Option<Value>,
+        hexed_cluster_id: Option<String>,
+        dna: Option<String>,
+    ) -> Result<String, ErrorObjectOwned> {
+        let analysis = match (cluster_description, hexed_cluster_id) {
+            (Some(cluster_description), None) => {
+                let dob_metadata: ClusterDescriptionField = match cluster_description {
+                    Value::String(description) => serde_json::from_str(&description),
+                    description => serde_json::from_value(description),
+                }
+                .map_err(|_| Error::DOBMetadataUnexpected)?;
+                analyze_traits_pattern(traits_pattern(&dob_metadata)?, dna.as_deref())?
+            }
+            (None, Some(hexed_cluster_id)) => {
+                tracing::info!("analyzing pattern of cluster_id {hexed_cluster_id}");
+                let cluster_id = parse_cluster_id(&hexed_cluster_id)?;
+                self.decoder
+                    .analyze_cluster_pattern(cluster_id, dna.as_deref())
+                    .await?
+            }
+            _ => {
+                return Err(Error::PatternAnalysisUnsupported(
+                    "either cluster description or cluster id is required".to_owned(),
+                )
+                .into())
+            }
+        };
+        Ok(serde_json::to_string(&analysis).unwrap())
+    }
+}
+
+// methods which are only served at `admin_rpc_server_address`, since they run arbitrary binaries
+#[rpc(server)]
+trait DecoderAdminRpc {
+    #[method(name = "dob_dry_run")]
+    async fn dry_run(
+        &self,
+        hexed_spore_id: String,
+        substitutes: Vec<ServerDecoderSubstitute>,
+    ) -> Result<String, ErrorObjectOwned>;
+}
+
+pub struct DecoderAdminServer {
+    decoder: Arc<DOBDecoder>,
+}
+
+impl DecoderAdminServer {
+    pub fn new(decoder: Arc<DOBDecoder>) -> Self {
+        clean_substitute_binaries(decoder.setting());
+        Self { decoder }
+    }
+}
+
+#[async_trait]
+impl DecoderAdminRpcServer for DecoderAdminServer {
+    // render spore by deployed decoders and by uploaded substitute binaries side by side
+    async fn dry_run(
+        &self,
+        hexed_spore_id: String,
+        substitutes: Vec<ServerDecoderSubstitute>,
+    ) -> Result<String, ErrorObjectOwned> {
+        tracing::info!("dry-running spore_id {hexed_spore_id}");
+        let spore_id = parse_spore_id(&hexed_spore_id)?;
+        // stored binaries are kept until the dry-run returns, and removed once dropped
+        let mut binaries = Vec::new();
+        let mut decoder_substitutes = Vec::new();
+        for substitute in substitutes {
+            let binary = hex::decode(trim_0x(&substitute.binary)).map_err(|_| {
+                Error::DecoderSubstituteInvalid("binary is not in hex format".to_owned())
+            })?;
+            let binary = store_substitute_binary(self.decoder.setting(), &binary)?;
+            decoder_substitutes.push(DecoderSubstitute {
+                decoder: substitute.decoder,
+                binary_path: binary.path().to_owned(),
+            });
+            binaries.push(binary);
+        }
+        let report = self
+            .decoder
+            .dry_run_spore(spore_id, decoder_substitutes)
+            .await?;
+        Ok(serde_json::to_string(&report).unwrap())
+    }
 }
 
 fn trim_0x(hexed: &str) -> &str {
     hexed.trim_start_matches("0x")
 }
 
+fn parse_spore_id(hexed_spore_id: &str) -> Result<[u8; 32], Error> {
+    hex::decode(trim_0x(hexed_spore_id))
+        .map_err(|_| Error::HexedSporeIdParseError)?
+        .try_into()
+        .map_err(|_| Error::SporeIdLengthInvalid)
+}
+
+fn parse_tx_hash(hexed_tx_hash: &str) -> Result<H256, Error> {
+    let tx_hash: [u8; 32] = hex::decode(trim_0x(hexed_tx_hash))
+        .map_err(|_| Error::HexedTxHashParseError)?
+        .try_into()
+        .map_err(|_| Error::HexedTxHashParseError)?;
+    Ok(H256(tx_hash))
+}
+
+fn parse_cluster_id(hexed_cluster_id: &str) -> Result<[u8; 32], Error> {
+    hex::decode(trim_0x(hexed_cluster_id))
+        .map_err(|_| Error::HexedClusterIdParseError)?
+        .try_into()
+        .map_err(|_| Error::ClusterIdLengthInvalid)
+}
+
+// cached DOB file consists of lines of render output, dob content, expiration timestamp and cluster
+// resolution, the last of which is missing in files cached by previous versions
 fn read_dob_from_cache(
     cache_path: PathBuf,
     mut expiration: u64,
-) -> Result<Option<(String, Value)>, Error> {
+) -> Result<Option<(String, Value, Option<ClusterResolution>)>, Error> {
     if !cache_path.exists() {
         return Ok(None);
     }
     let file_content = fs::read_to_string(&cache_path)
         .map_err(|_| Error::DOBRenderCacheNotFound(cache_path.clone()))?;
     let mut lines = file_content.split('\n');
-    let (Some(result), Some(content), timestamp) = (lines.next(), lines.next(), lines.next())
+    let (Some(result), Some(content), timestamp, resolution) =
+        (lines.next(), lines.next(), lines.next(), lines.next())
     else {
         return Err(Error::DOBRenderCacheModified(cache_path));
     };
@@ -163,21 +745,27 @@ fn read_dob_from_cache(
                 .map_err(|_| Error::DOBRenderCacheModified(cache_path.clone()))?;
         }
     }
+    let cluster_resolution = resolution
+        .filter(|value| !value.is_empty())
+        .map(serde_json::from_str)
+        .transpose()
+        .map_err(|_| Error::DOBRenderCacheModified(cache_path.clone()))?;
     match serde_json::from_str(content) {
         Ok(content) => {
             if expiration > 0 && now()? > Duration::from_secs(expiration) {
                 Ok(None)
             } else {
-                Ok(Some((result.to_string(), content)))
+                Ok(Some((result.to_string(), content, cluster_resolution)))
             }
         }
         Err(_) => Err(Error::DOBRenderCacheModified(cache_path)),
     }
 }
 
-fn write_dob_to_cache(
+pub fn write_dob_to_cache(
     render_result: &str,
     dob_content: &Value,
+    cluster_resolution: &ClusterResolution,
     cache_path: PathBuf,
     cache_expiration: u64,
 ) -> Result<(), Error> {
@@ -190,7 +778,9 @@ fn write_dob_to_cache(
         0 // zero means always read from cache
     };
     let json_dob_content = serde_json::to_string(dob_content).unwrap();
-    let file_content = format!("{render_result}\n{json_dob_content}\n{expiration_timestamp}");
+    let json_resolution = serde_json::to_string(cluster_resolution).unwrap();
+    let file_content =
+        format!("{render_result}\n{json_dob_content}\n{expiration_timestamp}\n{json_resolution}");
     fs::write(&cache_path, file_content).map_err(|_| Error::DOBRenderCacheNotFound(cache_path))?;
     Ok(())
 }
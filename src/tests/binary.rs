@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+
+use ckb_jsonrpc_types::OutPoint;
+use ckb_sdk::rpc::ckb_indexer::Cell;
+use ckb_types::H256;
+use serde_json::json;
+
+use crate::decoder::{
+    binary::{
+        add_discovery_miss, find_decoder_cell, is_intact, is_missed_since, is_recently_missed,
+        is_revalidation_due, persist_discovered_decoder, read_discovered_decoders,
+        record_discovery_miss, revalidate_record, stamp_record, DecoderBinaryRecord, Revalidation,
+    },
+    helpers::write_file_atomically,
+};
+use crate::types::{DecoderUpgradePolicy, OnchainDecoderDeployment, Settings};
+
+fn prepare_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("dob-decoder-binary-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn out_point(tx: u8) -> OutPoint {
+    serde_json::from_value(json!({
+        "tx_hash": format!("0x{}", hex::encode([tx; 32])),
+        "index": "0x0",
+    }))
+    .unwrap()
+}
+
+fn record(binary: &[u8], verified_at: u64) -> DecoderBinaryRecord {
+    DecoderBinaryRecord {
+        data_hash: H256(ckb_hash::blake2b_256(binary)),
+        out_point: out_point(1),
+        verified_at,
+        binary_size: 0,
+        binary_modified_at_ns: 0,
+        upgraded_data_hash: None,
+    }
+}
+
+#[test]
+fn test_concurrent_atomic_writes() {
+    let directory = prepare_directory("atomic");
+    let path = directory.join("decoder.bin");
+    let writers = (0..8u8)
+        .map(|writer| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    write_file_atomically(&path, &[writer; 4096]).expect("write");
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    writers
+        .into_iter()
+        .for_each(|writer| writer.join().unwrap());
+    let content = std::fs::read(&path).unwrap();
+    assert_eq!(content.len(), 4096);
+    assert!(content.iter().all(|byte| *byte == content[0]));
+    let files = std::fs::read_dir(&directory).unwrap().count();
+    assert_eq!(files, 1, "temporary files are left");
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_stamped_binary_check() {
+    let directory = prepare_directory("stamp");
+    let path = directory.join("decoder.bin");
+    std::fs::write(&path, b"decoder binary").unwrap();
+    let mut record = record(b"decoder binary", 0);
+
+    // unstamped record from previous versions is checked by hash
+    assert!(is_intact(&path, &record, false));
+    stamp_record(&path, &mut record).unwrap();
+    assert_eq!(record.binary_size, 14);
+    assert!(is_intact(&path, &record, false));
+    assert!(is_intact(&path, &record, true));
+
+    // tampered binary is trusted by its stamp until it's rehashed on revalidation
+    std::fs::write(&path, b"tampered binar").unwrap();
+    stamp_record(&path, &mut record).unwrap();
+    assert!(is_intact(&path, &record, false));
+    assert!(!is_intact(&path, &record, true));
+
+    // binary whose size or modification time differs from stamp is never intact
+    let mut resized = record.clone();
+    resized.binary_size += 1;
+    assert!(!is_intact(&path, &resized, false));
+    let mut touched = record.clone();
+    touched.binary_modified_at_ns += 1;
+    assert!(!is_intact(&path, &touched, false));
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(!is_intact(&path, &record, false));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_revalidation_due() {
+    let record = record(b"decoder binary", 100);
+    assert!(!is_revalidation_due(&record, 0, u64::MAX));
+    assert!(!is_revalidation_due(&record, 10, 109));
+    assert!(is_revalidation_due(&record, 10, 110));
+}
+
+#[test]
+fn test_revalidate_record_under_policies() {
+    let cached_hash = ckb_hash::blake2b_256(b"decoder binary");
+    let upgraded_hash = ckb_hash::blake2b_256(b"upgraded binary");
+
+    // live decoder cell is moved without upgrade, whose out point is followed
+    let mut unchanged = record(b"decoder binary", 0);
+    unchanged.upgraded_data_hash = Some(H256(upgraded_hash));
+    let revalidation = revalidate_record(
+        &mut unchanged,
+        Some((cached_hash, out_point(2))),
+        DecoderUpgradePolicy::Pin,
+    );
+    assert_eq!(revalidation, Revalidation::Unchanged);
+    assert_eq!(unchanged.out_point, out_point(2));
+    assert_eq!(unchanged.upgraded_data_hash, None);
+
+    let mut missing = record(b"decoder binary", 0);
+    let revalidation = revalidate_record(&mut missing, None, DecoderUpgradePolicy::Follow);
+    assert_eq!(revalidation, Revalidation::Unchanged);
+    assert_eq!(missing.out_point, out_point(1));
+
+    let mut followed = record(b"decoder binary", 0);
+    let revalidation = revalidate_record(
+        &mut followed,
+        Some((upgraded_hash, out_point(2))),
+        DecoderUpgradePolicy::Follow,
+    );
+    assert_eq!(revalidation, Revalidation::Replace);
+
+    // pinned binary keeps its out point, and only the upgraded hash is recorded
+    let mut pinned = record(b"decoder binary", 0);
+    let revalidation = revalidate_record(
+        &mut pinned,
+        Some((upgraded_hash, out_point(2))),
+        DecoderUpgradePolicy::Pin,
+    );
+    assert_eq!(revalidation, Revalidation::Pinned(H256(upgraded_hash)));
+    assert_eq!(pinned.out_point, out_point(1));
+    assert_eq!(pinned.data_hash, H256(cached_hash));
+    assert_eq!(pinned.upgraded_data_hash, Some(H256(upgraded_hash)));
+}
+
+fn cell(data: &[u8], tx: u8) -> Cell {
+    serde_json::from_value(json!({
+        "output": {
+            "capacity": "0x0",
+            "lock": {
+                "code_hash": format!("0x{}", hex::encode([0u8; 32])),
+                "hash_type": "type",
+                "args": "0x",
+            },
+            "type": null,
+        },
+        "output_data": format!("0x{}", hex::encode(data)),
+        "out_point": {
+            "tx_hash": format!("0x{}", hex::encode([tx; 32])),
+            "index": "0x0",
+        },
+        "block_number": "0x1",
+        "tx_index": "0x0",
+    }))
+    .expect("indexer cell")
+}
+
+#[test]
+fn test_find_decoder_cell_by_code_hash() {
+    let code_hash = H256(ckb_hash::blake2b_256(b"decoder binary"));
+    let cells = vec![
+        cell(b"other binary", 1),
+        cell(b"decoder binary", 2),
+        cell(b"decoder binary", 3),
+    ];
+    let (binary, out_point) = find_decoder_cell(cells, &code_hash).expect("decoder found");
+    assert_eq!(binary, b"decoder binary");
+    assert_eq!(out_point.tx_hash.0, [2; 32]);
+    assert!(find_decoder_cell(vec![cell(b"other binary", 1)], &code_hash).is_none());
+}
+
+#[test]
+fn test_discovery_miss_expiration() {
+    let missed = H256([1; 32]);
+    let expired = H256([2; 32]);
+    let mut misses = Vec::new();
+    add_discovery_miss(&mut misses, &expired, 0);
+    add_discovery_miss(&mut misses, &missed, 1000);
+    assert!(is_missed_since(&misses, &missed, 1000));
+    assert!(is_missed_since(&misses, &missed, 1599));
+    assert!(!is_missed_since(&misses, &missed, 1600));
+    // expired miss is dropped as another miss is added
+    assert_eq!(misses.len(), 1);
+    assert!(!is_missed_since(&misses, &expired, 1000));
+
+    // missing again refreshes the miss rather than duplicating it
+    add_discovery_miss(&mut misses, &missed, 1500);
+    assert_eq!(misses.len(), 1);
+    assert!(is_missed_since(&misses, &missed, 2000));
+}
+
+#[tokio::test]
+async fn test_concurrent_discovery_records() {
+    let directory = prepare_directory("discovery");
+    let settings = Settings {
+        decoders_cache_directory: directory.clone(),
+        ..Default::default()
+    };
+    let persists = (0..16u8).map(|index| {
+        persist_discovered_decoder(
+            &settings,
+            OnchainDecoderDeployment {
+                code_hash: H256([index; 32]),
+                tx_hash: H256([index; 32]),
+                out_index: index as u32,
+            },
+        )
+    });
+    futures::future::try_join_all(persists).await.unwrap();
+    assert_eq!(read_discovered_decoders(&settings).len(), 16);
+
+    let misses = (0..16u8).map(|index| record_discovery_miss(&settings, &H256([index; 32])));
+    futures::future::try_join_all(misses).await.unwrap();
+    for index in 0..16u8 {
+        assert!(is_recently_missed(&settings, &H256([index; 32])).unwrap());
+    }
+    assert!(!is_recently_missed(&settings, &H256([0xff; 32])).unwrap());
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,116 @@
+use ckb_sdk::rpc::ckb_indexer::Cell;
+use ckb_types::{packed, prelude::Entity};
+use serde_json::{json, Value};
+
+use crate::{decoder::cluster::match_cluster_reference, types::ClusterResolution};
+
+const CLUSTER_ID: [u8; 32] = [1; 32];
+const PROXY_CODE_HASH: [u8; 32] = [0xaa; 32];
+const AGENT_CODE_HASH: [u8; 32] = [0xbb; 32];
+
+fn type_script(code_hash: [u8; 32], args: &[u8]) -> Value {
+    json!({
+        "code_hash": format!("0x{}", hex::encode(code_hash)),
+        "hash_type": "data1",
+        "args": format!("0x{}", hex::encode(args)),
+    })
+}
+
+fn cell(type_script: Value, data: &[u8], tx: u8) -> Cell {
+    serde_json::from_value(json!({
+        "output": {
+            "capacity": "0x0",
+            "lock": {
+                "code_hash": format!("0x{}", hex::encode([0u8; 32])),
+                "hash_type": "type",
+                "args": "0x",
+            },
+            "type": type_script,
+        },
+        "output_data": format!("0x{}", hex::encode(data)),
+        "out_point": {
+            "tx_hash": format!("0x{}", hex::encode([tx; 32])),
+            "index": "0x0",
+        },
+        "block_number": "0x1",
+        "tx_index": "0x0",
+    }))
+    .expect("indexer cell")
+}
+
+// proxy cell refers to cluster in data, and its type args are proxy id and minimal payment
+fn proxy_cell(cluster_id: [u8; 32], tx: u8) -> Cell {
+    let mut args = [tx; 32].to_vec();
+    args.push(2);
+    cell(type_script(PROXY_CODE_HASH, &args), &cluster_id, tx)
+}
+
+// agent cell refers to cluster in data, and to proxy by the type hash of proxy in its type args
+fn agent_cell(cluster_id: [u8; 32], proxy: &Cell, tx: u8) -> Cell {
+    let proxy_type: packed::Script = proxy.output.type_.clone().unwrap().into();
+    let proxy_type_hash = proxy_type.calc_script_hash();
+    cell(
+        type_script(AGENT_CODE_HASH, proxy_type_hash.as_slice()),
+        &cluster_id,
+        tx,
+    )
+}
+
+fn out_point_tx(out_point: &ckb_jsonrpc_types::OutPoint) -> u8 {
+    out_point.tx_hash.0[0]
+}
+
+#[test]
+fn test_cluster_referenced_by_proxy() {
+    let proxies = [proxy_cell([2; 32], 2), proxy_cell(CLUSTER_ID, 3)];
+    match match_cluster_reference(CLUSTER_ID, &proxies, &[]) {
+        Some(ClusterResolution::Proxy {
+            proxy_out_point,
+            cluster_id,
+        }) => {
+            assert_eq!(out_point_tx(&proxy_out_point), 3);
+            assert_eq!(cluster_id.0, CLUSTER_ID);
+        }
+        _ => panic!("cluster should be referenced by proxy"),
+    }
+}
+
+#[test]
+fn test_cluster_referenced_by_agent() {
+    let proxies = [proxy_cell(CLUSTER_ID, 2), proxy_cell(CLUSTER_ID, 3)];
+    let agents = [agent_cell(CLUSTER_ID, &proxies[1], 4)];
+    match match_cluster_reference(CLUSTER_ID, &proxies, &agents) {
+        Some(ClusterResolution::Agent {
+            agent_out_point,
+            proxy_out_point,
+            cluster_id,
+        }) => {
+            assert_eq!(out_point_tx(&agent_out_point), 4);
+            assert_eq!(out_point_tx(&proxy_out_point), 3);
+            assert_eq!(cluster_id.0, CLUSTER_ID);
+        }
+        _ => panic!("cluster should be referenced by agent"),
+    }
+}
+
+#[test]
+fn test_agent_without_matched_proxy() {
+    let proxies = [proxy_cell(CLUSTER_ID, 2)];
+    // agent whose type args are the cluster id rather than the type hash of proxy
+    let agents = [cell(
+        type_script(AGENT_CODE_HASH, &CLUSTER_ID),
+        &CLUSTER_ID,
+        4,
+    )];
+    assert!(matches!(
+        match_cluster_reference(CLUSTER_ID, &proxies, &agents),
+        Some(ClusterResolution::Proxy { .. })
+    ));
+    // agent of proxy referring to another cluster
+    let other_proxy = proxy_cell([2; 32], 5);
+    let agents = [agent_cell(CLUSTER_ID, &other_proxy, 6)];
+    assert!(matches!(
+        match_cluster_reference(CLUSTER_ID, &[other_proxy], &agents),
+        None
+    ));
+}
@@ -0,0 +1,106 @@
+use serde_json::json;
+
+use crate::decoder::content::{decode_content, ContentType};
+use crate::types::Error;
+
+const DNA: &str = "eda7a47a751d2dc42d4b724e47cfd67a";
+
+#[test]
+fn test_decode_empty_content() {
+    let result = decode_content(&ContentType::parse("dob/1"), &[]);
+    assert!(matches!(result, Err(Error::SporeContentEmpty)));
+}
+
+#[test]
+fn test_decode_raw_content() {
+    let dna = hex::decode(DNA).unwrap();
+    let mut prefixed = vec![0u8];
+    prefixed.extend_from_slice(&dna);
+    let (_, auto_dna) = decode_content(&ContentType::parse("dob/1"), &prefixed).unwrap();
+    assert_eq!(auto_dna, DNA);
+    let (_, raw_dna) = decode_content(&ContentType::parse("dob/1;encoding=raw"), &dna).unwrap();
+    assert_eq!(raw_dna, DNA);
+}
+
+#[test]
+fn test_decode_molecule_content() {
+    let dna = hex::decode(DNA).unwrap();
+    let mut content = (dna.len() as u32).to_le_bytes().to_vec();
+    content.extend_from_slice(&dna);
+    let (_, molecule_dna) =
+        decode_content(&ContentType::parse("dob/0; encoding=molecule"), &content).unwrap();
+    assert_eq!(molecule_dna, DNA);
+}
+
+#[test]
+fn test_decode_cbor_content() {
+    // {"dna": <text DNA>, "id": 7}
+    let mut content = vec![0xa2, 0x63];
+    content.extend_from_slice(b"dna");
+    content.push(0x60 + DNA.len() as u8);
+    content.extend_from_slice(DNA.as_bytes());
+    content.push(0x62);
+    content.extend_from_slice(b"id");
+    content.push(0x07);
+    let (value, cbor_dna) =
+        decode_content(&ContentType::parse("dob/1;encoding=\"cbor\""), &content).unwrap();
+    assert_eq!(cbor_dna, DNA);
+    assert_eq!(value, json!({"dna": DNA, "id": 7}));
+
+    // [<bytes DNA>]
+    let dna = hex::decode(DNA).unwrap();
+    let mut content = vec![0x81, 0x40 + dna.len() as u8];
+    content.extend_from_slice(&dna);
+    let (_, cbor_dna) =
+        decode_content(&ContentType::parse("dob/1;encoding=cbor"), &content).unwrap();
+    assert_eq!(cbor_dna, DNA);
+}
+
+#[test]
+fn test_decode_cbor_content_edges() {
+    let content_type = ContentType::parse("dob/1;encoding=cbor");
+    // {"dna": <text DNA>, "rate": 1.0 in half float, "tag": 1(-2)}
+    let mut content = vec![0xa3, 0x63];
+    content.extend_from_slice(b"dna");
+    content.push(0x60 + DNA.len() as u8);
+    content.extend_from_slice(DNA.as_bytes());
+    content.push(0x64);
+    content.extend_from_slice(b"rate");
+    content.extend_from_slice(&[0xf9, 0x3c, 0x00]);
+    content.push(0x63);
+    content.extend_from_slice(b"tag");
+    content.extend_from_slice(&[0xc1, 0x21]);
+    let (value, _) = decode_content(&content_type, &content).unwrap();
+    assert_eq!(value, json!({"dna": DNA, "rate": 1.0, "tag": -2}));
+
+    // trailing bytes after the document
+    let mut trailing = content.clone();
+    trailing.push(0x00);
+    let result = decode_content(&content_type, &trailing);
+    assert!(matches!(result, Err(Error::SporeContentMalformed(..))));
+
+    // nesting beyond the limit
+    let nested = [vec![0x81; 64], vec![0x60]].concat();
+    let result = decode_content(&content_type, &nested);
+    assert!(matches!(result, Err(Error::SporeContentMalformed(..))));
+
+    // map key must be a string
+    let result = decode_content(&content_type, &[0xa1, 0x01, 0x60]);
+    assert!(matches!(result, Err(Error::SporeContentMalformed(..))));
+}
+
+#[test]
+fn test_decode_content_failures() {
+    let content_type = ContentType::parse("dob/1");
+    let result = decode_content(&content_type, b"{\"id\": 1}");
+    assert!(matches!(result, Err(Error::SporeContentDNANotFound(_))));
+    let result = decode_content(&content_type, b"[1]");
+    assert!(matches!(result, Err(Error::SporeContentDNANotFound(_))));
+    let result = decode_content(&content_type, b"not json");
+    assert!(matches!(result, Err(Error::SporeContentMalformed(..))));
+    let result = decode_content(&ContentType::parse("dob/1;encoding=yaml"), b"dna");
+    assert!(matches!(
+        result,
+        Err(Error::SporeContentEncodingUnsupported(_))
+    ));
+}
@@ -16,7 +16,9 @@ const UNICORN_SPORE_ID: H256 =
 const EXAMPLE_SPORE_ID: H256 =
     h256!("0x683d0362a2e67d6edc80e3bf16136fae8a7fba21f6cb013931c5994c9ddb8d70");
 
-fn generate_unicorn_dob_ingredients(onchain_decoder: bool) -> (Value, ClusterDescriptionField) {
+pub(crate) fn generate_unicorn_dob_ingredients(
+    onchain_decoder: bool,
+) -> (Value, ClusterDescriptionField) {
     let unicorn_content = json!({
         "block_number": 120,
         "cell_id": 11844,
@@ -49,7 +51,9 @@ fn generate_unicorn_dob_ingredients(onchain_decoder: bool) -> (Value, ClusterDes
     (unicorn_content, unicorn_metadata)
 }
 
-fn generate_example_dob_ingredients(onchain_decoder: bool) -> (Value, ClusterDescriptionField) {
+pub(crate) fn generate_example_dob_ingredients(
+    onchain_decoder: bool,
+) -> (Value, ClusterDescriptionField) {
     let example_content = json!({
         "block_number": 120,
         "cell_id": 11844,
@@ -137,3 +141,28 @@ fn test_example_json_serde() {
     println!("[spore_content] = {json_content}");
     println!("[cluster_description] = {json_metadata}");
 }
+
+#[test]
+fn test_malformed_metadata_of_selected_version() {
+    let decoder = json!({
+        "type": "code_hash",
+        "hash": "0x32f29aba4b17f3d05bec8cec55d50ef86766fd0bf82fdedaa14269f344d3784a",
+    });
+    // format of the version selected by `ver` reports why it's malformed
+    let result = serde_json::from_value::<DOBClusterFormat>(json!({ "decoder": decoder }));
+    let error = result.err().expect("missing pattern").to_string();
+    assert!(error.contains("pattern"), "{error}");
+    let result = serde_json::from_value::<DOBClusterFormat>(json!({ "ver": 1, "decoders": 1 }));
+    assert!(result.is_err());
+
+    // malformed formats of other versions are ignored
+    let dob = serde_json::from_value::<DOBClusterFormat>(json!({
+        "ver": 0,
+        "decoder": decoder,
+        "pattern": [],
+        "decoders": 1,
+    }))
+    .expect("dob/0 metadata");
+    assert!(dob.dob_ver_0.is_some());
+    assert!(dob.dob_ver_1.is_none());
+}
@@ -1,2 +1,2 @@
-mod decoder;
+pub(crate) mod decoder;
 mod legacy_decoder;
@@ -2,7 +2,10 @@ use ckb_types::h256;
 use serde_json::{json, Value};
 
 use crate::{
-    decoder::DOBDecoder,
+    decoder::{
+        protocol::{Dob1Protocol, DobProtocol},
+        DOBDecoder,
+    },
     tests::prepare_settings,
     types::{
         ClusterDescriptionField, DOBClusterFormat, DOBClusterFormatV0, DOBClusterFormatV1,
@@ -10,7 +13,7 @@ use crate::{
     },
 };
 
-fn generate_dob1_ingredients() -> (Value, ClusterDescriptionField) {
+pub(crate) fn generate_dob1_ingredients() -> (Value, ClusterDescriptionField) {
     let content = json!({
         "dna": "ac7b88aabbcc687474703a2f2f3132372e302e302e313a383039300000"
     });
@@ -67,3 +70,17 @@ async fn test_dob1_basic_decode() {
     let render_result = decoder.decode_dna(dna, dob_metadata).await.expect("decode");
     println!("\nrender_result: {}", render_result);
 }
+
+#[tokio::test]
+async fn test_dob1_stages_decode() {
+    let settings = prepare_settings("dob/1");
+    let (content, dob_metadata) = generate_dob1_ingredients();
+    let decoder = DOBDecoder::new(settings);
+    let dna = content.get("dna").unwrap().as_str().unwrap();
+    let stages = Dob1Protocol
+        .decode_stages(&decoder, dna, &dob_metadata.dob)
+        .await
+        .expect("decode stages");
+    assert_eq!(stages.len(), 2);
+    assert!(stages.iter().all(|stage| stage.cycles > 0));
+}
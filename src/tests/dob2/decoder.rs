@@ -0,0 +1,98 @@
+use serde_json::Value;
+
+use crate::{
+    decoder::{
+        protocol::{Dob2Protocol, DobProtocol},
+        DOBDecoder,
+    },
+    tests::prepare_settings,
+    types::{ClusterDescriptionField, DOBDecoderStage, DOBStageKind, Error},
+};
+
+const CLUSTER_DESCRIPTION: &str = include_str!("cluster_description.json");
+const DNA: &str = "ac7b88aabbcc687474703a2f2f3132372e302e302e313a383039300000";
+
+fn generate_dob2_metadata() -> ClusterDescriptionField {
+    serde_json::from_str(CLUSTER_DESCRIPTION).expect("parse dob/2 cluster description")
+}
+
+#[test]
+fn test_dob2_parse_metadata() {
+    let dob_metadata = generate_dob2_metadata();
+    assert_eq!(dob_metadata.dob_version(), 2);
+    let dob2 = dob_metadata.dob.dob_ver_2.as_ref().expect("dob/2 format");
+    let kinds = dob2
+        .stages
+        .iter()
+        .map(|stage| stage.kind)
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, vec![DOBStageKind::Traits, DOBStageKind::Render]);
+    let decoders = Dob2Protocol
+        .parse_metadata(&dob_metadata.dob)
+        .expect("parse stages");
+    assert_eq!(decoders.len(), 2);
+}
+
+#[test]
+fn test_dob2_duplicate_stage_name() {
+    let mut dob_metadata = generate_dob2_metadata();
+    let dob2 = dob_metadata.dob.dob_ver_2.as_mut().unwrap();
+    dob2.stages[1].name = dob2.stages[0].name.clone();
+    let result = Dob2Protocol.parse_metadata(&dob_metadata.dob);
+    assert!(matches!(result, Err(Error::DOBStageInvalid(..))));
+}
+
+#[tokio::test]
+async fn test_dob2_staged_decode() {
+    let settings = prepare_settings("dob/2");
+    let decoder = DOBDecoder::new(settings);
+    let render_result = decoder
+        .decode_dna(DNA, generate_dob2_metadata())
+        .await
+        .expect("decode");
+    println!("\nrender_result: {}", render_result);
+    let stage_outputs: Vec<Value> = serde_json::from_str(&render_result).unwrap();
+    let stages = stage_outputs
+        .iter()
+        .map(|output| output["stage"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(stages, vec!["traits", "image"]);
+}
+
+#[tokio::test]
+async fn test_dob2_cached_stages_decode() {
+    let cache_directory =
+        std::env::temp_dir().join(format!("dob-decoder-dob2-stages-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_directory);
+    let mut settings = prepare_settings("dob/2");
+    settings.decoder_stages_cache_directory = Some(cache_directory.clone());
+    let decoder = DOBDecoder::new(settings);
+    let dob_metadata = generate_dob2_metadata();
+    let stages = Dob2Protocol
+        .decode_stages(&decoder, DNA, &dob_metadata.dob)
+        .await
+        .expect("decode stages");
+    assert_eq!(stages.len(), 2);
+    assert!(stages.iter().all(|stage| !stage.cached && stage.cycles > 0));
+
+    // both traits and render stages are loaded from cache on the second run
+    let cached_stages = Dob2Protocol
+        .decode_stages(&decoder, DNA, &dob_metadata.dob)
+        .await
+        .expect("decode cached stages");
+    assert!(cached_stages.iter().all(|stage| stage.cached));
+    let outputs = |stages: &[DOBDecoderStage]| {
+        stages
+            .iter()
+            .map(|stage| stage.output.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(outputs(&stages), outputs(&cached_stages));
+    let render_result = decoder
+        .decode_dna(DNA, generate_dob2_metadata())
+        .await
+        .expect("decode");
+    let stage_outputs: Vec<Value> = serde_json::from_str(&render_result).unwrap();
+    assert_eq!(stage_outputs[1]["output"], stages[1].output);
+    std::fs::remove_dir_all(cache_directory).unwrap();
+}
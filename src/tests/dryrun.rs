@@ -0,0 +1,39 @@
+use serde_json::json;
+
+use crate::{decoder::dryrun::diff_render_outputs, types::OutputDifference};
+
+#[test]
+fn test_diff_identical_render_outputs() {
+    let output = r#"[{"name":"Wings","traits":[{"String":"Sun Wings"}]}]"#;
+    assert!(diff_render_outputs(output, output).is_empty());
+}
+
+#[test]
+fn test_diff_render_outputs_by_value() {
+    let deployed = r#"[{"name":"Wings","traits":[{"String":"Sun Wings"}]},{"name":"Lucky Number","traits":[{"Number":35}]}]"#;
+    let dry_run = r#"[{"name":"Wings","traits":[{"String":"Moon Wings"}]},{"name":"Lucky Number","traits":[{"Number":35}]},{"name":"Tail","traits":[{"String":"Meteor Tail"}]}]"#;
+    let differences = diff_render_outputs(deployed, dry_run);
+    assert_eq!(
+        differences,
+        vec![
+            OutputDifference {
+                path: "/0/traits/0/String".to_owned(),
+                deployed: Some(json!("Sun Wings")),
+                dry_run: Some(json!("Moon Wings")),
+            },
+            OutputDifference {
+                path: "/2".to_owned(),
+                deployed: None,
+                dry_run: Some(json!({"name": "Tail", "traits": [{"String": "Meteor Tail"}]})),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_diff_plain_render_outputs() {
+    let differences = diff_render_outputs("<svg></svg>", "<svg/>");
+    assert_eq!(differences.len(), 1);
+    assert_eq!(differences[0].path, "");
+    assert_eq!(differences[0].deployed, Some(json!("<svg></svg>")));
+}
@@ -0,0 +1,119 @@
+use ckb_sdk::rpc::ckb_indexer::Tx;
+use serde_json::json;
+
+use crate::{
+    decoder::history::{classify_spore_event, fold_cells_page, CellTransaction},
+    types::SporeEventType,
+};
+
+fn cell(tx: u8, io_type: &str, io_index: u32) -> Tx {
+    serde_json::from_value(json!({
+        "tx_hash": format!("0x{}", hex::encode([tx; 32])),
+        "block_number": format!("{:#x}", tx),
+        "tx_index": "0x0",
+        "io_index": format!("{io_index:#x}"),
+        "io_type": io_type,
+    }))
+    .expect("indexer cell")
+}
+
+// transaction 1 mints, 2 transfers across the page boundary, 3 transfers and 4 melts
+fn indexer_pages() -> Vec<Vec<Tx>> {
+    vec![
+        vec![cell(1, "output", 0), cell(2, "input", 0)],
+        vec![
+            cell(2, "output", 1),
+            cell(3, "input", 0),
+            cell(3, "output", 0),
+        ],
+        vec![cell(4, "input", 2)],
+    ]
+}
+
+// fold pages from `(page, skipped_cells)` like paging through indexer, and return the position to
+// continue from, none if all pages are folded
+fn fold_pages(
+    pages: &[Vec<Tx>],
+    position: (usize, u32),
+    limit: usize,
+    cell_txs: &mut Vec<CellTransaction>,
+) -> Option<(usize, u32)> {
+    let (first_page, mut skipped_cells) = position;
+    for (page_index, page) in pages.iter().enumerate().skip(first_page) {
+        if let Some(index) = fold_cells_page(page.clone(), skipped_cells, limit, cell_txs) {
+            return Some((page_index, index));
+        }
+        skipped_cells = 0;
+    }
+    None
+}
+
+fn events(cell_txs: &[CellTransaction]) -> Vec<(u8, Option<SporeEventType>)> {
+    cell_txs
+        .iter()
+        .map(|cell_tx| (cell_tx.tx_hash.0[0], classify_spore_event(cell_tx)))
+        .collect()
+}
+
+#[test]
+fn test_fold_all_spore_events() {
+    let mut cell_txs = Vec::new();
+    assert_eq!(
+        fold_pages(&indexer_pages(), (0, 0), 10, &mut cell_txs),
+        None
+    );
+    assert_eq!(
+        events(&cell_txs),
+        vec![
+            (1, Some(SporeEventType::Mint)),
+            (2, Some(SporeEventType::Transfer)),
+            (3, Some(SporeEventType::Transfer)),
+            (4, Some(SporeEventType::Melt)),
+        ]
+    );
+    assert_eq!(cell_txs[1].output_index, Some(1));
+    assert_eq!(cell_txs[3].block_number, 4);
+}
+
+#[test]
+fn test_resume_transaction_split_across_pages() {
+    let pages = indexer_pages();
+    // the second transaction starts at the end of the first page, so that the page is resumed
+    // from its second cell rather than skipped
+    let mut first_round = Vec::new();
+    let position = fold_pages(&pages, (0, 0), 1, &mut first_round);
+    assert_eq!(position, Some((0, 1)));
+    assert_eq!(events(&first_round), vec![(1, Some(SporeEventType::Mint))]);
+
+    // the split transaction is listed completely, and the next round starts within the page
+    let mut second_round = Vec::new();
+    let position = fold_pages(&pages, position.unwrap(), 1, &mut second_round);
+    assert_eq!(position, Some((1, 1)));
+    assert_eq!(
+        events(&second_round),
+        vec![(2, Some(SporeEventType::Transfer))]
+    );
+    assert!(second_round[0].consumed);
+
+    let mut last_round = Vec::new();
+    let position = fold_pages(&pages, position.unwrap(), 2, &mut last_round);
+    assert_eq!(position, None);
+    assert_eq!(
+        events(&last_round),
+        vec![
+            (3, Some(SporeEventType::Transfer)),
+            (4, Some(SporeEventType::Melt)),
+        ]
+    );
+}
+
+#[test]
+fn test_classify_transaction_without_spore_change() {
+    let cell_tx = CellTransaction {
+        tx_hash: Default::default(),
+        block_number: 0,
+        consumed: false,
+        output_index: None,
+    };
+    assert_eq!(classify_spore_event(&cell_tx), None);
+}
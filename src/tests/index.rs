@@ -0,0 +1,125 @@
+use serde_json::json;
+
+use crate::{
+    decoder::index::TraitIndex,
+    types::{ClusterTraitIndex, DOBTrait, IndexedSpore, TraitFilter},
+};
+
+fn indexed_spore(spore_id: &str, wings: &str, lucky_number: u64) -> IndexedSpore {
+    IndexedSpore {
+        spore_id: spore_id.to_owned(),
+        traits: [
+            ("Wings".to_owned(), DOBTrait::String(wings.to_owned())),
+            (
+                "Lucky Number".to_owned(),
+                DOBTrait::Number(lucky_number.into()),
+            ),
+        ]
+        .into_iter()
+        .collect(),
+    }
+}
+
+fn prepare_index() -> TraitIndex {
+    let index = TraitIndex::load(None);
+    index.update(ClusterTraitIndex {
+        cluster_id: "aa".to_owned(),
+        indexed_at: 0,
+        spores: vec![
+            indexed_spore("01", "Sun Wings", 35),
+            indexed_spore("02", "Moon Wings", 7),
+            indexed_spore("03", "Sun Wings", 12),
+        ],
+    });
+    index.update(ClusterTraitIndex {
+        cluster_id: "bb".to_owned(),
+        indexed_at: 0,
+        spores: vec![indexed_spore("04", "Sun Wings", 40)],
+    });
+    index
+}
+
+#[test]
+fn test_search_traits_by_equality_and_range() {
+    let index = prepare_index();
+    let sun_wings = TraitFilter {
+        name: "Wings".to_owned(),
+        equals: Some(json!("Sun Wings")),
+        ..Default::default()
+    };
+    let page = index.search(None, &[sun_wings.clone()], 10, None, true);
+    assert_eq!(page.total, Some(3));
+    assert!(page.cursor.is_none());
+
+    let lucky_range = TraitFilter {
+        name: "Lucky Number".to_owned(),
+        min: Some(20.0),
+        max: Some(40.0),
+        ..Default::default()
+    };
+    let page = index.search(None, &[sun_wings.clone(), lucky_range], 10, None, false);
+    assert!(page.total.is_none());
+    let spore_ids = page
+        .spores
+        .iter()
+        .map(|spore| spore.spore_id.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(spore_ids, vec!["01", "04"]);
+
+    let page = index.search(Some("bb"), &[sun_wings], 10, None, true);
+    assert_eq!(page.total, Some(1));
+    let lucky_number = TraitFilter {
+        name: "Lucky Number".to_owned(),
+        equals: Some(json!(7.0)),
+        ..Default::default()
+    };
+    assert_eq!(
+        index.search(None, &[lucky_number], 10, None, true).total,
+        Some(1)
+    );
+}
+
+#[test]
+fn test_search_traits_pagination() {
+    let index = prepare_index();
+    let first_page = index.search(None, &[], 3, None, true);
+    assert_eq!(first_page.total, Some(4));
+    assert_eq!(first_page.spores.len(), 3);
+    let cursor = first_page.cursor.expect("cursor of next page");
+    assert_eq!(
+        (cursor.cluster_id.as_str(), cursor.spore_id.as_str()),
+        ("aa", "03")
+    );
+    let second_page = index.search(None, &[], 3, Some(&cursor), false);
+    assert_eq!(second_page.spores.len(), 1);
+    assert_eq!(second_page.spores[0].spore_id, "04");
+    assert!(second_page.cursor.is_none());
+
+    // a page filled up by the last matches has no next page
+    let full_page = index.search(None, &[], 4, None, false);
+    assert_eq!(full_page.spores.len(), 4);
+    assert!(full_page.cursor.is_none());
+}
+
+#[test]
+fn test_store_and_reload_index() {
+    let directory =
+        std::env::temp_dir().join(format!("dob-decoder-trait-index-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    let index = TraitIndex::load(Some(directory.clone()));
+    index.update(ClusterTraitIndex {
+        cluster_id: "aa".to_owned(),
+        indexed_at: 10,
+        spores: vec![indexed_spore("01", "Sun Wings", 35)],
+    });
+    // truncated index and leftover temporary file are skipped on loading
+    std::fs::write(directory.join("bb.json"), b"{\"cluster_id\":").unwrap();
+    std::fs::write(directory.join("aa.json.1.0.tmp"), b"{}").unwrap();
+    let files = std::fs::read_dir(&directory).unwrap().count();
+    assert_eq!(files, 3);
+
+    let reloaded = TraitIndex::load(Some(directory.clone()));
+    assert_eq!(reloaded.indexed_at("aa"), Some(10));
+    assert_eq!(reloaded.indexed_at("bb"), None);
+    std::fs::remove_dir_all(directory).unwrap();
+}
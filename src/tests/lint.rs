@@ -0,0 +1,183 @@
+use serde_json::json;
+
+use crate::{
+    decoder::{
+        helpers::generate_dna,
+        lint::{lint_patterns, lint_svg_pattern, lint_traits_pattern, sample_dna_length},
+        DOBDecoder,
+    },
+    tests::{
+        dob0::decoder::{generate_example_dob_ingredients, generate_unicorn_dob_ingredients},
+        dob1::decoder::generate_dob1_ingredients,
+        prepare_settings,
+    },
+    types::{ClusterDescriptionField, DiagnosticSeverity},
+};
+
+fn traits_pattern() -> serde_json::Value {
+    json!([
+        [
+            "Name",
+            "String",
+            0,
+            1,
+            "options",
+            ["Alice", "Bob", "Charlie", "David"]
+        ],
+        ["Age", "Number", 1, 1, "range", [0, 100]],
+        ["Score", "Number", 2, 1, "rawNumber"],
+        ["_URL", "string", 6, 21, "utf8"]
+    ])
+}
+
+#[test]
+fn test_lint_valid_traits_pattern() {
+    let lint = lint_traits_pattern(&traits_pattern(), "dob.pattern", Some(27));
+    assert!(lint.diagnostics.is_empty(), "{:?}", lint.diagnostics);
+    assert_eq!(lint.trait_names, vec!["Name", "Age", "Score", "_URL"]);
+    assert_eq!(lint.required_dna_length, 27);
+}
+
+#[test]
+fn test_lint_traits_pattern_out_of_range() {
+    let lint = lint_traits_pattern(&traits_pattern(), "dob.pattern", Some(3));
+    let codes: Vec<_> = lint
+        .diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.code, diagnostic.location.as_str()))
+        .collect();
+    assert_eq!(
+        codes,
+        vec![("pattern_offset_out_of_range", "dob.pattern[3]")]
+    );
+}
+
+#[test]
+fn test_lint_traits_pattern_beyond_max_dna_length() {
+    let pattern = json!([["Seed", "String", 4294967295u64, 32, "rawString"]]);
+    let lint = lint_traits_pattern(&pattern, "dob.pattern", None);
+    let codes: Vec<_> = lint
+        .diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.code, diagnostic.location.as_str()))
+        .collect();
+    assert_eq!(
+        codes,
+        vec![("pattern_offset_out_of_range", "dob.pattern[0]")]
+    );
+    assert_eq!(lint.required_dna_length, 0);
+}
+
+#[test]
+fn test_lint_malformed_traits_pattern() {
+    let pattern = json!([
+        ["Age", "Number", 1, 1],
+        ["Age", "Number", 1, 1, "range", [100, 0]],
+        ["Age", "Boolean", 1, 1, "options", []],
+        ["Score", "String", 2, 9, "rawNumber"],
+        ["Level", "Number", 3, 1, "dice"]
+    ]);
+    let lint = lint_traits_pattern(&pattern, "dob.pattern", None);
+    let codes: Vec<_> = lint
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.code)
+        .collect();
+    assert_eq!(
+        codes,
+        vec![
+            "pattern_item_malformed",
+            "pattern_args_invalid",
+            "pattern_name_duplicated",
+            "pattern_type_unknown",
+            "pattern_args_invalid",
+            "pattern_length_invalid",
+            "pattern_type_mismatch",
+            "pattern_kind_unknown",
+        ]
+    );
+}
+
+#[test]
+fn test_lint_svg_pattern() {
+    let pattern = json!([
+        [
+            "IMAGE.0",
+            "attributes",
+            "",
+            "raw",
+            "xmlns='http://www.w3.org/2000/svg'"
+        ],
+        [
+            "IMAGE.0",
+            "elements",
+            "Name",
+            "options",
+            [["Alice", "<rect />"], [["*"], "<rect />"]]
+        ],
+        [
+            "IMAGE.0",
+            "elements",
+            "Wings",
+            "range",
+            [[[0, 50], "<image />"]]
+        ],
+        ["IMAGE.0", "elements", "Age", "range", "<image />"]
+    ]);
+    let trait_names = vec!["Name".to_owned(), "Age".to_owned()];
+    let diagnostics = lint_svg_pattern(&pattern, "dob.decoders[1].pattern", &trait_names);
+    let codes: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.code))
+        .collect();
+    assert_eq!(
+        codes,
+        vec![
+            (DiagnosticSeverity::Warning, "pattern_trait_unknown"),
+            (DiagnosticSeverity::Error, "pattern_args_invalid"),
+        ]
+    );
+}
+
+// patterns of the test fixtures are taken by the reference decoders, e.g. legacy `raw` kind
+#[test]
+fn test_lint_fixture_patterns() {
+    let fixtures: [(&str, ClusterDescriptionField); 4] = [
+        ("unicorn", generate_unicorn_dob_ingredients(false).1),
+        ("dob/0 example", generate_example_dob_ingredients(false).1),
+        ("dob/1 example", generate_dob1_ingredients().1),
+        (
+            "dob/2 example",
+            serde_json::from_str(include_str!("dob2/cluster_description.json")).unwrap(),
+        ),
+    ];
+    for (fixture, dob_metadata) in fixtures {
+        let mut diagnostics = Vec::new();
+        let version = dob_metadata.dob_version();
+        lint_patterns(&dob_metadata.dob, version, None, &mut diagnostics);
+        assert!(diagnostics.is_empty(), "{fixture}: {diagnostics:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_validate_dob2_cluster() {
+    let settings = prepare_settings("dob/2");
+    let decoder = DOBDecoder::new(settings);
+    let description = include_str!("dob2/cluster_description.json");
+    let report = decoder.validate_cluster(description, None).await;
+    assert!(report.valid, "{:?}", report.diagnostics);
+    assert_eq!(report.dob_version, Some(2));
+    assert!(report.sample_render.is_some());
+}
+
+#[test]
+fn test_sample_dna_length_and_generation() {
+    let description = include_str!("dob2/cluster_description.json");
+    let dob_metadata = serde_json::from_str(description).expect("parse description");
+    let length = sample_dna_length(&dob_metadata);
+    assert_eq!(length, 27);
+    let dna = generate_dna(b"seed", length);
+    assert_eq!(dna.len(), length * 2);
+    assert_eq!(dna, generate_dna(b"seed", length));
+    assert_ne!(dna, generate_dna(b"another seed", length));
+}
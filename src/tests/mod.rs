@@ -2,8 +2,20 @@ use ckb_types::h256;
 
 use crate::types::{HashType, OnchainDecoderDeployment, ScriptId, Settings};
 
+mod binary;
+mod cluster;
+mod content;
 mod dob0;
 mod dob1;
+mod dob2;
+mod dryrun;
+mod history;
+mod index;
+mod lint;
+mod pattern;
+mod stats;
+mod traits;
+mod transaction;
 
 fn prepare_settings(version: &str) -> Settings {
     Settings {
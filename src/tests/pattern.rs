@@ -0,0 +1,121 @@
+use serde_json::json;
+
+use crate::{
+    decoder::pattern::{analyze_traits_pattern, traits_pattern as cluster_traits_pattern},
+    tests::dob0::decoder::generate_example_dob_ingredients,
+    types::{Error, TraitValueSet},
+};
+
+fn traits_pattern() -> serde_json::Value {
+    json!([
+        [
+            "Name",
+            "String",
+            0,
+            1,
+            "options",
+            ["Alice", "Bob", "Charlie", "David"]
+        ],
+        ["Age", "Number", 1, 1, "range", [0, 100]],
+        ["Score", "Number", 2, 1, "rawNumber"],
+        ["Level", "Number", 0, 2, "range", [1, 3]],
+        ["_URL", "string", 6, 21, "utf8"]
+    ])
+}
+
+#[test]
+fn test_analyze_traits_pattern() {
+    let dna = "0x0a1b2c3d4e5f";
+    let analysis = analyze_traits_pattern(&traits_pattern(), Some(dna)).expect("analyze");
+    assert_eq!(analysis.dna_length, 27);
+    assert_eq!(analysis.unused_bytes, vec![3, 4, 5]);
+    assert_eq!(analysis.bytes[0].traits, vec!["Name", "Level"]);
+    assert_eq!(analysis.bytes[1].traits, vec!["Age", "Level"]);
+    assert_eq!(analysis.bytes[26].traits, vec!["_URL"]);
+
+    let cardinalities: Vec<_> = analysis
+        .traits
+        .iter()
+        .map(|byte_map| (byte_map.name.as_str(), byte_map.cardinality, byte_map.hint))
+        .collect();
+    assert_eq!(
+        cardinalities,
+        vec![
+            ("Name", Some(4), false),
+            ("Age", Some(101), false),
+            ("Score", Some(256), false),
+            ("Level", Some(3), false),
+            ("_URL", None, true),
+        ]
+    );
+    assert!(matches!(
+        &analysis.traits[0].values,
+        TraitValueSet::Options { values } if values.len() == 4
+    ));
+    assert_eq!(analysis.traits[1].dna_bytes.as_deref(), Some("1b"));
+    assert_eq!(analysis.traits[3].dna_bytes.as_deref(), Some("0a1b"));
+    assert_eq!(analysis.traits[4].dna_bytes.as_deref(), Some(""));
+
+    // hint traits are out of trait space, which is 4 * 101 * 256 * 3
+    assert_eq!(analysis.trait_space_size, Some(310272));
+    assert!((analysis.trait_space_bits - 310272f64.log2()).abs() < 1e-9);
+}
+
+#[test]
+fn test_analyze_traits_pattern_capped_by_dna_space() {
+    let pattern = json!([
+        ["Seed", "String", 0, 32, "rawString"],
+        ["Again", "String", 0, 32, "rawString"]
+    ]);
+    let analysis = analyze_traits_pattern(&pattern, None).expect("analyze");
+    assert_eq!(analysis.traits[0].cardinality, None);
+    assert_eq!(analysis.traits[0].dna_bytes, None);
+    // both traits read the same 32 bytes, so that the space is 2^256 rather than 2^512
+    assert_eq!(analysis.trait_space_size, None);
+    assert_eq!(analysis.trait_space_bits, 256.0);
+}
+
+// pattern of the DOB/0 example cluster, which reads bytes in legacy `raw` kind
+#[test]
+fn test_analyze_example_pattern() {
+    let (content, dob_metadata) = generate_example_dob_ingredients(false);
+    let pattern = cluster_traits_pattern(&dob_metadata).expect("traits pattern");
+    let dna = content["dna"].as_str();
+    let analysis = analyze_traits_pattern(pattern, dna).expect("analyze");
+    assert_eq!(analysis.dna_length, 27);
+    assert!(analysis.unused_bytes.is_empty());
+    assert_eq!(analysis.bytes[3].traits, vec!["DNA", "Value"]);
+
+    let cardinalities: Vec<_> = analysis
+        .traits
+        .iter()
+        .map(|byte_map| (byte_map.name.as_str(), byte_map.cardinality))
+        .collect();
+    assert_eq!(
+        cardinalities,
+        vec![
+            ("Name", Some(8)),
+            ("Age", Some(101)),
+            ("Score", Some(256)),
+            ("DNA", Some(1 << 24)),
+            ("URL", None),
+            ("Value", Some(1 << 24)),
+        ]
+    );
+    assert!(matches!(analysis.traits[2].values, TraitValueSet::Raw));
+    assert!(matches!(analysis.traits[4].values, TraitValueSet::Utf8));
+    assert_eq!(analysis.traits[3].dna_bytes.as_deref(), Some("b5e7a2"));
+    assert_eq!(analysis.traits[5].dna_bytes.as_deref(), Some("b5e7a2"));
+
+    // `URL` reads 21 bytes, so that the space overflows and is counted from all 27 bytes read
+    assert_eq!(analysis.trait_space_size, None);
+    assert_eq!(analysis.trait_space_bits, 216.0);
+}
+
+#[test]
+fn test_analyze_unsupported_pattern() {
+    let result = analyze_traits_pattern(&json!("ipfs://pattern"), None);
+    assert!(matches!(result, Err(Error::PatternAnalysisUnsupported(_))));
+    let result = analyze_traits_pattern(&json!([["Name", "String", 0, 1, "gradient"]]), None);
+    assert!(matches!(result, Err(Error::PatternAnalysisUnsupported(_))));
+}
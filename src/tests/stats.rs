@@ -0,0 +1,59 @@
+use crate::{
+    decoder::stats::{aggregate_cluster_stats, parse_render_traits, ClusterTraits},
+    types::{DOBTrait, StandardDOBOutput},
+};
+
+fn render(wings: &str, lucky_number: u64, dna: &str) -> Vec<StandardDOBOutput> {
+    let render_output = format!(
+        r##"[{{"name":"prev.bgcolor","traits":[{{"String":"#DBAB00"}}]}},{{"name":"_DNA","traits":[{{"String":"{dna}"}}]}},{{"name":"Wings","traits":[{{"String":"{wings}"}}]}},{{"name":"Lucky Number","traits":[{{"Number":{lucky_number}}}]}}]"##
+    );
+    parse_render_traits(&render_output).expect("parse traits")
+}
+
+#[test]
+fn test_parse_dob2_render_traits() {
+    let render_output = r#"[{"stage":"traits","kind":"traits","output":[{"name":"Age","traits":[{"Number":23}]}]},{"stage":"image","kind":"render","media_type":"image/svg+xml","output":"<svg></svg>"}]"#;
+    let traits = parse_render_traits(render_output).expect("parse traits");
+    assert_eq!(traits.len(), 1);
+    assert_eq!(traits[0].name, "Age");
+}
+
+#[test]
+fn test_aggregate_cluster_stats() {
+    let cluster_traits = ClusterTraits {
+        spores: vec![
+            ([1u8; 32], render("Sun Wings", 35, "aa")),
+            ([2u8; 32], render("Sun Wings", 35, "bb")),
+            ([3u8; 32], render("Moon Wings", 35, "cc")),
+            ([4u8; 32], render("Sun Wings", 7, "dd")),
+        ],
+        failed_count: 1,
+    };
+    let stats = aggregate_cluster_stats([0u8; 32], &cluster_traits, 0);
+    assert_eq!(stats.spore_count, 5);
+    assert_eq!(stats.failed_count, 1);
+    assert_eq!(
+        stats.traits.keys().collect::<Vec<_>>(),
+        vec!["Lucky Number", "Wings"]
+    );
+    let wings = &stats.traits["Wings"];
+    assert_eq!(wings[0].value, DOBTrait::String("Sun Wings".to_owned()));
+    assert_eq!(wings[0].count, 3);
+    assert_eq!(wings[1].frequency, 0.25);
+
+    let ranks = stats
+        .spores
+        .iter()
+        .map(|spore| (spore.spore_id[..2].to_owned(), spore.rank))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ranks,
+        vec![
+            ("03".to_owned(), 1),
+            ("04".to_owned(), 1),
+            ("01".to_owned(), 3),
+            ("02".to_owned(), 3),
+        ]
+    );
+    assert_eq!(stats.spores[0].traits.len(), 2);
+}
@@ -0,0 +1,80 @@
+use serde_json::json;
+
+use crate::types::{DOBTrait, Error, StandardDOBOutput};
+
+const RAW_OUTPUT: &str = r##"[{"name":"Name","traits":[{"String":"Ethan"}]},{"name":"Age","traits":[{"Number":23}]},{"name":"Ratio","traits":[{"Number":0.75}]},{"name":"prev.bgcolor","traits":[{"Color":"#CEBAF7"}]},{"name":"Birth","traits":[{"Timestamp":1700000000}]},{"name":"IMAGE.0","traits":[{"SVG":"<svg xmlns='http://www.w3.org/2000/svg'></svg>"}]},{"name":"prev.bg","traits":[{"ImageURI":"btcfs://59e87ca177ef0fd457e87e9f93627660022cf519b531e1f4e3a6dda9e5e33827i0"}]},{"name":"Extra","traits":[{"Custom":{"level":[1,2]}}]}]"##;
+
+#[test]
+fn test_parse_typed_traits() {
+    let outputs = StandardDOBOutput::parse_list(RAW_OUTPUT).expect("parse traits");
+    let traits: Vec<_> = outputs
+        .iter()
+        .map(|output| output.first_trait().unwrap().clone())
+        .collect();
+    assert_eq!(traits[0], DOBTrait::String("Ethan".to_owned()));
+    assert_eq!(traits[1].as_f64(), Some(23.0));
+    assert_eq!(traits[2].as_f64(), Some(0.75));
+    assert_eq!(traits[3], DOBTrait::Color("#CEBAF7".to_owned()));
+    assert_eq!(traits[4], DOBTrait::Timestamp(1700000000));
+    assert!(matches!(traits[5], DOBTrait::Svg(_)));
+    assert!(matches!(traits[6], DOBTrait::ImageUri(_)));
+    assert_eq!(
+        traits[7],
+        DOBTrait::Other("Custom".to_owned(), json!({"level": [1, 2]}))
+    );
+}
+
+#[test]
+fn test_typed_traits_round_trip() {
+    let outputs = StandardDOBOutput::parse_list(RAW_OUTPUT).expect("parse traits");
+    assert_eq!(serde_json::to_string(&outputs).unwrap(), RAW_OUTPUT);
+}
+
+#[test]
+fn test_invalid_typed_traits() {
+    let cases = [
+        (r#"{"Number":"23"}"#, "Number", json!("23")),
+        (r#"{"Color":"red"}"#, "Color", json!("red")),
+        (r#"{"Timestamp":-1}"#, "Timestamp", json!(-1)),
+        (r#"{"SVG":"<img/>"}"#, "SVG", json!("<img/>")),
+        (
+            r#"{"ImageURI":"not an uri"}"#,
+            "ImageURI",
+            json!("not an uri"),
+        ),
+    ];
+    for (case, type_, value) in cases {
+        // values that don't meet their types are kept as they are, and reported as issues
+        let raw_output = format!(r#"[{{"name":"Invalid","traits":[{case}]}}]"#);
+        let outputs = StandardDOBOutput::parse_list(&raw_output).expect(case);
+        assert_eq!(
+            outputs[0].traits,
+            vec![DOBTrait::Other(type_.to_owned(), value)]
+        );
+        assert_eq!(serde_json::to_string(&outputs).unwrap(), raw_output);
+        let issues = StandardDOBOutput::trait_issues(&outputs);
+        assert_eq!(issues.len(), 1, "{case}");
+        assert_eq!(issues[0].name, "Invalid");
+    }
+    let outputs = StandardDOBOutput::parse_list(RAW_OUTPUT).expect("parse traits");
+    assert!(StandardDOBOutput::trait_issues(&outputs).is_empty());
+
+    let result =
+        StandardDOBOutput::parse_list(r#"[{"name":"Pair","traits":[{"String":"a","Number":1}]}]"#);
+    assert!(matches!(result, Err(Error::DecoderOutputTraitInvalid(..))));
+    let result = StandardDOBOutput::parse_list(r#"{"name":"Age"}"#);
+    assert!(matches!(result, Err(Error::DecoderOutputInvalid)));
+}
+
+#[test]
+fn test_keep_extra_output_fields() {
+    let raw_output = r#"[{"name":"Age","traits":[{"Number":23}],"unit":"year","hidden":true}]"#;
+    let outputs = StandardDOBOutput::parse_list(raw_output).expect("parse traits");
+    assert_eq!(outputs[0].extra["unit"], json!("year"));
+    assert_eq!(outputs[0].extra["hidden"], json!(true));
+    let round_trip: serde_json::Value = serde_json::to_value(&outputs).unwrap();
+    assert_eq!(
+        round_trip,
+        serde_json::from_str::<serde_json::Value>(raw_output).unwrap()
+    );
+}
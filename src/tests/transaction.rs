@@ -0,0 +1,204 @@
+use ckb_jsonrpc_types::{Transaction, TxStatus};
+use serde_json::{json, Value};
+
+use crate::decoder::{
+    transaction::{committed_block_number, transaction_clusters, transaction_spores},
+    DOBDecoder,
+};
+use crate::tests::prepare_settings;
+use crate::types::{
+    ClusterDescriptionField, DOBClusterFormat, DOBClusterFormatV0, DOBDecoderFormat,
+    DecoderLocationType, Error,
+};
+
+const SPORE_CODE_HASH: &str = "0x685a60219309029d01310311dba953d67029170ca4848a4ff638e57002130a0d";
+const CLUSTER_CODE_HASH: &str =
+    "0x0bbe768b519d8ea7b96d58f1182eb7e6ef96c541fbd9526975077ee09f049058";
+const DNA: &str = "df4ffcb5e7a283ea7e6f09a504d0e256";
+
+// molecule table whose fields are `Bytes`, or `BytesOpt` which is empty if none
+fn molecule_table(fields: &[Option<&[u8]>]) -> Vec<u8> {
+    let header_size = 4 * (fields.len() + 1);
+    let mut offsets = Vec::new();
+    let mut body = Vec::new();
+    for field in fields {
+        offsets.push((header_size + body.len()) as u32);
+        if let Some(field) = field {
+            body.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            body.extend_from_slice(field);
+        }
+    }
+    let mut table = ((header_size + body.len()) as u32).to_le_bytes().to_vec();
+    for offset in offsets {
+        table.extend_from_slice(&offset.to_le_bytes());
+    }
+    table.extend(body);
+    table
+}
+
+fn spore_data(content_type: &str, cluster_id: [u8; 32]) -> Vec<u8> {
+    let content = json!({ "dna": DNA }).to_string();
+    molecule_table(&[
+        Some(content_type.as_bytes()),
+        Some(content.as_bytes()),
+        Some(&cluster_id),
+    ])
+}
+
+fn cluster_metadata() -> ClusterDescriptionField {
+    ClusterDescriptionField {
+        description: "transaction cluster".to_string(),
+        dob: DOBClusterFormat::new_dob0(DOBClusterFormatV0 {
+            decoder: DOBDecoderFormat {
+                location: DecoderLocationType::CodeHash,
+                hash: Some(
+                    "0x32f29aba4b17f3d05bec8cec55d50ef86766fd0bf82fdedaa14269f344d3784a"
+                        .parse()
+                        .unwrap(),
+                ),
+                script: None,
+            },
+            pattern: serde_json::from_str(
+                "[[\"Age\",\"number\",1,1,\"range\",[0,100]],[\"Score\",\"number\",2,1,\"raw\"]]",
+            )
+            .unwrap(),
+        }),
+    }
+}
+
+fn cluster_data() -> Vec<u8> {
+    let description = serde_json::to_string(&cluster_metadata()).unwrap();
+    molecule_table(&[Some(b"cluster"), Some(description.as_bytes())])
+}
+
+fn output(code_hash: &str, args: [u8; 32]) -> Value {
+    json!({
+        "capacity": "0x0",
+        "lock": {
+            "code_hash": format!("0x{}", hex::encode([0u8; 32])),
+            "hash_type": "type",
+            "args": "0x",
+        },
+        "type": {
+            "code_hash": code_hash,
+            "hash_type": "data1",
+            "args": format!("0x{}", hex::encode(args)),
+        },
+    })
+}
+
+// outputs are a cluster, a spore in the cluster, a plain cell and a spore of unconfigured version
+fn prepare_transaction() -> Transaction {
+    let mut plain_output = output(SPORE_CODE_HASH, [0; 32]);
+    plain_output["type"] = Value::Null;
+    serde_json::from_value(json!({
+        "version": "0x0",
+        "cell_deps": [],
+        "header_deps": [],
+        "inputs": [],
+        "outputs": [
+            output(CLUSTER_CODE_HASH, [1; 32]),
+            output(SPORE_CODE_HASH, [2; 32]),
+            plain_output,
+            output(SPORE_CODE_HASH, [3; 32]),
+        ],
+        "outputs_data": [
+            format!("0x{}", hex::encode(cluster_data())),
+            format!("0x{}", hex::encode(spore_data("dob/0", [1; 32]))),
+            "0x",
+            format!("0x{}", hex::encode(spore_data("dob/1", [1; 32]))),
+        ],
+        "witnesses": [],
+    }))
+    .expect("transaction")
+}
+
+#[test]
+fn test_collect_transaction_clusters_and_spores() {
+    let settings = prepare_settings("dob/0");
+    let tx = prepare_transaction();
+    let clusters = transaction_clusters(&settings, &tx);
+    assert_eq!(clusters.len(), 1);
+    assert_eq!(clusters[&[1; 32]].as_bytes(), cluster_data());
+
+    let spores = transaction_spores(&settings, &tx, None)
+        .into_iter()
+        .map(|(out_index, spore_id, _)| (out_index, spore_id))
+        .collect::<Vec<_>>();
+    assert_eq!(spores, vec![(1, [2; 32]), (3, [3; 32])]);
+    let spores = transaction_spores(&settings, &tx, Some(3));
+    assert_eq!(spores.len(), 1);
+    assert_eq!(spores[0].2.as_bytes(), spore_data("dob/1", [1; 32]));
+    assert!(transaction_spores(&settings, &tx, Some(2)).is_empty());
+}
+
+#[test]
+fn test_committed_block_number() {
+    let status = |status: &str, block_number: Option<&str>| -> TxStatus {
+        serde_json::from_value(json!({
+            "status": status,
+            "block_number": block_number,
+            "block_hash": null,
+            "reason": null,
+        }))
+        .expect("tx status")
+    };
+    assert_eq!(
+        committed_block_number(&status("committed", Some("0x10"))).unwrap(),
+        Some(16)
+    );
+    assert_eq!(
+        committed_block_number(&status("pending", None)).unwrap(),
+        None
+    );
+    assert_eq!(
+        committed_block_number(&status("proposed", None)).unwrap(),
+        None
+    );
+    assert!(matches!(
+        committed_block_number(&status("rejected", None)),
+        Err(Error::FetchTransactionError(_))
+    ));
+    assert!(matches!(
+        committed_block_number(&status("unknown", None)),
+        Err(Error::FetchTransactionError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_decode_transaction_with_cluster_inside() {
+    let settings = prepare_settings("dob/0");
+    let decoder = DOBDecoder::new(settings);
+    let dobs = decoder.decode_transaction(&prepare_transaction()).await;
+    assert_eq!(dobs.len(), 2);
+
+    let dob = dobs[0].result.as_ref().expect("render spore");
+    assert_eq!(dobs[0].out_index, 1);
+    assert!(dob.cluster_in_transaction);
+    assert!(dob.cluster_resolution.is_none());
+    assert!(!dob.finalized);
+    let expected = decoder
+        .decode_dna(DNA, cluster_metadata())
+        .await
+        .expect("decode");
+    assert_eq!(dob.render_output, expected);
+
+    assert_eq!(dobs[1].out_index, 3);
+    assert!(matches!(
+        dobs[1].result,
+        Err(Error::DOBVersionUnexpected(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_decode_unknown_outpoint() {
+    let settings = prepare_settings("dob/0");
+    let decoder = DOBDecoder::new(settings);
+    let out_point = serde_json::from_value(json!({
+        "tx_hash": format!("0x{}", hex::encode([0xffu8; 32])),
+        "index": "0x0",
+    }))
+    .unwrap();
+    let result = decoder.decode_by_outpoint(&out_point).await;
+    assert!(matches!(result, Err(Error::FetchTransactionError(_))));
+}
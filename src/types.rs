@@ -1,8 +1,9 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-use ckb_jsonrpc_types::Script;
+use ckb_jsonrpc_types::{JsonBytes, OutPoint, Script};
 use ckb_types::{core::ScriptHashType, H256};
-use serde::{ser::SerializeMap, Deserialize};
+use serde::{de::Error as _, ser::SerializeMap, Deserialize, Deserializer};
 use serde_json::Value;
 
 #[cfg(feature = "standalone_server")]
@@ -13,7 +14,7 @@ use serde::Serialize;
 #[allow(clippy::enum_variant_names)]
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error("DOB version number only support 0 and 1, please check your cluster config")]
+    #[error("DOB version number isn't registered in decoder, please check your cluster config")]
     DOBVersionNumberUndefined,
     #[error("spore id byte length should be equal to 32")]
     SporeIdLengthInvalid,
@@ -27,6 +28,8 @@ pub enum Error {
     SporeDataContentTypeUncompatible,
     #[error("declared dob version `{0}` isn't set into server's configuration")]
     DOBVersionUnexpected(String),
+    #[error("spore declares dob version {0}, but its cluster declares dob version {1}")]
+    DOBVersionMismatch(u8, u8),
     #[error("cluster id not found in spore data")]
     ClusterIdNotSet,
     #[error("cluster id `{0}` not found from ckb network")]
@@ -37,10 +40,20 @@ pub enum Error {
     DecoderIdNotFound,
     #[error("output of decoder program not in format json")]
     DecoderOutputInvalid,
+    #[error("trait `{0}` in decoder output is invalid: {1}")]
+    DecoderOutputTraitInvalid(String, String),
     #[error("decoder program outputs nothing, please check decoder program code")]
     DecoderOutputEmpty,
     #[error("spore id string is not in hex format")]
     HexedSporeIdParseError,
+    #[error("cluster id string is not in hex format")]
+    HexedClusterIdParseError,
+    #[error("cluster id byte length should be equal to 32")]
+    ClusterIdLengthInvalid,
+    #[error("transaction hash string is not in hex format or byte length isn't equal to 32")]
+    HexedTxHashParseError,
+    #[error("spore cell not found in outpoint({0}:{1})")]
+    SporeCellNotFound(String, u32),
     #[error("configured decoder binary persistence path is unwriteable")]
     DecoderBinaryPathInvalid,
     #[error("execute_riscv_binary call failed: {0}")]
@@ -51,10 +64,26 @@ pub enum Error {
     FetchLiveCellsError(String),
     #[error("get_transaction or get_transactions rpc failed: {0}")]
     FetchTransactionError(String),
+    #[error("no cell with type hash `{0}` found in scanned pages, scan limit reached")]
+    CellScanLimitReached(String),
+    #[error("header of block #{0} not found")]
+    BlockHeaderNotFound(u64),
+    #[error("page limit must be greater than zero")]
+    PageLimitInvalid,
     #[error("decoder cell not found in outpoint({0}:{1})")]
     DecoderCellNotFound(String, u32),
     #[error("spore content doesn't follow the specs of DOB protocol")]
     DOBContentUnexpected,
+    #[error("spore content charset `{0}` is unsupported")]
+    SporeContentCharsetUnsupported(String),
+    #[error("spore content is empty")]
+    SporeContentEmpty,
+    #[error("spore content encoding `{0}` is unsupported")]
+    SporeContentEncodingUnsupported(String),
+    #[error("spore content is not valid in `{0}` encoding: {1}")]
+    SporeContentMalformed(String, String),
+    #[error("DNA not found in spore content: {0}")]
+    SporeContentDNANotFound(String),
     #[error("cluster description doesn't follow the specs of DOB protocol")]
     DOBMetadataUnexpected,
     #[error("configured DOB render cache file `{0}` is unwriteable or unreadable")]
@@ -75,11 +104,36 @@ pub enum Error {
     DecoderScriptNotFound,
     #[error("decoders configured in cluster are empty, please check your cluster config")]
     DecoderChainIsEmpty,
-}
-
-pub enum Dob<'a> {
-    V0(&'a DOBClusterFormatV0),
-    V1(&'a DOBClusterFormatV1),
+    #[error("stage `{0}` configured in cluster is invalid: {1}")]
+    DOBStageInvalid(String, String),
+    #[error("DOB/{0} doesn't support decoding with stage outputs")]
+    DOBStagesUnsupported(u8),
+    #[error("no DNA to preview, please provide DNA list or random count")]
+    PreviewDNAListEmpty,
+    #[error("up to {0} DNAs are allowed to preview at once")]
+    PreviewDNACountExceeded(usize),
+    #[error("DNA to preview is limited to {0} bytes")]
+    PreviewDNALengthExceeded(usize),
+    #[error("statistics of cluster `{0}` are not computed yet, please check `stats_clusters`")]
+    ClusterStatsNotComputed(String),
+    #[error("cluster statistics file `{0}` is unwriteable")]
+    ClusterStatsUnwritable(String),
+    #[error("spore `{0}` isn't found in rarity statistics of its cluster")]
+    SporeRarityNotFound(String),
+    #[error("pattern can't be analyzed: {0}")]
+    PatternAnalysisUnsupported(String),
+    #[error("substitute decoder binary is invalid: {0}")]
+    DecoderSubstituteInvalid(String),
+    #[error("no substitute decoder is referenced by cluster, please check decoder locations")]
+    DecoderSubstituteUnmatched,
+    #[error("rpc secret from {0} is unresolvable")]
+    RpcSecretUnresolved(String),
+    #[error("rpc header `{0}` is not a valid http header")]
+    RpcHeaderInvalid(String),
+    #[error("rpc uri `{0}` is invalid, e.g. \"http://127.0.0.1:8114\"")]
+    RpcUriInvalid(String),
+    #[error("subscription to ckb node failed: {0}")]
+    SubscriptionError(String),
 }
 
 #[cfg(feature = "standalone_server")]
@@ -91,7 +145,7 @@ impl From<Error> for ErrorObjectOwned {
 }
 
 // value on `description` field in Cluster data, adapting for DOB protocol in JSON format
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[cfg_attr(test, derive(serde::Serialize, PartialEq, Debug))]
 pub struct ClusterDescriptionField {
     pub description: String,
@@ -99,26 +153,9 @@ pub struct ClusterDescriptionField {
 }
 
 impl ClusterDescriptionField {
-    pub fn unbox_dob(&self) -> Result<Dob, Error> {
-        match self.dob.ver {
-            Some(0) | None => {
-                let dob0 = self
-                    .dob
-                    .dob_ver_0
-                    .as_ref()
-                    .ok_or(Error::ClusterDataUncompatible)?;
-                Ok(Dob::V0(dob0))
-            }
-            Some(1) => {
-                let dob1 = self
-                    .dob
-                    .dob_ver_1
-                    .as_ref()
-                    .ok_or(Error::ClusterDataUncompatible)?;
-                Ok(Dob::V1(dob1))
-            }
-            _ => Err(Error::DOBVersionNumberUndefined),
-        }
+    // DOB protocol version declared by cluster, which is DOB/0 if absent
+    pub fn dob_version(&self) -> u8 {
+        self.dob.ver.unwrap_or(0)
     }
 }
 
@@ -127,25 +164,236 @@ pub struct DOBSporeFormat {
     pub content: Value,
     pub dna: String,
     pub cluster_id: [u8; 32],
+    // block number of the transaction that spore data is read from, none if not read from chain
+    pub block_number: Option<u64>,
+    // on-chain state of spore cell, only exists when resolved in `live_cell_first` mode
+    pub cell: Option<SporeCellInfo>,
+}
+
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SporeStatus {
+    #[serde(rename(serialize = "live"))]
+    Live,
+    #[serde(rename(serialize = "melted"))]
+    Melted,
+}
+
+// `owner` is the lock script of live spore cell, and `out_point` refers to the live spore cell, or
+// the minted spore cell if melted
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone, Debug)]
+pub struct SporeCellInfo {
+    pub status: SporeStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Script>,
+    pub out_point: OutPoint,
+}
+
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SporeEventType {
+    #[serde(rename(serialize = "mint"))]
+    Mint,
+    #[serde(rename(serialize = "transfer"))]
+    Transfer,
+    #[serde(rename(serialize = "melt"))]
+    Melt,
+}
+
+// one step of spore history, `lock` and `out_point` refer to the spore cell created in this
+// transaction, which don't exist in melt step
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone, Debug)]
+pub struct SporeEvent {
+    pub event: SporeEventType,
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock: Option<Script>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out_point: Option<OutPoint>,
+}
+
+// position of listing spore history, which points to the indexer cursor of `available_spores[script_index]`
+// and the cells already listed after it
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct SporeHistoryCursor {
+    pub script_index: usize,
+    pub indexer_cursor: Option<JsonBytes>,
+    pub skipped_cells: u32,
+}
+
+// steps of spore history listed in one round, `cursor` is none if all steps are listed
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone, Debug)]
+pub struct SporeHistoryPage {
+    pub history: Vec<SporeEvent>,
+    pub cursor: Option<SporeHistoryCursor>,
+}
+
+// DOB rendered from spore cell, `cluster_in_transaction` indicates its cluster is found in the same
+// transaction rather than on chain, otherwise `cluster_resolution` reports how it's resolved, and
+// `finalized` indicates whether both spore and cluster are confirmed by enough blocks
+pub struct RenderedDOB {
+    pub spore: DOBSporeFormat,
+    pub render_output: String,
+    pub cluster_in_transaction: bool,
+    pub cluster_resolution: Option<ClusterResolution>,
+    pub finalized: bool,
+}
+
+// rendering result of spore cell in transaction outputs
+pub struct TransactionDOB {
+    pub out_index: u32,
+    pub spore_id: [u8; 32],
+    pub result: Result<RenderedDOB, Error>,
+}
+
+// position of scanning spores in cluster, which points to the indexer cursor of `available_spores[script_index]`
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct ClusterSporesCursor {
+    pub script_index: usize,
+    pub indexer_cursor: Option<JsonBytes>,
+}
+
+// live spores found in one scanning round, `next_cursor` is none if all spore cells are scanned
+pub struct ClusterSporesPage {
+    pub spores: Vec<([u8; 32], DOBSporeFormat)>,
+    pub next_cursor: Option<ClusterSporesCursor>,
+}
+
+// approach of searching spore cell on chain
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Default, Clone, Copy)]
+pub enum SporeResolutionMode {
+    // only read spore data from mint transaction, which reports nothing about spore status
+    #[default]
+    #[serde(rename(serialize = "mint_transaction", deserialize = "mint_transaction"))]
+    MintTransaction,
+    // query live spore cell first, and fall back to mint transaction for melted spore, which costs
+    // an extra live cell query even if the render is cached
+    #[serde(rename(serialize = "live_cell_first", deserialize = "live_cell_first"))]
+    LiveCellFirst,
+}
+
+// policy of handling upgraded decoder deployed under type_id or type_script
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Default, Clone, Copy)]
+pub enum DecoderUpgradePolicy {
+    // replace cached decoder binary with the upgraded one
+    #[default]
+    #[serde(rename(serialize = "follow", deserialize = "follow"))]
+    Follow,
+    // keep using the first-seen decoder binary
+    #[serde(rename(serialize = "pin", deserialize = "pin"))]
+    Pin,
+}
+
+// source of cluster data that spore is decoded against
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Default, Clone, Copy)]
+pub enum ClusterDataSource {
+    // read the live cluster cell, and fall back to the latest cluster transaction if consumed
+    #[default]
+    #[serde(rename(serialize = "latest", deserialize = "latest"))]
+    Latest,
+    // read cluster data as it was at the block height that spore was minted
+    #[serde(rename(serialize = "spore_mint", deserialize = "spore_mint"))]
+    SporeMint,
+}
+
+// cluster metadata along with the block number of cluster cell it's read from
+pub struct DOBClusterInfo {
+    pub metadata: ClusterDescriptionField,
+    pub block_number: u64,
+    pub resolution: ClusterResolution,
+}
+
+// path through which cluster is resolved from the cluster id in spore, `historical` means cluster
+// data is read from the output of a past cluster transaction, whose cell may have been consumed,
+// and `proxy` or `agent` means so as well, but the cluster is still referred by a live cluster
+// proxy cell, or a cluster agent cell which refers to a cluster proxy cell in turn
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[serde(tag = "type")]
+#[derive(Clone, Debug)]
+pub enum ClusterResolution {
+    #[serde(rename(serialize = "direct", deserialize = "direct"))]
+    Direct,
+    #[serde(rename(serialize = "proxy", deserialize = "proxy"))]
+    Proxy {
+        proxy_out_point: OutPoint,
+        cluster_id: H256,
+    },
+    #[serde(rename(serialize = "agent", deserialize = "agent"))]
+    Agent {
+        agent_out_point: OutPoint,
+        proxy_out_point: OutPoint,
+        cluster_id: H256,
+    },
+    #[serde(rename(serialize = "historical", deserialize = "historical"))]
+    Historical { out_point: OutPoint },
 }
 
 // contains `decoder` and `pattern` identifiers
 //
-// note: if `ver` is empty, `dob_ver_0` must uniquely exist
-#[derive(Deserialize)]
+// note: if `ver` is empty, `dob_ver_0` must uniquely exist, and the format selected by `ver` must be
+// well-formed, while formats of other versions are only parsed if compatible
+#[derive(Clone)]
 #[cfg_attr(feature = "standalone_server", derive(Serialize))]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct DOBClusterFormat {
-    #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ver: Option<u8>,
     #[serde(flatten)]
     pub dob_ver_0: Option<DOBClusterFormatV0>,
     #[serde(flatten)]
     pub dob_ver_1: Option<DOBClusterFormatV1>,
+    #[serde(flatten)]
+    pub dob_ver_2: Option<DOBClusterFormatV2>,
 }
 
-#[derive(Deserialize)]
+impl<'de> Deserialize<'de> for DOBClusterFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Value::deserialize(deserializer)?;
+        if !raw.is_object() {
+            return Err(D::Error::custom("dob metadata is not an object"));
+        }
+        let ver = match raw.get("ver") {
+            None | Some(Value::Null) => None,
+            Some(ver) => Some(u8::deserialize(ver).map_err(D::Error::custom)?),
+        };
+        let selected = ver.unwrap_or(0);
+        Ok(Self {
+            ver,
+            dob_ver_0: deserialize_version::<_, D::Error>(&raw, selected == 0)?,
+            dob_ver_1: deserialize_version::<_, D::Error>(&raw, selected == 1)?,
+            dob_ver_2: deserialize_version::<_, D::Error>(&raw, selected == 2)?,
+        })
+    }
+}
+
+// parse format of one DOB version from metadata object, error is returned only if the version is
+// selected by `ver`
+fn deserialize_version<'de, T, E>(raw: &'de Value, selected: bool) -> Result<Option<T>, E>
+where
+    T: Deserialize<'de>,
+    E: serde::de::Error,
+{
+    match T::deserialize(raw) {
+        Ok(format) => Ok(Some(format)),
+        Err(error) if selected => Err(E::custom(error)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Deserialize, Clone)]
 #[cfg_attr(feature = "standalone_server", derive(Serialize))]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct DOBClusterFormatV0 {
@@ -153,13 +401,320 @@ pub struct DOBClusterFormatV0 {
     pub pattern: Value,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[cfg_attr(feature = "standalone_server", derive(Serialize))]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct DOBClusterFormatV1 {
     pub decoders: Vec<DOBClusterFormatV0>,
 }
 
+// splits decoding into named stages, e.g. a `traits` stage parses DNA into traits, and a `render`
+// stage renders image from traits
+#[derive(Deserialize, Clone)]
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct DOBClusterFormatV2 {
+    pub stages: Vec<DOBStageFormat>,
+}
+
+#[derive(Deserialize, Clone)]
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct DOBStageFormat {
+    pub name: String,
+    pub kind: DOBStageKind,
+    pub decoder: DOBDecoderFormat,
+    pub pattern: Value,
+    // media type of artifact produced by `render` stage, e.g. `image/svg+xml`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[cfg_attr(test, derive(Debug))]
+pub enum DOBStageKind {
+    // outputs traits in `StandardDOBOutput` format, which are passed to the following stages
+    #[serde(rename(serialize = "traits", deserialize = "traits"))]
+    Traits,
+    // outputs artifact rendered from traits, such as image
+    #[serde(rename(serialize = "render", deserialize = "render"))]
+    Render,
+}
+
+// output of one decoder in DOB/1 decoder chain, along with hash of the decoder binary that ran and
+// cycles it consumed, `cached` means the output is loaded from stage cache without running decoder
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct DOBDecoderStage {
+    pub index: usize,
+    pub decoder: DOBDecoderFormat,
+    pub binary_hash: H256,
+    pub cycles: u64,
+    pub output: Value,
+    #[serde(default)]
+    pub cached: bool,
+}
+
+// artifact produced by one stage of DOB/2 decoding
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+pub struct DOBStageOutput {
+    pub stage: String,
+    pub kind: DOBStageKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub output: Value,
+}
+
+// severity of diagnostic reported by cluster validation, clusters with errors fail to render
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum DiagnosticSeverity {
+    #[serde(rename(serialize = "error"))]
+    Error,
+    #[serde(rename(serialize = "warning"))]
+    Warning,
+    #[serde(rename(serialize = "info"))]
+    Info,
+}
+
+// finding of cluster validation, `code` is a stable identifier for tooling, and `location` points
+// to the offending part of cluster description, e.g. `dob.decoders[0].pattern[3]`
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug))]
+pub struct ClusterDiagnostic {
+    pub severity: DiagnosticSeverity,
+    pub code: &'static str,
+    pub location: String,
+    pub message: String,
+}
+
+// result of validating a cluster description, along with the sample DNA rendered if no errors
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Default)]
+pub struct ClusterValidationReport {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dob_version: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_dna_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_dna: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_render: Option<String>,
+    pub diagnostics: Vec<ClusterDiagnostic>,
+}
+
+// number of rendered spores whose trait takes the value, and its proportion in all of them
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct TraitValueStats {
+    pub value: DOBTrait,
+    pub count: usize,
+    pub frequency: f64,
+}
+
+// trait of spore along with the frequency of its value in cluster
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct SporeTraitRarity {
+    pub name: String,
+    pub value: DOBTrait,
+    pub frequency: f64,
+}
+
+// rarity of spore, `score` sums up the inverse frequency of its trait values, and spores with
+// higher score rank ahead, equal scores share the same rank
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct SporeRarity {
+    pub spore_id: String,
+    pub score: f64,
+    pub rank: usize,
+    pub traits: Vec<SporeTraitRarity>,
+}
+
+// trait value distributions and spore rarities of cluster, computed from all its live spores
+//
+// note: traits named in `_` or `prev` prefix are rendering hints rather than features, e.g.
+// `prev.bgcolor`, which are excluded
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct ClusterStats {
+    pub cluster_id: String,
+    pub computed_at: u64,
+    pub spore_count: usize,
+    pub failed_count: usize,
+    pub traits: BTreeMap<String, Vec<TraitValueStats>>,
+    pub spores: Vec<SporeRarity>,
+}
+
+// decoded traits of spore in trait index, keyed by trait name
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct IndexedSpore {
+    pub spore_id: String,
+    pub traits: BTreeMap<String, DOBTrait>,
+}
+
+// traits of all live spores in cluster, which are sorted by spore id
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct ClusterTraitIndex {
+    pub cluster_id: String,
+    pub indexed_at: u64,
+    pub spores: Vec<IndexedSpore>,
+}
+
+// filter on a trait of spore, `equals` matches trait value exactly, and `min`/`max` bound numeric
+// trait value inclusively, spores without the trait never match
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone, Default)]
+pub struct TraitFilter {
+    pub name: String,
+    pub equals: Option<Value>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+// position in search results, which are ordered by cluster id and then spore id
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct TraitSearchCursor {
+    pub cluster_id: String,
+    pub spore_id: String,
+}
+
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+pub struct TraitSearchHit {
+    pub cluster_id: String,
+    pub spore_id: String,
+    pub traits: BTreeMap<String, DOBTrait>,
+}
+
+// one page of search results, pass `cursor` back to fetch the next page, null `cursor` means no
+// more results, and `total` counts all matched spores only if required
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+pub struct TraitSearchPage {
+    pub spores: Vec<TraitSearchHit>,
+    pub total: Option<usize>,
+    pub cursor: Option<TraitSearchCursor>,
+}
+
+// values a trait can take from its DNA bytes, `options` picks one of the values, `range` picks a
+// number between `min` and `max`, and raw kinds take the DNA bytes as they are
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[serde(tag = "kind")]
+#[derive(Clone)]
+#[cfg_attr(test, derive(Debug))]
+pub enum TraitValueSet {
+    #[serde(rename(serialize = "options"))]
+    Options { values: Vec<Value> },
+    #[serde(rename(serialize = "range"))]
+    Range { min: Value, max: Value },
+    // legacy raw kind, which takes DNA bytes as a number or hex string by the trait type
+    #[serde(rename(serialize = "raw"))]
+    Raw,
+    #[serde(rename(serialize = "rawNumber"))]
+    RawNumber,
+    #[serde(rename(serialize = "rawString"))]
+    RawString,
+    #[serde(rename(serialize = "utf8"))]
+    Utf8,
+}
+
+// DNA bytes `offset..offset + length` feed the trait, `cardinality` counts the distinct values
+// reachable by those bytes, none if it overflows, and `dna_bytes` are the bytes of analyzed DNA
+//
+// note: `hint` traits are rendering hints rather than features, e.g. `prev.bgcolor`
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone)]
+pub struct TraitByteMap {
+    pub name: String,
+    #[serde(rename(serialize = "type"))]
+    pub type_: String,
+    pub offset: usize,
+    pub length: usize,
+    pub values: TraitValueSet,
+    pub cardinality: Option<u128>,
+    pub hint: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dna_bytes: Option<String>,
+}
+
+// traits fed by one DNA byte
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(Clone)]
+pub struct DNAByteUsage {
+    pub index: usize,
+    pub traits: Vec<String>,
+}
+
+// byte map of traits pattern, `trait_space_size` is the number of distinct feature combinations,
+// capped by the number of distinct DNAs since traits reading the same bytes are correlated, none if
+// it overflows, while `trait_space_bits` is always counted
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+pub struct PatternAnalysis {
+    pub dna_length: usize,
+    pub traits: Vec<TraitByteMap>,
+    pub bytes: Vec<DNAByteUsage>,
+    pub unused_bytes: Vec<usize>,
+    pub trait_space_size: Option<u128>,
+    pub trait_space_bits: f64,
+}
+
+// local decoder binary which replaces the decoder at `decoder` location during a dry-run
+#[derive(Clone)]
+pub struct DecoderSubstitute {
+    pub decoder: DOBDecoderFormat,
+    pub binary_path: PathBuf,
+}
+
+// substitute decoder applied in dry-run, `matched` means the cluster references its location
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+pub struct DecoderSubstituteReport {
+    pub decoder: DOBDecoderFormat,
+    pub binary_hash: H256,
+    pub matched: bool,
+}
+
+// render output of one side of dry-run, either output or error exists
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[derive(PartialEq)]
+pub struct DryRunRender {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub render_output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub render_error: Option<String>,
+}
+
+// value differing between deployed and dry-run render outputs, `path` is in JSON pointer format
+// and a missing side means the value only exists in the other output
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct OutputDifference {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployed: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<Value>,
+}
+
+// side-by-side result of rendering spore by deployed decoders and by substitute ones
+#[cfg_attr(feature = "standalone_server", derive(Serialize))]
+pub struct DecoderDryRunReport {
+    pub spore_id: String,
+    pub dna: String,
+    pub substitutes: Vec<DecoderSubstituteReport>,
+    pub deployed: DryRunRender,
+    pub dry_run: DryRunRender,
+    pub identical: bool,
+    pub differences: Vec<OutputDifference>,
+}
+
 #[cfg(feature = "standalone_server")]
 impl DOBClusterFormat {
     #[allow(dead_code)]
@@ -168,6 +723,7 @@ impl DOBClusterFormat {
             ver: Some(0),
             dob_ver_0: Some(dob_ver_0),
             dob_ver_1: None,
+            dob_ver_2: None,
         }
     }
 
@@ -177,14 +733,25 @@ impl DOBClusterFormat {
             ver: Some(1),
             dob_ver_0: None,
             dob_ver_1: Some(dob_ver_1),
+            dob_ver_2: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn new_dob2(dob_ver_2: DOBClusterFormatV2) -> Self {
+        Self {
+            ver: Some(2),
+            dob_ver_0: None,
+            dob_ver_1: None,
+            dob_ver_2: Some(dob_ver_2),
         }
     }
 }
 
 // restricted decoder locator type
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 #[cfg_attr(feature = "standalone_server", derive(Serialize))]
-#[cfg_attr(test, derive(PartialEq, Debug))]
+#[cfg_attr(test, derive(Debug))]
 pub enum DecoderLocationType {
     #[serde(rename(serialize = "type_id", deserialize = "type_id"))]
     TypeId,
@@ -195,7 +762,7 @@ pub enum DecoderLocationType {
 }
 
 // decoder location information
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[cfg_attr(feature = "standalone_server", derive(Serialize))]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct DOBDecoderFormat {
@@ -247,59 +814,391 @@ pub struct ScriptId {
     pub hash_type: HashType,
 }
 
+// secret value used by rpc endpoint, written in plain text or loaded from environment variable or file
+#[cfg_attr(feature = "standalone_server", derive(Deserialize))]
+#[serde(untagged)]
+#[derive(Clone)]
+pub enum Secret {
+    Env { env: String },
+    File { file: PathBuf },
+    Plain(String),
+}
+
+impl Secret {
+    pub fn resolve(&self) -> Result<String, Error> {
+        match self {
+            Secret::Env { env } => std::env::var(env)
+                .map_err(|_| Error::RpcSecretUnresolved(format!("environment variable `{env}`"))),
+            Secret::File { file } => std::fs::read_to_string(file)
+                .map(|secret| secret.trim().to_owned())
+                .map_err(|_| Error::RpcSecretUnresolved(format!("file `{}`", file.display()))),
+            Secret::Plain(secret) => Ok(secret.clone()),
+        }
+    }
+}
+
+// plain secrets are redacted to avoid leaking them into logs
+#[cfg(feature = "standalone_server")]
+impl Serialize for Secret {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Secret::Env { env } => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("env", env)?;
+                map.end()
+            }
+            Secret::File { file } => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("file", file)?;
+                map.end()
+            }
+            Secret::Plain(_) => serializer.serialize_str("<redacted>"),
+        }
+    }
+}
+
+// authentication method of rpc endpoint
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[serde(tag = "type")]
+#[derive(Clone)]
+pub enum RpcAuth {
+    #[serde(rename(serialize = "basic", deserialize = "basic"))]
+    Basic { username: String, password: Secret },
+    #[serde(rename(serialize = "bearer", deserialize = "bearer"))]
+    Bearer { token: Secret },
+}
+
+// extra headers and authentication applied to every request sent to rpc endpoint
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Default, Clone)]
+pub struct RpcEndpointOptions {
+    #[serde(default)]
+    pub headers: BTreeMap<String, Secret>,
+    pub auth: Option<RpcAuth>,
+}
+
+// limitation of requests sent to ckb node, zero value means unlimited
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Default, Clone)]
+pub struct RpcRateLimit {
+    #[serde(default)]
+    pub requests_per_second: u32,
+    #[serde(default)]
+    pub max_in_flight: usize,
+}
+
+// cache of chain data fetched from ckb node, committed transactions are persisted on hard-disk if
+// directory is set, and live cells are kept in memory for `live_cell_ttl_sec` at most
+#[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub struct RpcCacheSettings {
+    #[serde(default = "default_rpc_cache_capacity")]
+    pub memory_capacity: usize,
+    pub persistence_directory: Option<PathBuf>,
+    #[serde(default = "default_live_cell_ttl_sec")]
+    pub live_cell_ttl_sec: u64,
+}
+
+fn default_rpc_cache_capacity() -> usize {
+    10000
+}
+
+fn default_live_cell_ttl_sec() -> u64 {
+    30
+}
+
+fn default_decoder_revalidation_interval_sec() -> u64 {
+    3600
+}
+
+fn default_decoder_stages_cache_expiration_sec() -> u64 {
+    604800
+}
+
 // standalone server settings in TOML format
 #[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
 #[cfg_attr(test, derive(Default))]
 pub struct Settings {
     pub protocol_versions: Vec<String>,
     pub ckb_rpc: String,
+    pub ckb_rpc_options: Option<RpcEndpointOptions>,
+    pub ckb_indexer_rpc: Option<String>,
+    pub ckb_indexer_rpc_options: Option<RpcEndpointOptions>,
+    pub ckb_rpc_rate_limit: Option<RpcRateLimit>,
+    pub ckb_rpc_cache: Option<RpcCacheSettings>,
+    pub ckb_subscription: Option<String>,
     pub rpc_server_address: String,
+    pub admin_rpc_server_address: Option<String>,
     pub decoders_cache_directory: PathBuf,
+    #[serde(default)]
+    pub decoder_upgrade_policy: DecoderUpgradePolicy,
+    #[serde(default = "default_decoder_revalidation_interval_sec")]
+    pub decoder_revalidation_interval_sec: u64,
     pub dobs_cache_directory: PathBuf,
+    pub decoder_stages_cache_directory: Option<PathBuf>,
+    #[serde(default = "default_decoder_stages_cache_expiration_sec")]
+    pub decoder_stages_cache_expiration_sec: u64,
+    #[serde(default)]
+    pub strict_dob_traits: bool,
+    pub cluster_stats_directory: Option<PathBuf>,
+    #[serde(default)]
+    pub stats_clusters: Vec<H256>,
+    #[serde(default)]
+    pub cluster_stats_refresh_interval_sec: u64,
     pub dobs_cache_expiration_sec: u64,
+    #[serde(default)]
+    pub min_confirmations: u64,
+    #[serde(default)]
+    pub spore_resolution: SporeResolutionMode,
+    #[serde(default)]
+    pub cluster_data_source: ClusterDataSource,
     pub onchain_decoder_deployment: Vec<OnchainDecoderDeployment>,
+    #[serde(default)]
+    pub decoder_deployer_locks: Vec<Script>,
     pub available_spores: Vec<ScriptId>,
     pub available_clusters: Vec<ScriptId>,
+    #[serde(default)]
+    pub available_cluster_proxies: Vec<ScriptId>,
+    #[serde(default)]
+    pub available_cluster_agents: Vec<ScriptId>,
+    #[serde(default)]
+    pub indexed_clusters: Vec<H256>,
+    pub trait_index_directory: Option<PathBuf>,
+    #[serde(default)]
+    pub trait_index_refresh_interval_sec: u64,
 }
 
+// output of decoder, fields other than `name` and `traits` are kept in `extra` as they are
 #[cfg_attr(feature = "standalone_server", derive(Serialize, Deserialize))]
 #[derive(Default, Clone)]
 pub struct StandardDOBOutput {
     pub name: String,
-    pub traits: Vec<ParsedTrait>,
+    pub traits: Vec<DOBTrait>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, Value>,
 }
 
+// trait whose value doesn't meet its type, e.g. a `Color` trait that isn't a hex color
 #[derive(Clone)]
-pub struct ParsedTrait {
-    pub type_: String,
-    pub value: Value,
+#[cfg_attr(test, derive(Debug))]
+pub struct DOBTraitIssue {
+    pub name: String,
+    pub reason: String,
+}
+
+impl StandardDOBOutput {
+    // parse raw output of decoder, which is a JSON array of `StandardDOBOutput`, traits whose
+    // values don't meet their types are kept in `DOBTrait::Other`, and reported by `trait_issues`
+    pub fn parse_list(raw_output: &str) -> Result<Vec<Self>, Error> {
+        let outputs: Vec<Value> =
+            serde_json::from_str(raw_output).map_err(|_| Error::DecoderOutputInvalid)?;
+        outputs.into_iter().map(Self::from_value).collect()
+    }
+
+    // traits of outputs whose values don't meet their types
+    pub fn trait_issues(outputs: &[Self]) -> Vec<DOBTraitIssue> {
+        outputs
+            .iter()
+            .flat_map(|output| {
+                output.traits.iter().filter_map(|trait_| match trait_ {
+                    DOBTrait::Other(type_, value) => DOBTrait::from_entry(type_, value.clone())
+                        .err()
+                        .map(|reason| DOBTraitIssue {
+                            name: output.name.clone(),
+                            reason,
+                        }),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    fn from_value(output: Value) -> Result<Self, Error> {
+        let Value::Object(mut output) = output else {
+            return Err(Error::DecoderOutputInvalid);
+        };
+        let Some(Value::String(name)) = output.remove("name") else {
+            return Err(Error::DecoderOutputInvalid);
+        };
+        let Some(Value::Array(traits)) = output.remove("traits") else {
+            return Err(Error::DecoderOutputInvalid);
+        };
+        let traits = traits
+            .into_iter()
+            .map(|value| {
+                DOBTrait::from_wire(value)
+                    .map_err(|reason| Error::DecoderOutputTraitInvalid(name.clone(), reason))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name,
+            traits,
+            extra: output,
+        })
+    }
+
+    // first trait value of the output, most of decoders output exactly one trait under a name
+    pub fn first_trait(&self) -> Option<&DOBTrait> {
+        self.traits.first()
+    }
+}
+
+// typed trait value in decoder output, which is a single-entry map on wire, e.g.
+// `{"String": "Bob"}`
+//
+// note: trait types unknown to the server, and values that don't meet their types, are kept in
+// `Other` as they are, so that any decoder output round-trips losslessly
+#[derive(Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum DOBTrait {
+    String(String),
+    Number(serde_json::Number),
+    // hex color in `#RGB`, `#RRGGBB` or `#RRGGBBAA` format
+    Color(String),
+    // unix timestamp in seconds
+    Timestamp(u64),
+    Svg(String),
+    // URI referring to an image, e.g. `btcfs://`, `ipfs://`, `https://` or `data:image/png;...`
+    ImageUri(String),
+    Other(String, Value),
 }
 
-impl Serialize for ParsedTrait {
+impl DOBTrait {
+    pub const STRING: &'static str = "String";
+    pub const NUMBER: &'static str = "Number";
+    pub const COLOR: &'static str = "Color";
+    pub const TIMESTAMP: &'static str = "Timestamp";
+    pub const SVG: &'static str = "SVG";
+    pub const IMAGE_URI: &'static str = "ImageURI";
+
+    // type name on wire, which is the key of single-entry map
+    pub fn type_name(&self) -> &str {
+        match self {
+            Self::String(_) => Self::STRING,
+            Self::Number(_) => Self::NUMBER,
+            Self::Color(_) => Self::COLOR,
+            Self::Timestamp(_) => Self::TIMESTAMP,
+            Self::Svg(_) => Self::SVG,
+            Self::ImageUri(_) => Self::IMAGE_URI,
+            Self::Other(type_, _) => type_.as_str(),
+        }
+    }
+
+    // value on wire, which is the value of single-entry map
+    pub fn value(&self) -> Value {
+        match self {
+            Self::String(value) | Self::Color(value) | Self::Svg(value) | Self::ImageUri(value) => {
+                Value::String(value.clone())
+            }
+            Self::Number(value) => Value::Number(value.clone()),
+            Self::Timestamp(value) => Value::Number((*value).into()),
+            Self::Other(_, value) => value.clone(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) | Self::Color(value) | Self::Svg(value) | Self::ImageUri(value) => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(value) => value.as_f64(),
+            Self::Timestamp(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    // build typed trait from type name and value on wire, and return the reason if the value
+    // doesn't meet its type
+    pub fn from_entry(type_: &str, value: Value) -> Result<Self, String> {
+        let string = |value: Value| match value {
+            Value::String(string) => Ok(string),
+            _ => Err(format!("`{type_}` trait expects a string value")),
+        };
+        let trait_ = match type_ {
+            Self::STRING => Self::String(string(value)?),
+            Self::NUMBER => match value {
+                Value::Number(number) => Self::Number(number),
+                _ => return Err(format!("`{type_}` trait expects a number value")),
+            },
+            Self::COLOR => {
+                let color = string(value)?;
+                let hex = color.strip_prefix('#').unwrap_or_default();
+                if ![3, 6, 8].contains(&hex.len()) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!("`{color}` is not a hex color"));
+                }
+                Self::Color(color)
+            }
+            Self::TIMESTAMP => match value.as_u64() {
+                Some(timestamp) => Self::Timestamp(timestamp),
+                None => return Err(format!("`{type_}` trait expects an unsigned integer value")),
+            },
+            Self::SVG => {
+                let svg = string(value)?;
+                if !svg.contains("<svg") {
+                    return Err("SVG content has no `<svg` element".to_owned());
+                }
+                Self::Svg(svg)
+            }
+            Self::IMAGE_URI => {
+                let uri = string(value)?;
+                let valid_scheme = uri.split_once(':').is_some_and(|(scheme, rest)| {
+                    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                        && scheme
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                        && !rest.is_empty()
+                });
+                if !valid_scheme {
+                    return Err(format!("`{uri}` is not a URI"));
+                }
+                Self::ImageUri(uri)
+            }
+            _ => Self::Other(type_.to_owned(), value),
+        };
+        Ok(trait_)
+    }
+
+    fn from_wire(value: Value) -> Result<Self, String> {
+        let Value::Object(map) = value else {
+            return Err("trait should be a single-entry map".to_owned());
+        };
+        if map.len() != 1 {
+            return Err("trait should be a single-entry map".to_owned());
+        }
+        // value that doesn't meet its type is kept as it is, so that decoder outputs accepted by
+        // previous versions are still rendered
+        let (type_, value) = map.into_iter().next().unwrap();
+        Ok(Self::from_entry(&type_, value.clone()).unwrap_or(Self::Other(type_, value)))
+    }
+}
+
+impl Serialize for DOBTrait {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
         let mut map = serializer.serialize_map(Some(1))?;
-        map.serialize_entry(&self.type_, &self.value)?;
+        map.serialize_entry(self.type_name(), &self.value())?;
         map.end()
     }
 }
 
-impl<'de> Deserialize<'de> for ParsedTrait {
+impl<'de> Deserialize<'de> for DOBTrait {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let map: Value = Deserialize::deserialize(deserializer)?;
-        map.as_object()
-            .and_then(|map| map.iter().next())
-            .map(|(type_, value)| {
-                Ok(Self {
-                    type_: type_.to_string(),
-                    value: value.clone(),
-                })
-            })
-            .unwrap_or_else(|| Err(serde::de::Error::custom("invalid ParsedTrait")))
+        let value: Value = Deserialize::deserialize(deserializer)?;
+        Self::from_wire(value).map_err(serde::de::Error::custom)
     }
 }
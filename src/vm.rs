@@ -49,7 +49,7 @@ impl<Mac: SupportMachine> Syscalls<Mac> for DebugSyscall {
 fn main_asm(
     code: Bytes,
     args: Vec<Bytes>,
-) -> Result<(i8, Vec<String>), Box<dyn std::error::Error>> {
+) -> Result<(i8, Vec<String>, u64), Box<dyn std::error::Error>> {
     let debug_result = Arc::new(Mutex::new(Vec::new()));
     let debug = Box::new(DebugSyscall {
         output: debug_result.clone(),
@@ -69,13 +69,14 @@ fn main_asm(
 
     let error_code = machine.run()?;
     let result = debug_result.lock().unwrap().clone();
-    Ok((error_code, result))
+    Ok((error_code, result, machine.machine.cycles()))
 }
 
+// execute binary and return its exit code, debug outputs and consumed cycles
 pub fn execute_riscv_binary(
     binary_path: &str,
     args: Vec<Bytes>,
-) -> Result<(i8, Vec<String>), Box<dyn std::error::Error>> {
+) -> Result<(i8, Vec<String>, u64), Box<dyn std::error::Error>> {
     let code = std::fs::read(binary_path)?.into();
     main_asm(code, args)
 }
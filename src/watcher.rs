@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ckb_jsonrpc_types::BlockView;
+use jsonrpsee::tracing;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::client::{ChainEvent, SubscriptionClient, SubscriptionTopic};
+use crate::decoder::helpers::{cached_decoder_names, decoder_cache_names, script_matches};
+use crate::decoder::DOBDecoder;
+use crate::server::write_dob_to_cache;
+use crate::types::ClusterResolution;
+
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(5);
+
+// committed blocks waiting for pre-rendering, blocks beyond are skipped rather than piled up
+const BLOCK_QUEUE_CAPACITY: usize = 16;
+
+// pre-rendered spores waiting for finality before written into DOB cache, oldest are dropped beyond
+const MAX_PENDING_RENDERS: usize = 1024;
+
+struct PendingRender {
+    spore_id: String,
+    block_number: u64,
+    cluster_block_number: u64,
+    render_output: String,
+    dob_content: Value,
+    cluster_resolution: ClusterResolution,
+}
+
+// keep subscribing to ckb node, tracking new tip and reacting to spores, clusters and decoders in
+// newly committed blocks
+pub async fn watch_chain_events(decoder: Arc<DOBDecoder>, subscription_uri: String) {
+    let client = SubscriptionClient::new(&subscription_uri);
+    let topics = [
+        SubscriptionTopic::NewTipHeader,
+        SubscriptionTopic::NewTipBlock,
+    ];
+    let (block_sender, block_receiver) = mpsc::channel(BLOCK_QUEUE_CAPACITY);
+    tokio::spawn(prerender_blocks(decoder.clone(), block_receiver));
+    loop {
+        match client.subscribe(&topics).await {
+            Ok(mut events) => {
+                tracing::info!("subscribed to ckb node at {subscription_uri}");
+                while let Some(event) = events.recv().await {
+                    match event {
+                        ChainEvent::NewTipHeader(header) => decoder
+                            .rpc()
+                            .update_tip_block_number(header.inner.number.value()),
+                        ChainEvent::NewTipBlock(block) => {
+                            let block_number = block.header.inner.number.value();
+                            decoder.rpc().update_tip_block_number(block_number);
+                            invalidate_block(&decoder, &block);
+                            if block_sender.try_send(block).is_err() {
+                                tracing::warn!(
+                                    "pre-rendering queue is full, skip block {block_number}"
+                                );
+                            }
+                        }
+                    }
+                }
+                tracing::warn!("subscription to {subscription_uri} closed");
+            }
+            Err(error) => tracing::error!("subscription to {subscription_uri} failed: {error}"),
+        }
+        tokio::time::sleep(RESUBSCRIBE_INTERVAL).await;
+    }
+}
+
+// drop cached cells consumed in the block and cached binaries of decoders upgraded in the block,
+// which is cheap enough to run in place
+fn invalidate_block(decoder: &DOBDecoder, block: &BlockView) {
+    let consumed_out_points = block
+        .transactions
+        .iter()
+        .flat_map(|tx| tx.inner.inputs.iter())
+        .map(|input| input.previous_output.clone())
+        .collect::<Vec<_>>();
+    decoder
+        .rpc()
+        .invalidate_consumed_cells(&consumed_out_points);
+
+    let settings = decoder.setting();
+    for tx in &block.transactions {
+        for type_script in tx
+            .inner
+            .outputs
+            .iter()
+            .filter_map(|output| output.type_.as_ref())
+        {
+            if script_matches(type_script, &settings.available_spores)
+                || script_matches(type_script, &settings.available_clusters)
+            {
+                continue;
+            }
+            for path in decoder.invalidate_decoder(type_script) {
+                tracing::info!(
+                    "decoder upgraded in tx {:#x}, invalidated {}",
+                    tx.hash,
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+// pre-render blocks one by one on blocking threads, since decoders run in VM synchronously
+async fn prerender_blocks(decoder: Arc<DOBDecoder>, mut blocks: mpsc::Receiver<Box<BlockView>>) {
+    let mut pending_renders = VecDeque::new();
+    while let Some(block) = blocks.recv().await {
+        let handle = tokio::runtime::Handle::current();
+        let block_decoder = decoder.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            handle.block_on(prerender_block(block_decoder, *block, pending_renders))
+        })
+        .await;
+        pending_renders = match result {
+            Ok(pending_renders) => pending_renders,
+            Err(error) => {
+                tracing::error!("pre-rendering block aborted: {error}");
+                VecDeque::new()
+            }
+        };
+    }
+}
+
+async fn prerender_block(
+    decoder: Arc<DOBDecoder>,
+    block: BlockView,
+    mut pending_renders: VecDeque<PendingRender>,
+) -> VecDeque<PendingRender> {
+    let settings = decoder.setting();
+    let block_number = block.header.inner.number.value();
+    for tx in &block.transactions {
+        let outputs = tx.inner.outputs.iter().zip(tx.inner.outputs_data.iter());
+        for (output, data) in outputs {
+            let Some(type_script) = &output.type_ else {
+                continue;
+            };
+            if script_matches(type_script, &settings.available_spores) {
+                let spore_id = hex::encode(type_script.args.as_bytes());
+                match decoder.prerender_spore(data.as_bytes(), block_number).await {
+                    Ok((render_output, ingredients)) => {
+                        tracing::info!("pre-rendered spore {spore_id} in tx {:#x}", tx.hash);
+                        if pending_renders.len() >= MAX_PENDING_RENDERS {
+                            pending_renders.pop_front();
+                        }
+                        pending_renders.push_back(PendingRender {
+                            spore_id,
+                            block_number,
+                            cluster_block_number: ingredients.cluster.block_number,
+                            render_output,
+                            dob_content: ingredients.spore.content,
+                            cluster_resolution: ingredients.cluster.resolution,
+                        });
+                    }
+                    Err(error) => tracing::debug!("skip pre-rendering spore {spore_id}: {error}"),
+                }
+            } else if script_matches(type_script, &settings.available_clusters) {
+                let cluster_id = hex::encode(type_script.args.as_bytes());
+                match decoder.prepare_cluster_decoders(data.as_bytes()).await {
+                    Ok(_) => tracing::info!("prepared decoders of cluster {cluster_id}"),
+                    Err(error) => tracing::debug!("skip preparing cluster {cluster_id}: {error}"),
+                }
+            }
+        }
+    }
+    cache_final_renders(&decoder, pending_renders)
+}
+
+// write pre-rendered spores into DOB cache once both spore and cluster are finalized, the same
+// as renders served by rpc server, and keep the rest pending
+fn cache_final_renders(
+    decoder: &DOBDecoder,
+    pending_renders: VecDeque<PendingRender>,
+) -> VecDeque<PendingRender> {
+    let settings = decoder.setting();
+    let rpc = decoder.rpc();
+    let (final_renders, pending_renders): (VecDeque<_>, _) =
+        pending_renders.into_iter().partition(|render| {
+            rpc.is_final(render.block_number) && rpc.is_final(render.cluster_block_number)
+        });
+    for render in final_renders {
+        let cache_path = settings
+            .dobs_cache_directory
+            .join(format!("{}.dob", render.spore_id));
+        if let Err(error) = write_dob_to_cache(
+            &render.render_output,
+            &render.dob_content,
+            &render.cluster_resolution,
+            cache_path,
+            settings.dobs_cache_expiration_sec,
+        ) {
+            tracing::warn!(
+                "failed to cache pre-rendered spore {}: {error}",
+                render.spore_id
+            );
+        }
+    }
+    pending_renders
+}